@@ -2,37 +2,42 @@
 
 //! Automatic fractal generation for those who wish to skip the menu system.
 
-use crate::barnsley::*;
-use crate::julia_sets::*;
-use crate::mandelbrot::*;
+use crate::fractal;
+use crate::layers::render_layers;
 use crate::util::*;
-use rand::Rng;
-
-/// str literals for randomly selecting a fractal
-const FRACTALS: [&str; 4] = ["barnsley", "mandelbrot", "julia", "multi-julia"];
+use image::DynamicImage;
 
 /// Ultimate automation of the fractal generation process.
 /// This does all of work of deciding fractal properties for a user
-/// including which fractal to use, what colors, and what transformations
-pub fn auto_random(num_to_make: usize, filename: &str) {
+/// including which fractal to use, what colors, and what transformations.
+/// Each fractal is drawn using `threads` worker threads.
+pub fn auto_random(num_to_make: usize, filename: &str, threads: usize) -> Result<(), String> {
     for i in 0..num_to_make {
-        let fractal_index = rand::thread_rng().gen_range(0, 4);
-        let fractal = FRACTALS[fractal_index];
         let filename = filename.to_owned() + &i.to_string() + ".png";
 
         let mut scheme = Scheme {
-            fractal: fractal.to_string(),
+            fractal: fractal::random().name().to_string(),
+            threads,
             ..Default::default()
         };
 
         randomize(&mut scheme);
-        match fractal {
-            "barnsley" => barnsley_fern(1000, 1000, &filename, &mut scheme),
-            "julia" | "multi-julia" => julia_fractal(fractal, 1000, 1000, &filename, &scheme),
-            "mandelbrot" => mandelbrot_fractal(1000, 1000, &filename, &mut scheme),
-            _ => println!("Unsupported fractal type"),
-        }
-
-        random_transforms(&scheme, &filename);
+        scheme.random_transforms = pick_random_transforms();
+        let image = generate_fractal(&scheme, 1000, 1000);
+        save_image(&image, &filename, &scheme)?;
+        log_random(&scheme, &filename)?;
     }
+    Ok(())
+}
+
+/// Draw the fractal described by a scheme in memory over its background, then apply
+/// the scheme's chosen transformation and any random ones it was given.
+/// Unknown fractal names fall back to the Mandelbrot set.
+pub fn generate_fractal(scheme: &Scheme, imgx: u32, imgy: u32) -> DynamicImage {
+    let fractal = fractal::lookup(&scheme.fractal).unwrap_or(&crate::mandelbrot::Mandelbrot);
+    let image = DynamicImage::ImageRgba8(render_layers(fractal, scheme, imgx, imgy));
+
+    scheme_transforms(scheme)
+        .into_iter()
+        .fold(image, process_image)
 }
@@ -0,0 +1,153 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Gradient backgrounds drawn behind a fractal. A gradient of any
+//! number of color stops is laid over the image in one of three shapes:
+//! along a line (linear), out from the middle (radial) or around the
+//! middle (conic). Every shape is scaled to the image, so the whole
+//! gradient shows no matter how large or oddly shaped the image is.
+
+// Gradient shapes follow CSS: https://developer.mozilla.org/en-US/docs/Web/CSS/gradient
+
+use crate::palette::{Gradient, Interpolation, Palette};
+use crate::util::*;
+use std::f64::consts::PI;
+
+/// Names of the gradient shapes, for input and messages.
+pub const SHAPE_NAMES: [&str; 3] = ["linear", "radial", "conic"];
+
+/// How a gradient is laid over the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Shape {
+    /// Colors change along a line through the middle of the image.
+    Linear,
+    /// Colors change from the middle of the image out to its corners.
+    Radial,
+    /// Colors change going around the middle of the image.
+    Conic,
+}
+
+/// A gradient background.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BackgroundGradient {
+    pub shape: Shape,
+    pub gradient: Gradient,
+    /// Counterclockwise angle in degrees. Linear gradients run in this
+    /// direction, with 0 running left to right, and conic gradients start
+    /// from it. Radial gradients ignore it.
+    pub angle: f64,
+}
+
+impl BackgroundGradient {
+    /// A linear gradient from one color in the top left corner of the
+    /// image to another in the bottom right.
+    pub fn two_color(first: Color, second: Color) -> BackgroundGradient {
+        BackgroundGradient {
+            shape: Shape::Linear,
+            gradient: Gradient::even(
+                &[color_to_rgb(first), color_to_rgb(second)],
+                Interpolation::Linear,
+            ),
+            angle: -45.0,
+        }
+    }
+
+    /// Parse the stops of a gradient, written like a palette: either a
+    /// palette name or colors such as "red,blue@0.3,green".
+    pub fn parse(stops: &str) -> Result<BackgroundGradient, String> {
+        Ok(BackgroundGradient {
+            gradient: Palette::parse(stops)?.gradient,
+            ..BackgroundGradient::two_color(Color::Black, Color::White)
+        })
+    }
+
+    /// Position along the gradient, from 0 to 1, of the middle of pixel (x, y).
+    pub fn position(&self, x: u32, y: u32, imgx: u32, imgy: u32) -> f64 {
+        let half_x = f64::from(imgx) / 2.0;
+        let half_y = f64::from(imgy) / 2.0;
+        // Offsets from the middle of the image, with y pointing up.
+        let dx = f64::from(x) + 0.5 - half_x;
+        let dy = half_y - (f64::from(y) + 0.5);
+        let (sin, cos) = self.angle.to_radians().sin_cos();
+
+        let t = match self.shape {
+            Shape::Linear => {
+                // Half the length of the image measured along the gradient,
+                // so the first and last stops land on its corners.
+                let half_length = (half_x * cos).abs() + (half_y * sin).abs();
+                0.5 + (dx * cos + dy * sin) / (2.0 * half_length.max(1e-9))
+            }
+            Shape::Radial => {
+                let rx = dx / half_x.max(1e-9);
+                let ry = dy / half_y.max(1e-9);
+                (rx * rx + ry * ry).sqrt() / 2.0_f64.sqrt()
+            }
+            Shape::Conic => {
+                let turn = (dy.atan2(dx) - self.angle.to_radians()) / (2.0 * PI);
+                turn.rem_euclid(1.0)
+            }
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    /// Color of pixel (x, y) in an image of some size.
+    pub fn color_at(&self, x: u32, y: u32, imgx: u32, imgy: u32) -> [u8; 3] {
+        self.gradient.sample(self.position(x, y, imgx, imgy))
+    }
+}
+
+/// Parse the name of a gradient shape.
+pub fn parse_shape(name: &str) -> Result<Shape, String> {
+    match name.trim().to_lowercase().as_str() {
+        "linear" => Ok(Shape::Linear),
+        "radial" => Ok(Shape::Radial),
+        "conic" => Ok(Shape::Conic),
+        other => Err(unknown_name("gradient shape", other, &SHAPE_NAMES)),
+    }
+}
+
+#[cfg(test)]
+mod background_tests {
+    use super::*;
+
+    fn gradient(shape: Shape, angle: f64) -> BackgroundGradient {
+        BackgroundGradient {
+            shape,
+            angle,
+            ..BackgroundGradient::two_color(Color::Black, Color::White)
+        }
+    }
+
+    #[test]
+    fn test_linear_reaches_both_ends_on_wide_images() {
+        let linear = gradient(Shape::Linear, 0.0);
+        assert!(linear.position(0, 10, 3000, 20) < 0.001);
+        assert!(linear.position(2999, 10, 3000, 20) > 0.999);
+        assert!((linear.position(1499, 0, 3000, 20) - 0.5).abs() < 0.001);
+        // The default two color gradient runs corner to corner.
+        let diagonal = BackgroundGradient::two_color(Color::Black, Color::White);
+        assert!(diagonal.color_at(0, 0, 200, 100)[0] < 3);
+        assert!(diagonal.color_at(199, 99, 200, 100)[0] > 252);
+    }
+
+    #[test]
+    fn test_radial_and_conic() {
+        let radial = gradient(Shape::Radial, 0.0);
+        assert!(radial.position(50, 25, 101, 51) < 0.02);
+        assert!(radial.position(0, 0, 101, 51) > 0.98);
+        let conic = gradient(Shape::Conic, 90.0);
+        // Straight up is where a conic gradient at 90 degrees starts,
+        // and straight left is a quarter of the way around.
+        assert!(conic.position(50, 0, 101, 101) < 0.01);
+        assert!((conic.position(0, 50, 101, 101) - 0.25).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_parse_gradients() {
+        let parsed = BackgroundGradient::parse("red, #00f@0.2, lime").unwrap();
+        assert_eq!(3, parsed.gradient.stops.len());
+        assert_eq!([0, 0, 255], parsed.gradient.stops[1].color);
+        assert!(BackgroundGradient::parse("red").is_err());
+        assert_eq!(Ok(Shape::Conic), parse_shape("Conic"));
+        assert!(parse_shape("square").is_err());
+    }
+}
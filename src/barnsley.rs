@@ -5,50 +5,65 @@
 
 //! Barnsley's Fern implementation.
 
+use crate::density::DENSITY_PARAMS;
+use crate::fractal::*;
+use crate::ifs::{ifs_fractal, Ifs};
+use crate::layers::BlendMode;
 use crate::util::*;
-use rand::Rng;
-
-/// Plot Barnsley's fern - For some arbitrarily large number of iterations,
-/// apply one of four affine transformations. That is, start the x,y coordinate
-/// pair at 0,0 then multiply by some values in Barnsley's matrix of
-/// constants and adding some constant.
-pub fn barnsley_fern(imgx: u32, imgy: u32, filename: &str, scheme: &mut Scheme) {
-    let mut rng = rand::thread_rng();
-    let mut imgbuf = image::ImageBuffer::new(imgx, imgy);
-    let color: [u8; 3] = color_to_rgb(scheme.color);
-
-    let mut x = 0_f64;
-    let mut y = 0_f64;
-
-    apply_background(&mut imgbuf, &scheme);
-
-    // 20,000 is a good arbitrary number of points to plot, could be modified
-    for _ in 0..20000_u32 {
-        let rand_num = rng.gen::<f32>();
-        let cx: f64;
-        let cy: f64;
-
-        if rand_num <= 0.01 {
-            cx = 0.0_f64;
-            cy = 0.16 * y;
-        } else if rand_num <= 0.08 {
-            cx = 0.2 * x - 0.26 * y;
-            cy = 0.23 * x + 0.22 * y + 1.6;
-        } else if rand_num <= 0.15 {
-            cx = -0.15 * x + 0.28 * y;
-            cy = 0.26 * x + 0.26 * y + 0.44;
-        } else {
-            cx = 0.85 * x + 0.04 * y;
-            cy = -0.04 * x + 0.85 * y + 1.6;
-        }
-        x = cx;
-        y = cy;
+use image::RgbaImage;
+
+/// Barnsley's fern, drawn as the fern preset of the IFS fractal.
+pub struct Barnsley;
+
+impl Fractal for Barnsley {
+    fn name(&self) -> &'static str {
+        "barnsley"
+    }
+
+    /// Barnsley fades in with the density of its points, so any color works.
+    fn colors(&self) -> &'static [Color] {
+        &ALL_COLORS
+    }
+
+    fn supports_color(&self, _color: Color) -> bool {
+        true
+    }
 
-        let new_x = ((f64::from(imgx)) / 2.0 + x * (f64::from(imgx)) / 11.0).round() as u32;
-        let new_y = ((f64::from(imgy)) - y * (f64::from(imgy)) / 11.0).round() as u32;
-        let pixel = imgbuf.get_pixel_mut(new_x, new_y);
-        *pixel = image::Rgba([color[0], color[1], color[2], 255]);
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Normal
     }
 
-    imgbuf.save(filename).expect("Image write failed...");
+    fn params(&self) -> Vec<Param> {
+        let mut params = common_params();
+        params.extend_from_slice(&DENSITY_PARAMS);
+        params
+    }
+
+    /// Plot Barnsley's fern - For some arbitrarily large number of iterations,
+    /// apply one of four affine transformations, each picked with its own
+    /// weight, starting from 0,0. The points are counted per pixel on every
+    /// worker thread and drawn by their density.
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let fern = Ifs::preset("fern").expect("the fern settles onto its attractor");
+        ifs_fractal(&fern, imgx, imgy, scheme)
+    }
+}
+
+#[cfg(test)]
+mod barnsley_tests {
+    use super::*;
+
+    #[test]
+    fn test_fern_fits_odd_and_non_square_images() {
+        let scheme = Scheme {
+            threads: 2,
+            points: 20_000,
+            ..Default::default()
+        };
+        for &(width, height) in &[(1, 1), (2, 1), (7, 3), (3, 7), (101, 33)] {
+            let image = Barnsley.render(&scheme, width, height);
+            assert_eq!((width, height), image.dimensions());
+            assert!(image.pixels().any(|pixel| pixel[3] > 0));
+        }
+    }
 }
@@ -0,0 +1,399 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Buddhabrot, Anti-Buddhabrot and Nebulabrot. Rather than coloring each
+//! point c by how fast it escapes, random points c are iterated with the
+//! Mandelbrot formula and every point their orbit passes through is
+//! counted, for the orbits that escape, or for the Anti-Buddhabrot those
+//! that do not. The counts are drawn by density. The Nebulabrot counts
+//! orbits under a different iteration limit for each of red, green and
+//! blue.
+
+// Buddhabrot: https://en.wikipedia.org/wiki/Buddhabrot
+// Sampling only where orbits matter: http://www.steckles.com/buddha/
+
+use crate::density::*;
+use crate::fractal::*;
+use crate::mandelbrot::escape_orbit;
+use crate::util::*;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use num::Complex;
+use rand::Rng;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread;
+
+/// Region showing the whole Buddhabrot, turned to sit upright.
+pub const BUDDHABROT_VIEWPORT: Viewport = Viewport {
+    center_re: -0.4,
+    center_im: 0.0,
+    span: 3.0,
+    rotation: 90.0,
+};
+/// Red, green and blue iteration limits of the Nebulabrot unless a
+/// scheme says otherwise.
+pub const NEBULABROT_ITERATIONS: [u32; 3] = [2000, 200, 20];
+/// Points c are sampled from the square -2..2 by -2..2, which holds the
+/// whole Mandelbrot set.
+const SAMPLE_RADIUS: f64 = 2.0;
+/// Cells per side of the grid sampling is focused with.
+const IMPORTANCE_CELLS: usize = 200;
+/// Points traced in each cell to find whether its orbits matter.
+const IMPORTANCE_SAMPLES: usize = 8;
+/// Times more often a cell whose orbits matter is sampled than one whose
+/// orbits were not seen, which still may hold a few that do.
+const IMPORTANCE_FLOOR: usize = 16;
+
+/// Parameters of the Buddhabrot fractals other than the common ones.
+pub const BUDDHABROT_PARAMS: [Param; 1] = [Param {
+    name: "rgb-iterations",
+    description: "Iteration limits of the red, green and blue channels, e.g. 2000,200,20",
+    default: "none, or 2000,200,20 for the nebulabrot",
+}];
+
+/// The Buddhabrot, drawn by `buddhabrot_fractal`.
+pub struct Buddhabrot;
+
+/// The Anti-Buddhabrot, the orbits of points inside the Mandelbrot set,
+/// drawn by `buddhabrot_fractal`.
+pub struct AntiBuddhabrot;
+
+/// The Nebulabrot, a Buddhabrot with an iteration limit per color
+/// channel, drawn by `buddhabrot_fractal`.
+pub struct Nebulabrot;
+
+/// Parameters of all three.
+fn buddhabrot_params() -> Vec<Param> {
+    let mut params = common_params();
+    params.extend_from_slice(&VIEWPORT_PARAMS);
+    params.extend_from_slice(&ITERATION_PARAMS[..2]);
+    params.extend_from_slice(&DENSITY_PARAMS);
+    params.extend_from_slice(&BUDDHABROT_PARAMS);
+    params
+}
+
+impl Fractal for Buddhabrot {
+    fn name(&self) -> &'static str {
+        "buddhabrot"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        buddhabrot_params()
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(BUDDHABROT_VIEWPORT)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        buddhabrot_fractal(imgx, imgy, scheme, false, scheme.rgb_iterations)
+    }
+}
+
+impl Fractal for AntiBuddhabrot {
+    fn name(&self) -> &'static str {
+        "anti-buddhabrot"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        buddhabrot_params()
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(BUDDHABROT_VIEWPORT)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        buddhabrot_fractal(imgx, imgy, scheme, true, scheme.rgb_iterations)
+    }
+}
+
+impl Fractal for Nebulabrot {
+    fn name(&self) -> &'static str {
+        "nebulabrot"
+    }
+
+    /// Colored by its channels, so no color is offered.
+    fn colors(&self) -> &'static [Color] {
+        &[]
+    }
+
+    fn supports_color(&self, _color: Color) -> bool {
+        false
+    }
+
+    fn params(&self) -> Vec<Param> {
+        buddhabrot_params()
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(BUDDHABROT_VIEWPORT)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let limits = scheme.rgb_iterations.unwrap_or(NEBULABROT_ITERATIONS);
+        buddhabrot_fractal(imgx, imgy, scheme, false, Some(limits))
+    }
+}
+
+/// Which orbits a Buddhabrot counts: those escaping within the iteration
+/// limit of a channel, or for the Anti-Buddhabrot those that do not.
+struct Orbits {
+    anti: bool,
+    limits: Vec<u32>,
+    bailout: f64,
+}
+
+impl Orbits {
+    /// Trace the orbit of c and call `plot` with every point counted and
+    /// the channel it is counted in.
+    fn trace<F: FnMut(usize, Complex<f64>)>(
+        &self,
+        c: Complex<f64>,
+        orbit: &mut Vec<Complex<f64>>,
+        mut plot: F,
+    ) {
+        // Points in the main cardioid and bulb never escape.
+        if !self.anti && in_main_bulbs(c) {
+            return;
+        }
+        let max = self.limits.iter().copied().max().unwrap_or(1);
+        orbit.clear();
+        let escape = escape_orbit(c, max, self.bailout, |z| orbit.push(z));
+        for (channel, &limit) in self.limits.iter().enumerate() {
+            let escaped = escape.escaped && escape.iterations < limit;
+            if escaped != self.anti {
+                for &z in &orbit[..orbit.len().min(limit as usize)] {
+                    plot(channel, z);
+                }
+            }
+        }
+    }
+}
+
+/// Whether c is in the main cardioid or the period 2 bulb of the
+/// Mandelbrot set, which hold most of its area.
+fn in_main_bulbs(c: Complex<f64>) -> bool {
+    let x = c.re - 0.25;
+    let q = x * x + c.im * c.im;
+    let cardioid = q * (q + x) <= 0.25 * c.im * c.im;
+    let bulb = (c.re + 1.0) * (c.re + 1.0) + c.im * c.im <= 1.0 / 16.0;
+    cardioid || bulb
+}
+
+/// The cells of a grid over the square points c are sampled from that
+/// matter, to sample from more often than the rest (importance sampling).
+/// A few points are traced in each cell, and cells none of whose orbits
+/// were counted in view, inside the set, far outside it, and for a zoomed
+/// view most of the plane, are sampled `IMPORTANCE_FLOOR` times less
+/// often. So that densities keep their proportions, the orbits of those
+/// cells are counted that many times over.
+struct Importance {
+    matter: Vec<usize>,
+    rest: Vec<usize>,
+}
+
+impl Importance {
+    /// Trace the sample points of every cell on the worker threads.
+    fn new<V: Fn(Complex<f64>) -> bool + Sync>(
+        orbits: &Orbits,
+        threads: usize,
+        in_view: V,
+    ) -> Importance {
+        let total = IMPORTANCE_CELLS * IMPORTANCE_CELLS;
+        let matters: Vec<AtomicBool> = (0..total).map(|_| AtomicBool::new(false)).collect();
+        let next = AtomicUsize::new(0);
+        let worker = || {
+            let mut rng = rand::thread_rng();
+            let mut orbit = Vec::new();
+            loop {
+                let cell = next.fetch_add(1, Ordering::Relaxed);
+                if cell >= total {
+                    break;
+                }
+                let mut counted = false;
+                for _ in 0..IMPORTANCE_SAMPLES {
+                    let c = Importance::point_in(cell, &mut rng);
+                    orbits.trace(c, &mut orbit, |_, z| counted |= in_view(z));
+                    if counted {
+                        break;
+                    }
+                }
+                matters[cell].store(counted, Ordering::Relaxed);
+            }
+        };
+        if threads <= 1 {
+            worker();
+        } else {
+            thread::scope(|scope| {
+                for _ in 0..threads {
+                    scope.spawn(worker);
+                }
+            });
+        }
+
+        let (matter, rest) = (0..total).partition(|&cell| matters[cell].load(Ordering::Relaxed));
+        Importance { matter, rest }
+    }
+
+    /// A random point c, and the number of times its orbit is counted.
+    fn sample<R: Rng>(&self, rng: &mut R) -> (Complex<f64>, u32) {
+        let matter = self.matter.len() * IMPORTANCE_FLOOR;
+        let pick = rng.gen_range(0, matter + self.rest.len());
+        if pick < matter {
+            let cell = self.matter[pick / IMPORTANCE_FLOOR];
+            (Importance::point_in(cell, rng), 1)
+        } else {
+            let cell = self.rest[pick - matter];
+            (Importance::point_in(cell, rng), IMPORTANCE_FLOOR as u32)
+        }
+    }
+
+    /// A random point c in a cell of the grid.
+    fn point_in<R: Rng>(cell: usize, rng: &mut R) -> Complex<f64> {
+        let size = 2.0 * SAMPLE_RADIUS / IMPORTANCE_CELLS as f64;
+        let (x, y) = (cell % IMPORTANCE_CELLS, cell / IMPORTANCE_CELLS);
+        Complex::new(
+            -SAMPLE_RADIUS + (x as f64 + rng.gen::<f64>()) * size,
+            -SAMPLE_RADIUS + (y as f64 + rng.gen::<f64>()) * size,
+        )
+    }
+}
+
+/// Draw a Buddhabrot, or an Anti-Buddhabrot if `anti`, from the scheme's
+/// number of sampled points c, iterated up to the scheme's maximum. With
+/// `limits`, each of red, green and blue counts orbits under its own
+/// limit instead, and each channel is toned by its own density.
+pub fn buddhabrot_fractal(
+    imgx: u32,
+    imgy: u32,
+    scheme: &Scheme,
+    anti: bool,
+    limits: Option<[u32; 3]>,
+) -> RgbaImage {
+    let viewport = scheme.viewport.unwrap_or(BUDDHABROT_VIEWPORT);
+    let samples = density_samples(scheme, imgx, imgy);
+    let (width, height) = (imgx * samples, imgy * samples);
+    let orbits = Orbits {
+        anti,
+        limits: limits.map_or(vec![scheme.max_iterations], |limits| limits.to_vec()),
+        bailout: scheme.bailout,
+    };
+    let histograms: Vec<Histogram> = orbits
+        .limits
+        .iter()
+        .map(|_| Histogram::new(width, height))
+        .collect();
+    let to_image = viewport.image_mapping(width, height);
+    let cell = |z: Complex<f64>| {
+        let (x, y) = to_image(z.re, z.im);
+        if x >= 0.0 && y >= 0.0 && x < f64::from(width) && y < f64::from(height) {
+            Some((x as u32, y as u32))
+        } else {
+            None
+        }
+    };
+
+    let importance = Importance::new(&orbits, scheme.threads, |z| cell(z).is_some());
+    play_on_threads(scheme, |points| {
+        let mut rng = rand::thread_rng();
+        let mut orbit = Vec::new();
+        for _ in 0..points {
+            let (c, count) = importance.sample(&mut rng);
+            orbits.trace(c, &mut orbit, |channel, z| {
+                if let Some((x, y)) = cell(z) {
+                    histograms[channel].add_count(x, y, count);
+                }
+            });
+        }
+    });
+
+    match histograms.as_slice() {
+        [red, green, blue] => tone_map_rgb(
+            &[red.density(), green.density(), blue.density()],
+            samples,
+            Scale::Linear,
+            scheme,
+        ),
+        _ => tone_map_scaled(&histograms[0].density(), samples, Scale::Linear, scheme),
+    }
+}
+
+#[cfg(test)]
+mod buddhabrot_tests {
+    use super::*;
+
+    #[test]
+    fn test_orbits_counted_by_escape() {
+        let mut orbit = Vec::new();
+        let count = |anti, limits: Vec<u32>, c| {
+            let orbits = Orbits {
+                anti,
+                limits,
+                bailout: 2.0,
+            };
+            let mut counts = vec![0; orbits.limits.len()];
+            orbits.trace(c, &mut Vec::new(), |channel, _| counts[channel] += 1);
+            counts
+        };
+        // -2.1 escapes on its second iterate, 0 never does.
+        let outside = Complex::new(-2.1, 0.0);
+        let inside = Complex::new(0.0, 0.0);
+        assert_eq!(vec![1], count(false, vec![50], outside));
+        assert_eq!(vec![0], count(false, vec![50], inside));
+        assert_eq!(vec![0], count(true, vec![50], outside));
+        assert_eq!(vec![50], count(true, vec![50], inside));
+
+        // Near the edge of the set, escaping takes a while.
+        let slow = Complex::new(-0.75, 0.2);
+        let escape = escape_orbit(slow, 1000, 2.0, |z| orbit.push(z));
+        let n = escape.iterations;
+        assert!(escape.escaped && n > 10);
+        assert_eq!(vec![n, 0, n], count(false, vec![n + 1, n, 2 * n], slow));
+        assert_eq!(vec![0, 5], count(true, vec![n + 1, 5], slow));
+        assert_eq!(orbit.len() as u32, n);
+    }
+
+    #[test]
+    fn test_main_bulbs() {
+        assert!(in_main_bulbs(Complex::new(0.0, 0.0)));
+        assert!(in_main_bulbs(Complex::new(-1.0, 0.1)));
+        assert!(in_main_bulbs(Complex::new(0.24, 0.0)));
+        assert!(!in_main_bulbs(Complex::new(0.26, 0.0)));
+        assert!(!in_main_bulbs(Complex::new(-1.3, 0.0)));
+    }
+
+    #[test]
+    fn test_importance_samples_every_cell() {
+        let orbits = Orbits {
+            anti: false,
+            limits: vec![50],
+            bailout: 2.0,
+        };
+        // A view only orbits near -2 reach.
+        let importance = Importance::new(&orbits, 2, |z| z.re < -1.9);
+        assert!(!importance.matter.is_empty() && !importance.rest.is_empty());
+        let mut rng = rand::thread_rng();
+        let counts: Vec<u32> = (0..10_000).map(|_| importance.sample(&mut rng).1).collect();
+        assert!(counts.contains(&1));
+        assert!(counts.contains(&(IMPORTANCE_FLOOR as u32)));
+    }
+
+    #[test]
+    fn test_nebulabrot_colors_channels() {
+        let scheme = Scheme {
+            fractal: "nebulabrot".to_string(),
+            points: 20_000,
+            threads: 2,
+            ..Default::default()
+        };
+        let image = buddhabrot_fractal(24, 24, &scheme, false, Some([200, 50, 10]));
+        let mut channels = [0u64; 3];
+        for pixel in image.pixels() {
+            for channel in 0..3 {
+                channels[channel] += u64::from(pixel[channel]);
+            }
+        }
+        assert!(channels.iter().all(|&total| total > 0));
+        assert_eq!((24, 24), image.dimensions());
+    }
+}
@@ -0,0 +1,144 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! The chaos game shared by the IFS and flame fractals: a point moved
+//! again and again by one of several maps, each picked at random with
+//! some weight, settles onto the attractor of the maps.
+
+use crate::plot::Bounds;
+use crate::util::unknown_name;
+use rand::Rng;
+
+/// Points skipped while the chaos game settles onto the attractor.
+const SETTLE_POINTS: u64 = 20;
+/// Points used to measure the extent of the attractor.
+const BOUNDS_POINTS: usize = 50_000;
+
+/// Play the chaos game with maps of the given weights. From the state
+/// made by `start`, `step` moves the state with the map of some index,
+/// and `plot` is called with every state after the first few and that
+/// index. A step returning `None` has thrown the point away, and the game
+/// goes on from a new start.
+pub fn play<R, S, Start, Step, Plot>(
+    rng: &mut R,
+    weights: &[f64],
+    points: u64,
+    start: Start,
+    mut step: Step,
+    mut plot: Plot,
+) where
+    R: Rng,
+    S: Copy,
+    Start: Fn(&mut R) -> S,
+    Step: FnMut(&mut R, S, usize) -> Option<S>,
+    Plot: FnMut(&mut R, S, usize),
+{
+    let total: f64 = weights.iter().sum();
+    let mut state = start(rng);
+    for i in 0..points + SETTLE_POINTS {
+        let index = pick(rng, weights, total);
+        match step(rng, state, index) {
+            Some(next) => state = next,
+            None => {
+                state = start(rng);
+                continue;
+            }
+        }
+        if i >= SETTLE_POINTS {
+            plot(rng, state, index);
+        }
+    }
+}
+
+/// Index of a weight picked at random, in proportion to the weights.
+fn pick<R: Rng>(rng: &mut R, weights: &[f64], total: f64) -> usize {
+    let mut pick = rng.gen::<f64>() * total;
+    for (index, &weight) in weights.iter().enumerate() {
+        if pick < weight {
+            return index;
+        }
+        pick -= weight;
+    }
+    weights.len() - 1
+}
+
+/// Find the extent of an attractor from a sample of its points, leaving
+/// out the `outliers` share of them farthest out on each side. `play` is
+/// given a number of points to play the chaos game for and where to plot
+/// them. `None` when the points fly off rather than settle.
+pub fn measure_bounds<P>(outliers: f64, play: P) -> Option<Bounds>
+where
+    P: FnOnce(u64, &mut dyn FnMut((f64, f64))),
+{
+    let mut xs = Vec::with_capacity(BOUNDS_POINTS);
+    let mut ys = Vec::with_capacity(BOUNDS_POINTS);
+    play(BOUNDS_POINTS as u64, &mut |(x, y)| {
+        if x.is_finite() && y.is_finite() {
+            xs.push(x);
+            ys.push(y);
+        }
+    });
+    if xs.len() < BOUNDS_POINTS / 2 {
+        return None;
+    }
+    let range = |values: &mut Vec<f64>| {
+        values.sort_by(|a, b| a.partial_cmp(b).expect("points are finite"));
+        let outliers = (values.len() as f64 * outliers) as usize;
+        (values[outliers], values[values.len() - 1 - outliers])
+    };
+    let ((min_x, max_x), (min_y, max_y)) = (range(&mut xs), range(&mut ys));
+    if [min_x, min_y, max_x, max_y].iter().any(|n| n.abs() >= 1e12) {
+        return None;
+    }
+    Some(Bounds {
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+    })
+}
+
+/// Find a preset by name, or fail listing the names of every `kind` of
+/// preset there is.
+pub fn find_preset<'a, T>(
+    kind: &str,
+    presets: &'a [(&'static str, T)],
+    name: &str,
+) -> Result<&'a (&'static str, T), String> {
+    let name = name.trim().to_lowercase();
+    match presets.iter().find(|(preset, _)| *preset == name) {
+        Some(preset) => Ok(preset),
+        None => {
+            let names: Vec<&str> = presets.iter().map(|(name, _)| *name).collect();
+            Err(unknown_name(kind, &name, &names))
+        }
+    }
+}
+
+/// Name of one of the presets picked at random.
+pub fn random_preset<T>(presets: &[(&'static str, T)]) -> &'static str {
+    presets[rand::thread_rng().gen_range(0, presets.len())].0
+}
+
+#[cfg(test)]
+mod chaos_tests {
+    use super::*;
+
+    #[test]
+    fn test_bounds_leave_out_outliers() {
+        let bounds = measure_bounds(0.01, |points, plot| {
+            for i in 0..points {
+                plot((i as f64, -(i as f64)));
+            }
+        })
+        .unwrap();
+        assert_eq!(500.0, bounds.min_x);
+        assert_eq!(49_499.0, bounds.max_x);
+        assert_eq!(-49_499.0, bounds.min_y);
+        assert!(measure_bounds(0.0, |points, plot| {
+            for _ in 0..points {
+                plot((f64::INFINITY, 0.0));
+            }
+        })
+        .is_none());
+    }
+}
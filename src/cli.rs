@@ -0,0 +1,401 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Command line interface. The binary runs one of several subcommands:
+//! the web server, a single render, a batch of random fractals,
+//! or the interactive menus.
+
+use crate::auto_random::*;
+use crate::fractal;
+use crate::menu::*;
+use crate::params::GenParams;
+use crate::render::default_threads;
+use crate::util::*;
+
+/// Exit code for a successful run.
+pub const EXIT_OK: i32 = 0;
+/// Exit code when a fractal could not be written.
+pub const EXIT_FAILURE: i32 = 1;
+/// Exit code for bad command line arguments.
+pub const EXIT_USAGE: i32 = 2;
+
+/// Help text printed for `--help` and bad arguments.
+pub const USAGE: &str = "Usage: fractal_generator <command> [arguments]
+
+Commands:
+    serve
+        Start the web server.
+    render <fractal> <file> <width>x<height> [--<parameter> <value> ...]
+        Draw one fractal to a file. Parameters left out are chosen randomly,
+        except the transform, which is none.
+    random <file> <number to create>
+        Draw some number of completely random fractals, named <file>0.png, <file>1.png, ...
+    interactive <fractal> <file> <width>x<height>
+        Pick colors, background and transformations from the menus, then draw.
+    help
+        Print this message.
+
+Parameters for render:
+    --color <color>          Color of the fractal
+    --bg <color>             Solid background color
+    --bg-gradient <stops>    Gradient background of colors or a palette, e.g. navy,orange@0.7,white
+    --bg-shape <shape>       Shape of a gradient background: linear, radial or conic
+    --bg-angle <degrees>     Counterclockwise angle of a linear or conic gradient background
+    --transform <transform>  Image transformation, e.g. invert or blur, or none
+    --center <re>,<im>       Complex point in the middle of the image
+    --span <distance>        Distance across the shorter side of the image
+    --zoom <factor>          Magnification of the fractal's default view
+    --rotation <degrees>     Counterclockwise rotation of the view
+    --iterations <n>         Most iterations tried per point
+    --bailout <radius>       Escape radius past which a point has escaped
+    --smooth <true|false>    Color by the fractional iteration count
+    --samples <n>            Anti-alias with n x n samples per pixel, up to 8
+    --sampling <mode>        Placement of the samples: grid, jittered or adaptive
+    --precision <type>       Number type: auto, f32, f64, arbitrary or perturbation
+    --c <re>,<im>            Constant of a Julia set, or a preset: chain, cauliflower,
+                             spiral, dust, seahorse, dragon, galaxy, lace, filigree, pinwheel
+    --exponent <n>           Exponent of multi-julia and multibrot, real or complex, e.g. 2.5
+    --ifs <preset>           IFS preset: fern, cyclosorus-fern, culcita-fern, fishbone-fern,
+                             sierpinski-triangle, sierpinski-carpet, heighway-dragon,
+                             levy-c or maple-leaf
+    --maps <maps>            IFS affine maps as a b c d e f [weight], separated by ;
+    --maps-file <file>       File of IFS maps, one a b c d e f [weight] per line
+    --polynomial <p(z)>      Polynomial of the newton fractal, e.g. \"z^3 - 1\"
+    --roots <roots>          Roots of its polynomial instead, e.g. 1,-1,i,-i
+    --relaxation <a>         Factor of each Newton step, real or complex, e.g. 0.5+0.5i
+    --tolerance <distance>   Step size below which a point has converged
+    --flame <preset>         Flame preset: swirl-triangle, julia-rings, eclipse,
+                             horseshoe-bloom, heart-spiral or polar-shards
+    --transforms <t>         Flame transforms as a b c d e f [name=value ...], separated by ;
+    --transforms-file <file> File of flame transforms, one per line
+    --estimator <radius>     Pixels a flame's sparse points are smoothed over, 0 for none
+    --rgb-iterations <r,g,b> Iteration limits of the red, green and blue channels of the
+                             buddhabrot fractals, e.g. 2000,200,20
+    --points <n>             Points plotted by barnsley, ifs, flame and the buddhabrots, e.g. 5e6
+    --gamma <gamma>          Gamma of their log-density, higher brings out sparse areas
+    --palette <palette>      Named palette (fire, ocean, grayscale, rainbow, electric,
+                             forest, sunset, ultra) or color stops, e.g. black,red@0.3,yellow
+    --interpolation <space>  Blending between palette stops: linear, hsv or lab
+    --mapping <mapping>      Repeat the palette (cyclic) or stop at its ends (clamped)
+    --offset <shift>         Shift of the colors along the palette
+    --density <n>            Number of times the palette is passed through
+    --interior <color>       Color of points inside the set, or transparent
+    --blend <mode>           Blending of the fractal over the background: normal,
+                             multiply, screen or overlay
+    --fractal-only <bool>    Draw the fractal alone on a transparent background
+
+Options:
+    --threads <n>
+        Worker threads used to draw with render, random and interactive.
+        Defaults to one per CPU core.
+
+Colors may be CSS color names, hex colors or rgb(...) and hsl(...), e.g. coral,
+#ff8800 or \"rgb(255, 136, 0)\".
+
+The file extension of <file> picks the image format, e.g. julia.png.";
+
+/// A parsed command line.
+#[derive(Debug, PartialEq)]
+pub enum Command {
+    Serve,
+    Render {
+        params: Box<GenParams>,
+        filename: String,
+        width: u32,
+        height: u32,
+        threads: usize,
+    },
+    Random {
+        filename: String,
+        count: usize,
+        threads: usize,
+    },
+    Interactive {
+        fractal: String,
+        filename: String,
+        width: u32,
+        height: u32,
+        threads: usize,
+    },
+    Help,
+}
+
+/// Parse the command line arguments, not including the program name.
+/// The old `<fractal> <file> <width>x<height>` and `auto-random <file> <n>`
+/// forms are still accepted.
+pub fn parse_args(args: &[String]) -> Result<Command, String> {
+    if args.iter().any(|arg| arg == "-h" || arg == "--help") {
+        return Ok(Command::Help);
+    }
+    let (args, threads) = take_threads(args)?;
+    let args = &args[..];
+
+    let command = match args.first() {
+        Some(command) => command.as_str(),
+        None => return Err("missing command".to_string()),
+    };
+    let rest = &args[1..];
+
+    match command {
+        "help" => Ok(Command::Help),
+        "serve" => {
+            expect_count("serve", rest, 0)?;
+            Ok(Command::Serve)
+        }
+        "render" => parse_render(rest, threads),
+        "random" | "auto-random" => {
+            expect_count(command, rest, 2)?;
+            let count = rest[1]
+                .parse::<usize>()
+                .map_err(|_| format!("invalid number of fractals '{}'", rest[1]))?;
+            Ok(Command::Random {
+                filename: rest[0].clone(),
+                count,
+                threads,
+            })
+        }
+        "interactive" => {
+            expect_count("interactive", rest, 3)?;
+            let (width, height) = parse_size(&rest[2])?;
+            Ok(Command::Interactive {
+                fractal: parse_fractal_name(&rest[0])?,
+                filename: rest[1].clone(),
+                width,
+                height,
+                threads,
+            })
+        }
+        name if fractal::lookup(name).is_some() => parse_render(args, threads),
+        other => Err(format!("unknown command '{}'", other)),
+    }
+}
+
+/// Parse the arguments of `render`: fractal, file, size, then options.
+fn parse_render(args: &[String], threads: usize) -> Result<Command, String> {
+    if args.len() < 3 {
+        return Err("render expects <fractal> <file> <width>x<height>".to_string());
+    }
+    let (width, height) = parse_size(&args[2])?;
+    let mut params = GenParams {
+        fractal: Some(parse_fractal_name(&args[0])?),
+        ..Default::default()
+    };
+
+    let mut options = args[3..].iter();
+    while let Some(option) = options.next() {
+        let value = options
+            .next()
+            .ok_or_else(|| format!("missing value for option '{}'", option))?;
+        if option == "--maps-file" {
+            let maps = std::fs::read_to_string(value)
+                .map_err(|e| format!("could not read maps file '{}': {}", value, e))?;
+            params.maps = Some(maps);
+            continue;
+        }
+        if option == "--transforms-file" {
+            let transforms = std::fs::read_to_string(value)
+                .map_err(|e| format!("could not read transforms file '{}': {}", value, e))?;
+            params.transforms = Some(transforms);
+            continue;
+        }
+        // The fractal and size are positional arguments, never options.
+        let key = match option.trim_start_matches("--") {
+            "fractal" | "width" | "height" => "",
+            key if option.starts_with("--") => key,
+            _ => "",
+        };
+        params
+            .set(key, value)
+            .map_err(|_| format!("unknown option '{}'", option))?;
+    }
+
+    Ok(Command::Render {
+        params: Box::new(params),
+        filename: args[1].clone(),
+        width,
+        height,
+        threads,
+    })
+}
+
+/// Pull the `--threads <n>` option out of the arguments, which may appear
+/// anywhere after the command. Without it there is one thread per CPU core.
+fn take_threads(args: &[String]) -> Result<(Vec<String>, usize), String> {
+    let mut rest = Vec::new();
+    let mut threads = default_threads();
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        if arg != "--threads" {
+            rest.push(arg.clone());
+            continue;
+        }
+        threads = match args.next().map(|n| n.parse::<usize>()) {
+            Some(Ok(n)) if n > 0 => n,
+            _ => return Err("--threads expects a number greater than 0".to_string()),
+        };
+    }
+    Ok((rest, threads))
+}
+
+/// Make sure a command got exactly the number of arguments it needs.
+fn expect_count(command: &str, args: &[String], count: usize) -> Result<(), String> {
+    if args.len() == count {
+        Ok(())
+    } else {
+        Err(format!(
+            "{} expects {} arguments but got {}",
+            command,
+            count,
+            args.len()
+        ))
+    }
+}
+
+/// Parse an image size such as "800x800".
+fn parse_size(size: &str) -> Result<(u32, u32), String> {
+    match parse_pair::<u32>(size, 'x') {
+        Some((width, height)) if width > 0 && height > 0 => Ok((width, height)),
+        _ => Err(format!(
+            "invalid size '{}', expected <width>x<height>",
+            size
+        )),
+    }
+}
+
+/// Check a fractal name against the registry.
+fn parse_fractal_name(name: &str) -> Result<String, String> {
+    match fractal::lookup(name) {
+        Some(fractal) => Ok(fractal.name().to_string()),
+        None => Err(unknown_name("fractal", name, &fractal::names())),
+    }
+}
+
+/// Run any command other than `serve`, returning the process exit code.
+pub fn run(command: Command) -> i32 {
+    let result = match command {
+        Command::Serve | Command::Help => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Command::Render {
+            params,
+            filename,
+            width,
+            height,
+            threads,
+        } => match params.to_generation() {
+            Ok(mut generation) => {
+                generation.scheme.threads = threads;
+                let image = generate_fractal(&generation.scheme, width, height);
+                save_image(&image, &filename, &generation.scheme)
+            }
+            Err(message) => {
+                eprintln!("error: {}", message);
+                return EXIT_USAGE;
+            }
+        },
+        Command::Random {
+            filename,
+            count,
+            threads,
+        } => auto_random(count, &filename, threads),
+        Command::Interactive {
+            fractal,
+            filename,
+            width,
+            height,
+            threads,
+        } => {
+            let mut scheme = Scheme {
+                fractal,
+                threads,
+                ..Default::default()
+            };
+            if let Some(fractal) = fractal::lookup(&scheme.fractal) {
+                fractal.randomize(&mut scheme);
+            }
+            user_menu(&mut scheme);
+            let image = generate_fractal(&scheme, width, height);
+            save_image(&image, &filename, &scheme)
+        }
+    };
+
+    match result {
+        Ok(()) => EXIT_OK,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            EXIT_FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod cli_tests {
+    use super::*;
+
+    fn args(line: &str) -> Vec<String> {
+        line.split_whitespace().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn test_parse_render() {
+        let command = parse_args(&args(
+            "render julia julia.png 800x600 --threads 3 --color blue",
+        ))
+        .unwrap();
+        let params = GenParams {
+            fractal: Some("julia".to_string()),
+            color: Some("blue".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Command::Render {
+                params: Box::new(params),
+                filename: "julia.png".to_string(),
+                width: 800,
+                height: 600,
+                threads: 3,
+            },
+            command
+        );
+    }
+
+    #[test]
+    fn test_parse_legacy_forms() {
+        assert_eq!(
+            parse_args(&args("render julia julia.png 800x800")),
+            parse_args(&args("julia julia.png 800x800"))
+        );
+        assert_eq!(
+            Ok(Command::Random {
+                filename: "a_filename".to_string(),
+                count: 10,
+                threads: default_threads(),
+            }),
+            parse_args(&args("auto-random a_filename 10"))
+        );
+    }
+
+    #[test]
+    fn test_parse_help_and_serve() {
+        assert_eq!(Ok(Command::Help), parse_args(&args("render --help")));
+        assert_eq!(Ok(Command::Serve), parse_args(&args("serve")));
+    }
+
+    #[test]
+    fn test_parse_bad_args() {
+        assert!(parse_args(&[]).is_err());
+        assert!(parse_args(&args("explode")).is_err());
+        assert!(parse_args(&args("render julia julia.png 800")).is_err());
+        assert!(parse_args(&args("render julia julia.png 8x8 --color")).is_err());
+        assert!(parse_args(&args("render julia julia.png 8x8 --width 9")).is_err());
+        assert!(parse_args(&args("render julia julia.png 8x8 zoom 9")).is_err());
+        assert!(parse_args(&args("random a_filename lots")).is_err());
+        assert!(parse_args(&args("interactive fern fern.png 8x8")).is_err());
+        assert!(parse_args(&args("render ifs ifs.png 8x8 --maps-file /nonexistent")).is_err());
+        assert!(parse_args(&args(
+            "render flame flame.png 8x8 --transforms-file /nonexistent"
+        ))
+        .is_err());
+        assert!(parse_args(&args("random a_filename 2 --threads 0")).is_err());
+    }
+}
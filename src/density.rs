@@ -0,0 +1,548 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Density rendering for the chaos game fractals. Rather than painting
+//! every point flat, points are counted per pixel over millions of
+//! iterations spread across worker threads, and the counts are mapped
+//! through a logarithm, a gamma curve and a palette, so the dense parts
+//! of an attractor glow and the sparse parts fade out.
+
+// Log-density display: https://flam3.com/flame_draves.pdf, section 4
+
+use crate::fractal::Param;
+use crate::palette::{Gradient, Interpolation, Palette};
+use crate::plot::PlotMapping;
+use crate::render::{par_for_each_band, par_for_each_pixel};
+use crate::util::*;
+use image::{Rgba, RgbaImage};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::thread;
+
+/// Points plotted by a chaos game unless a scheme says otherwise.
+pub const DEFAULT_POINTS: u64 = 1_000_000;
+/// Most points a chaos game may be asked to plot.
+pub const MAX_POINTS: u64 = 100_000_000;
+/// Gamma applied to the log-density unless a scheme says otherwise.
+pub const DEFAULT_GAMMA: f64 = 2.2;
+/// Most histogram cells, so supersampling a large image is scaled back
+/// rather than running out of memory.
+const MAX_CELLS: u64 = 1 << 26;
+/// Radius in pixels of the density estimation filter around a single
+/// point, unless a scheme says otherwise.
+pub const DEFAULT_ESTIMATOR: f64 = 3.0;
+/// Largest radius of the density estimation filter.
+pub const MAX_ESTIMATOR: f64 = 10.0;
+/// How quickly the filter narrows as points pile up: the points of a cell
+/// holding n of them are spread over the radius divided by n^curve.
+const ESTIMATOR_CURVE: f64 = 0.4;
+/// Fixed point scale color coordinates are summed in.
+const COLOR_UNIT: f64 = 65536.0;
+
+/// Parameters of the fractals drawn by density.
+pub const DENSITY_PARAMS: [Param; 3] = [
+    Param {
+        name: "points",
+        description: "Points plotted, up to 100,000,000, e.g. 5e6",
+        default: "1000000",
+    },
+    Param {
+        name: "gamma",
+        description: "Gamma of the log-density, higher brings out sparse areas",
+        default: "2.2",
+    },
+    Param {
+        name: "samples",
+        description: "Cells per side of a pixel points are counted in, from 1 to 8",
+        default: "1",
+    },
+];
+
+/// How a cell's count is scaled against the largest count before gamma.
+/// Attractors are drawn on a log scale so their faintest points show,
+/// Buddhabrots on a linear one as every pixel of them is hit.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Scale {
+    Log,
+    Linear,
+}
+
+impl Scale {
+    /// Brightness from 0 to 1 of `count` points where the most is `max`.
+    pub fn brightness(self, count: f64, max: f64, gamma: f64) -> f64 {
+        let fraction = match self {
+            Scale::Log => (1.0 + count).ln() / (1.0 + max).ln().max(1e-9),
+            Scale::Linear => count / max.max(1e-9),
+        };
+        fraction.min(1.0).powf(1.0 / gamma)
+    }
+}
+
+/// Number of points landing in each cell of a grid, which worker threads
+/// can add to at the same time. A histogram made `with_colors` also sums
+/// the color coordinates of the points, for the flames.
+pub struct Histogram {
+    pub width: u32,
+    pub height: u32,
+    counts: Vec<AtomicU32>,
+    colors: Vec<AtomicU64>,
+}
+
+impl Histogram {
+    /// An empty histogram.
+    pub fn new(width: u32, height: u32) -> Histogram {
+        let cells = width as usize * height as usize;
+        Histogram {
+            width,
+            height,
+            counts: (0..cells).map(|_| AtomicU32::new(0)).collect(),
+            colors: Vec::new(),
+        }
+    }
+
+    /// An empty histogram that sums colors too.
+    pub fn with_colors(width: u32, height: u32) -> Histogram {
+        let cells = width as usize * height as usize;
+        Histogram {
+            colors: (0..cells).map(|_| AtomicU64::new(0)).collect(),
+            ..Histogram::new(width, height)
+        }
+    }
+
+    /// Count a point in one cell.
+    pub fn add(&self, x: u32, y: u32) {
+        self.add_count(x, y, 1);
+    }
+
+    /// Count some number of points in one cell.
+    pub fn add_count(&self, x: u32, y: u32, count: u32) {
+        self.counts[y as usize * self.width as usize + x as usize]
+            .fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Count a point with a color coordinate from 0 to 1 in one cell.
+    pub fn add_colored(&self, x: u32, y: u32, color: f64) {
+        self.add(x, y);
+        if !self.colors.is_empty() {
+            let fixed = (color.clamp(0.0, 1.0) * COLOR_UNIT) as u64;
+            self.colors[y as usize * self.width as usize + x as usize]
+                .fetch_add(fixed, Ordering::Relaxed);
+        }
+    }
+
+    /// Count a point of the plane in the cell a mapping fitted to this
+    /// histogram puts it in. Points outside the grid are left out.
+    pub fn plot(&self, mapping: &PlotMapping, point: (f64, f64)) {
+        if let Some((x, y)) = mapping.pixel(point) {
+            self.add(x, y);
+        }
+    }
+
+    /// Count a point of the plane with its color coordinate.
+    pub fn plot_colored(&self, mapping: &PlotMapping, point: (f64, f64), color: f64) {
+        if let Some((x, y)) = mapping.pixel(point) {
+            self.add_colored(x, y, color);
+        }
+    }
+
+    /// The counts, and color sums if there are any, as numbers.
+    pub fn density(&self) -> Density {
+        let load = |count: &AtomicU32| f64::from(count.load(Ordering::Relaxed));
+        let colors = if self.colors.is_empty() {
+            None
+        } else {
+            let load = |sum: &AtomicU64| sum.load(Ordering::Relaxed) as f64 / COLOR_UNIT;
+            Some(self.colors.iter().map(load).collect())
+        };
+        Density {
+            width: self.width,
+            height: self.height,
+            counts: self.counts.iter().map(load).collect(),
+            colors,
+        }
+    }
+}
+
+/// The points counted in each cell of a histogram, and the sum of their
+/// colors, as numbers that density estimation can spread between cells.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Density {
+    pub width: u32,
+    pub height: u32,
+    counts: Vec<f64>,
+    colors: Option<Vec<f64>>,
+}
+
+impl Density {
+    /// Points counted in the fullest cell.
+    pub fn max(&self) -> f64 {
+        self.counts.iter().copied().fold(0.0, f64::max)
+    }
+
+    /// Density estimation: spread the points of each cell over a gaussian
+    /// whose radius shrinks as they pile up, from `max_radius` cells for a
+    /// single point, so sparse areas are smoothed out while dense detail
+    /// stays sharp. Points spread past the edges are lost. Bands of rows
+    /// are gathered on up to `threads` worker threads.
+    pub fn estimate(&self, max_radius: f64, threads: usize) -> Density {
+        if max_radius <= 0.0 {
+            return self.clone();
+        }
+        let (width, height) = (i64::from(self.width), i64::from(self.height));
+        // Counts are whole before estimation, so few kernels are needed.
+        let mut kernels = HashMap::new();
+        for &count in self.counts.iter().filter(|&&count| count > 0.0) {
+            kernels
+                .entry(count.to_bits())
+                .or_insert_with(|| gaussian_kernel(max_radius / count.powf(ESTIMATOR_CURVE)));
+        }
+        let reach = kernels
+            .values()
+            .flatten()
+            .map(|&(_, dy, _)| dy.abs())
+            .max()
+            .unwrap_or(0);
+
+        // Each band takes what the cells within reach of it spread into
+        // it, so bands a few times the reach scan few rows twice.
+        let spread = |values: &[f64]| {
+            let mut spread = vec![0.0; values.len()];
+            let band_rows = 4 * reach as usize + 1;
+            par_for_each_band(
+                &mut spread,
+                width as usize,
+                band_rows,
+                threads,
+                |first_row, band| {
+                    let first = first_row as i64;
+                    let last = first + band.len() as i64 / width;
+                    for y in (first - reach).max(0)..(last + reach).min(height) {
+                        for x in 0..width {
+                            let cell = (y * width + x) as usize;
+                            let count = self.counts[cell];
+                            if count <= 0.0 {
+                                continue;
+                            }
+                            for &(dx, dy, weight) in &kernels[&count.to_bits()] {
+                                let (kx, ky) = (x + dx, y + dy);
+                                if kx < 0 || kx >= width || ky < first || ky >= last {
+                                    continue;
+                                }
+                                band[((ky - first) * width + kx) as usize] += values[cell] * weight;
+                            }
+                        }
+                    }
+                },
+            );
+            spread
+        };
+
+        Density {
+            width: self.width,
+            height: self.height,
+            counts: spread(&self.counts),
+            colors: self.colors.as_deref().map(spread),
+        }
+    }
+}
+
+/// Offsets and weights of a gaussian kernel reaching `radius` cells,
+/// summing to 1. Below half a cell it is the cell itself.
+fn gaussian_kernel(radius: f64) -> Vec<(i64, i64, f64)> {
+    if radius < 0.5 {
+        return vec![(0, 0, 1.0)];
+    }
+    let reach = radius.ceil() as i64;
+    let mut kernel = Vec::new();
+    for dy in -reach..=reach {
+        for dx in -reach..=reach {
+            let distance = ((dx * dx + dy * dy) as f64).sqrt() / radius;
+            if distance <= 1.0 {
+                kernel.push((dx, dy, (-2.0 * distance * distance).exp()));
+            }
+        }
+    }
+    let total: f64 = kernel.iter().map(|&(_, _, weight)| weight).sum();
+    kernel
+        .into_iter()
+        .map(|(dx, dy, weight)| (dx, dy, weight / total))
+        .collect()
+}
+
+/// Parse a number of points, which may be written like 5e6.
+pub fn parse_points(points: &str) -> Result<u64, String> {
+    match points.trim().parse::<f64>() {
+        Ok(n) if n >= 1.0 && n <= MAX_POINTS as f64 && n.fract() == 0.0 => Ok(n as u64),
+        _ => Err(format!(
+            "Invalid points '{}', expected a whole number from 1 to {}",
+            points, MAX_POINTS
+        )),
+    }
+}
+
+/// Parse the radius of the density estimation filter.
+pub fn parse_estimator(radius: &str) -> Result<f64, String> {
+    match radius.trim().parse::<f64>() {
+        Ok(r) if (0.0..=MAX_ESTIMATOR).contains(&r) => Ok(r),
+        _ => Err(format!(
+            "Invalid estimator '{}', expected a radius from 0 to {}",
+            radius, MAX_ESTIMATOR
+        )),
+    }
+}
+
+/// Cells per side of a pixel for a scheme's samples, fewer if an image
+/// that size would need too many cells.
+pub fn density_samples(scheme: &Scheme, imgx: u32, imgy: u32) -> u32 {
+    let pixels = u64::from(imgx.max(1)) * u64::from(imgy.max(1));
+    let mut samples = scheme.sampling.per_side().max(1);
+    while samples > 1 && pixels * u64::from(samples * samples) > MAX_CELLS {
+        samples -= 1;
+    }
+    samples
+}
+
+/// Play a chaos game on every worker thread: `play` is called with each
+/// thread's share of the scheme's points.
+pub fn play_on_threads<F>(scheme: &Scheme, play: F)
+where
+    F: Fn(u64) + Sync,
+{
+    let threads = scheme.threads.max(1) as u64;
+    if threads == 1 {
+        play(scheme.points);
+    } else {
+        thread::scope(|scope| {
+            for i in 0..threads {
+                let share = scheme.points / threads + u64::from(i < scheme.points % threads);
+                let play = &play;
+                scope.spawn(move || play(share));
+            }
+        });
+    }
+}
+
+/// Draw a chaos game by density. `play` is called on each worker thread
+/// with its share of the scheme's points and the histogram to count them
+/// in, which has `samples` cells per side of each pixel.
+pub fn density_fractal<F>(imgx: u32, imgy: u32, scheme: &Scheme, play: F) -> RgbaImage
+where
+    F: Fn(u64, &Histogram) + Sync,
+{
+    let samples = density_samples(scheme, imgx, imgy);
+    let histogram = Histogram::new(imgx * samples, imgy * samples);
+    play_on_threads(scheme, |points| play(points, &histogram));
+    tone_map(&histogram.density(), samples, scheme)
+}
+
+/// Color each pixel from the log-density of its cells: the fraction
+/// log(1 + count) / log(1 + most) raised to 1 / gamma sets the opacity,
+/// and the cells of a pixel are averaged. Colors come from the average
+/// color coordinate of a cell's points if they were summed, otherwise
+/// the fraction picks a color from the palette. Without a palette the
+/// fractal's color fades in with density, or for color coordinates runs
+/// from the fractal's color to white.
+pub fn tone_map(density: &Density, samples: u32, scheme: &Scheme) -> RgbaImage {
+    tone_map_scaled(density, samples, Scale::Log, scheme)
+}
+
+/// `tone_map` with counts on some scale rather than the log scale.
+pub fn tone_map_scaled(
+    density: &Density,
+    samples: u32,
+    scale: Scale,
+    scheme: &Scheme,
+) -> RgbaImage {
+    let mut imgbuf = RgbaImage::new(density.width / samples, density.height / samples);
+    let max = density.max();
+    let flat = color_to_rgb(scheme.color);
+    let to_white = Palette::new(Gradient::even(&[flat, [255, 255, 255]], Interpolation::Lab));
+    let cells = f64::from(samples * samples);
+
+    par_for_each_pixel(&mut imgbuf, scheme.threads, |x, y, pixel| {
+        let mut sum = [0.0; 4];
+        for sy in 0..samples {
+            for sx in 0..samples {
+                let cell = (y * samples + sy) as usize * density.width as usize
+                    + (x * samples + sx) as usize;
+                let count = density.counts[cell];
+                if count <= 0.0 {
+                    continue;
+                }
+                let value = scale.brightness(count, max, scheme.gamma);
+                let color = match (&density.colors, &scheme.palette) {
+                    (Some(colors), palette) => palette
+                        .as_ref()
+                        .unwrap_or(&to_white)
+                        .color(colors[cell] / count),
+                    (None, Some(palette)) => palette.color(value),
+                    (None, None) => flat,
+                };
+                // Weighted by opacity so faint cells do not darken the color.
+                for channel in 0..3 {
+                    sum[channel] += f64::from(color[channel]) * value;
+                }
+                sum[3] += value;
+            }
+        }
+        *pixel = if sum[3] > 0.0 {
+            let channel = |c: f64| (c / sum[3]).round() as u8;
+            let alpha = (sum[3] / cells * 255.0).round().min(255.0) as u8;
+            Rgba([channel(sum[0]), channel(sum[1]), channel(sum[2]), alpha])
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+    });
+
+    imgbuf
+}
+
+/// Color each pixel from three densities, one per red, green and blue
+/// channel, each mapped through its own logarithm and gamma like
+/// `tone_map` so every channel reaches full brightness. The brightest
+/// channel sets the opacity.
+pub fn tone_map_rgb(
+    channels: &[Density; 3],
+    samples: u32,
+    scale: Scale,
+    scheme: &Scheme,
+) -> RgbaImage {
+    let (width, height) = (channels[0].width, channels[0].height);
+    let mut imgbuf = RgbaImage::new(width / samples, height / samples);
+    let max: Vec<f64> = channels.iter().map(Density::max).collect();
+    let cells = f64::from(samples * samples);
+
+    par_for_each_pixel(&mut imgbuf, scheme.threads, |x, y, pixel| {
+        let mut sum = [0.0; 3];
+        for sy in 0..samples {
+            for sx in 0..samples {
+                let cell =
+                    (y * samples + sy) as usize * width as usize + (x * samples + sx) as usize;
+                for (channel, density) in channels.iter().enumerate() {
+                    let count = density.counts[cell];
+                    if count > 0.0 {
+                        sum[channel] += scale.brightness(count, max[channel], scheme.gamma);
+                    }
+                }
+            }
+        }
+        let brightest = sum.iter().copied().fold(0.0, f64::max);
+        *pixel = if brightest > 0.0 {
+            let channel = |c: f64| (c / brightest * 255.0).round() as u8;
+            let alpha = (brightest / cells * 255.0).round().min(255.0) as u8;
+            Rgba([channel(sum[0]), channel(sum[1]), channel(sum[2]), alpha])
+        } else {
+            Rgba([0, 0, 0, 0])
+        };
+    });
+
+    imgbuf
+}
+
+#[cfg(test)]
+mod density_tests {
+    use super::*;
+    use crate::plot::Bounds;
+
+    fn count(density: &Density, x: u32, y: u32) -> f64 {
+        density.counts[y as usize * density.width as usize + x as usize]
+    }
+
+    #[test]
+    fn test_histogram_counts_and_clips() {
+        let histogram = Histogram::new(3, 2);
+        let bounds = Bounds {
+            min_x: 0.0,
+            min_y: 0.0,
+            max_x: 3.0,
+            max_y: 2.0,
+        };
+        let mapping = PlotMapping::fit(&bounds, 3, 2, 0.0);
+        histogram.plot(&mapping, (0.5, 1.5));
+        histogram.plot(&mapping, (2.9, 0.1));
+        histogram.add(2, 1);
+        histogram.plot(&mapping, (3.1, 0.0));
+        histogram.plot(&mapping, (-0.1, 1.0));
+        histogram.plot(&mapping, (1.0, 2.5));
+        let density = histogram.density();
+        assert_eq!(1.0, count(&density, 0, 0));
+        assert_eq!(2.0, count(&density, 2, 1));
+        assert_eq!(2.0, density.max());
+    }
+
+    #[test]
+    fn test_scales() {
+        for &scale in &[Scale::Log, Scale::Linear] {
+            assert_eq!(0.0, scale.brightness(0.0, 100.0, 1.0));
+            assert_eq!(1.0, scale.brightness(100.0, 100.0, 2.2));
+        }
+        assert_eq!(0.25, Scale::Linear.brightness(25.0, 100.0, 1.0));
+        assert_eq!(0.5, Scale::Linear.brightness(25.0, 100.0, 2.0));
+        // A few points stand out on a log scale but not a linear one.
+        assert!(Scale::Log.brightness(3.0, 1000.0, 1.0) > 0.2);
+        assert!(Scale::Linear.brightness(3.0, 1000.0, 1.0) < 0.01);
+    }
+
+    #[test]
+    fn test_parse_points() {
+        assert_eq!(Ok(5_000_000), parse_points("5e6"));
+        assert_eq!(Ok(20_000), parse_points("20000"));
+        assert!(parse_points("0").is_err());
+        assert!(parse_points("1.5").is_err());
+        assert!(parse_points("1e9").is_err());
+        assert_eq!(Ok(2.5), parse_estimator("2.5"));
+        assert!(parse_estimator("-1").is_err());
+        assert!(parse_estimator("11").is_err());
+    }
+
+    #[test]
+    fn test_density_fades_with_count() {
+        let scheme = Scheme {
+            color: Color::Red,
+            threads: 3,
+            points: 7,
+            ..Default::default()
+        };
+        // Every thread's share of the points lands in the first pixel.
+        let image = density_fractal(2, 1, &scheme, |points, histogram| {
+            for _ in 0..points {
+                histogram.add(0, 0);
+            }
+        });
+        assert_eq!(Rgba([255, 0, 0, 255]), *image.get_pixel(0, 0));
+        assert_eq!(0, image.get_pixel(1, 0)[3]);
+
+        let histogram = Histogram::new(2, 1);
+        (0..100).for_each(|_| histogram.add(0, 0));
+        histogram.add(1, 0);
+        let image = tone_map(&histogram.density(), 1, &scheme);
+        let faint = image.get_pixel(1, 0)[3];
+        assert!(faint > 0 && faint < 255);
+    }
+
+    #[test]
+    fn test_estimation_spreads_sparse_points() {
+        let histogram = Histogram::with_colors(9, 40);
+        histogram.add_colored(4, 4, 0.5);
+        (0..1000).for_each(|_| histogram.add_colored(1, 1, 1.0));
+        let density = histogram.density().estimate(3.0, 1);
+        // A point spread across bands of rows is gathered the same way.
+        histogram.add_colored(4, 13, 0.5);
+        assert_eq!(
+            histogram.density().estimate(3.0, 1),
+            histogram.density().estimate(3.0, 3)
+        );
+
+        // The lone point is smeared over its neighbours but not lost.
+        assert!(count(&density, 4, 4) < 1.0);
+        assert!(count(&density, 5, 4) > 0.0);
+        let total: f64 = density.counts.iter().sum();
+        assert!((total - 1001.0).abs() < 1e-9);
+        // The pile of points stays in its cell, its color with it.
+        assert_eq!(1000.0, count(&density, 1, 1));
+        assert_eq!(
+            Some(1000.0),
+            density.colors.as_ref().map(|colors| colors[10])
+        );
+        assert_eq!(histogram.density(), histogram.density().estimate(0.0, 1));
+    }
+}
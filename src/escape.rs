@@ -0,0 +1,80 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Results of iterating a point of an escape time fractal. Besides the
+//! integer iteration count, a fractional (smooth) count is kept, which
+//! removes the hard bands between neighbouring counts.
+
+// Smooth coloring: https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Continuous_(smooth)_coloring
+
+/// How a point of an escape time fractal behaved.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Escape {
+    /// Iterations done before the point escaped, or the iteration limit.
+    pub iterations: u32,
+    /// Whether the point escaped before the iteration limit.
+    pub escaped: bool,
+    /// Fractional iteration count, continuous across the image.
+    pub smooth: f64,
+}
+
+impl Escape {
+    /// A point that never escaped within `max_iterations`.
+    pub fn inside(max_iterations: u32) -> Escape {
+        Escape {
+            iterations: max_iterations,
+            escaped: false,
+            smooth: f64::from(max_iterations),
+        }
+    }
+
+    /// A point that escaped after `iterations`, with `norm_sqr` the squared
+    /// magnitude of its last value. `power` is the exponent of the formula,
+    /// e.g. 2 for z^2 + c. The smooth count is
+    /// n + 1 - log_power(ln|z| / ln(bailout)), which lies in n..n+1.
+    pub fn escaped(iterations: u32, norm_sqr: f64, bailout: f64, power: f64) -> Escape {
+        let log_z = norm_sqr.ln() / 2.0;
+        let log_bailout = bailout.max(1.0 + 1e-9).ln();
+        let fraction = (log_z / log_bailout).ln() / power.abs().max(1.0 + 1e-9).ln();
+        let smooth = f64::from(iterations) + 1.0 - fraction;
+        Escape {
+            iterations,
+            escaped: true,
+            smooth: if smooth.is_finite() {
+                smooth.max(0.0)
+            } else {
+                f64::from(iterations)
+            },
+        }
+    }
+
+    /// Iteration count used for coloring: the smooth one if asked for.
+    pub fn value(&self, smooth: bool) -> f64 {
+        if smooth {
+            self.smooth
+        } else {
+            f64::from(self.iterations)
+        }
+    }
+}
+
+#[cfg(test)]
+mod escape_tests {
+    use super::*;
+
+    #[test]
+    fn test_smooth_count_between_integer_counts() {
+        for &norm in &[2.01_f64, 2.5, 3.9] {
+            let escape = Escape::escaped(7, norm * norm, 2.0, 2.0);
+            assert!(escape.smooth > 7.0 && escape.smooth <= 8.0);
+        }
+    }
+
+    #[test]
+    fn test_smooth_count_is_continuous() {
+        // Escaping just past the bailout after n iterations matches
+        // escaping at nearly the bailout squared after n + 1.
+        let early = Escape::escaped(7, 2.0000001_f64.powi(2), 2.0, 2.0);
+        let late = Escape::escaped(8, (4.0_f64 * 0.9999999).powi(2), 2.0, 2.0);
+        assert!((late.smooth - early.smooth).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,672 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Fractal flames: the chaos game of an IFS whose transforms are affine
+//! maps followed by a weighted sum of nonlinear variations and an optional
+//! post-transform. Each transform has a color coordinate the points it
+//! makes drift towards, and a final transform can reshape every point
+//! before it is plotted. Points are drawn by log-density, smoothed by
+//! density estimation, and colored from the palette by their coordinate.
+
+// The fractal flame algorithm: https://flam3.com/flame_draves.pdf
+
+use crate::chaos;
+use crate::density::*;
+use crate::fractal::*;
+use crate::ifs::AffineMap;
+use crate::layers::BlendMode;
+use crate::plot::*;
+use crate::util::*;
+use image::RgbaImage;
+use rand::Rng;
+use std::f64::consts::PI;
+use std::fmt;
+
+/// Most transforms a flame may have.
+pub const MAX_TRANSFORMS: usize = 64;
+/// Share of the measured points left out on each side of the bounds,
+/// as the variations throw a few points very far out.
+const BOUNDS_OUTLIERS: f64 = 0.002;
+
+/// Parameters of the flame fractal.
+pub const FLAME_PARAMS: [Param; 3] = [
+    Param {
+        name: "flame",
+        description: "Preset flame, e.g. swirl-triangle or julia-rings",
+        default: "random",
+    },
+    Param {
+        name: "transforms",
+        description: "Transforms as a b c d e f [name=value ...] separated by ;",
+        default: "",
+    },
+    Param {
+        name: "estimator",
+        description: "Radius in pixels sparse points are smoothed over, up to 10, 0 for none",
+        default: "3",
+    },
+];
+
+/// The nonlinear functions a transform sums, from the flame paper's
+/// appendix. r is the distance from the origin, theta = atan2(x, y).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variation {
+    Linear,
+    Sinusoidal,
+    Spherical,
+    Swirl,
+    Horseshoe,
+    Polar,
+    Handkerchief,
+    Heart,
+    Disc,
+    Spiral,
+    Hyperbolic,
+    Diamond,
+    Julia,
+    Bent,
+    Fisheye,
+    Exponential,
+    Power,
+    Cosine,
+    Bubble,
+    Cylinder,
+    Eyefish,
+    Tangent,
+    Cross,
+}
+
+/// Every variation by the name transforms are written with.
+pub const VARIATIONS: [(&str, Variation); 23] = [
+    ("linear", Variation::Linear),
+    ("sinusoidal", Variation::Sinusoidal),
+    ("spherical", Variation::Spherical),
+    ("swirl", Variation::Swirl),
+    ("horseshoe", Variation::Horseshoe),
+    ("polar", Variation::Polar),
+    ("handkerchief", Variation::Handkerchief),
+    ("heart", Variation::Heart),
+    ("disc", Variation::Disc),
+    ("spiral", Variation::Spiral),
+    ("hyperbolic", Variation::Hyperbolic),
+    ("diamond", Variation::Diamond),
+    ("julia", Variation::Julia),
+    ("bent", Variation::Bent),
+    ("fisheye", Variation::Fisheye),
+    ("exponential", Variation::Exponential),
+    ("power", Variation::Power),
+    ("cosine", Variation::Cosine),
+    ("bubble", Variation::Bubble),
+    ("cylinder", Variation::Cylinder),
+    ("eyefish", Variation::Eyefish),
+    ("tangent", Variation::Tangent),
+    ("cross", Variation::Cross),
+];
+
+impl Variation {
+    /// The variation a name stands for.
+    pub fn parse(name: &str) -> Result<Variation, String> {
+        match VARIATIONS.iter().find(|(variation, _)| *variation == name) {
+            Some(&(_, variation)) => Ok(variation),
+            None => {
+                let names: Vec<&str> = VARIATIONS.iter().map(|(name, _)| *name).collect();
+                Err(unknown_name("variation", name, &names))
+            }
+        }
+    }
+
+    /// Name of the variation.
+    pub fn name(self) -> &'static str {
+        VARIATIONS
+            .iter()
+            .find(|&&(_, variation)| variation == self)
+            .map_or("linear", |(name, _)| name)
+    }
+
+    /// Apply the variation to a point. The Julia variation takes one of
+    /// two square roots at random.
+    pub fn apply<R: Rng>(self, (x, y): (f64, f64), rng: &mut R) -> (f64, f64) {
+        let r2 = x * x + y * y;
+        let r = r2.sqrt();
+        let theta = x.atan2(y);
+        match self {
+            Variation::Linear => (x, y),
+            Variation::Sinusoidal => (x.sin(), y.sin()),
+            Variation::Spherical => (x / r2, y / r2),
+            Variation::Swirl => {
+                let (sin, cos) = r2.sin_cos();
+                (x * sin - y * cos, x * cos + y * sin)
+            }
+            Variation::Horseshoe => ((x - y) * (x + y) / r, 2.0 * x * y / r),
+            Variation::Polar => (theta / PI, r - 1.0),
+            Variation::Handkerchief => (r * (theta + r).sin(), r * (theta - r).cos()),
+            Variation::Heart => (r * (theta * r).sin(), -r * (theta * r).cos()),
+            Variation::Disc => {
+                let (sin, cos) = (PI * r).sin_cos();
+                (theta / PI * sin, theta / PI * cos)
+            }
+            Variation::Spiral => ((theta.cos() + r.sin()) / r, (theta.sin() - r.cos()) / r),
+            Variation::Hyperbolic => (theta.sin() / r, r * theta.cos()),
+            Variation::Diamond => (theta.sin() * r.cos(), theta.cos() * r.sin()),
+            Variation::Julia => {
+                let omega = if rng.gen::<bool>() { PI } else { 0.0 };
+                let (sin, cos) = (theta / 2.0 + omega).sin_cos();
+                (r.sqrt() * cos, r.sqrt() * sin)
+            }
+            Variation::Bent => match (x >= 0.0, y >= 0.0) {
+                (true, true) => (x, y),
+                (false, true) => (2.0 * x, y),
+                (true, false) => (x, y / 2.0),
+                (false, false) => (2.0 * x, y / 2.0),
+            },
+            Variation::Fisheye => (2.0 * y / (r + 1.0), 2.0 * x / (r + 1.0)),
+            Variation::Exponential => {
+                let (sin, cos) = (PI * y).sin_cos();
+                ((x - 1.0).exp() * cos, (x - 1.0).exp() * sin)
+            }
+            Variation::Power => {
+                let scale = r.powf(theta.sin());
+                (scale * theta.cos(), scale * theta.sin())
+            }
+            Variation::Cosine => ((PI * x).cos() * y.cosh(), -(PI * x).sin() * y.sinh()),
+            Variation::Bubble => (4.0 * x / (r2 + 4.0), 4.0 * y / (r2 + 4.0)),
+            Variation::Cylinder => (x.sin(), y),
+            Variation::Eyefish => (2.0 * x / (r + 1.0), 2.0 * y / (r + 1.0)),
+            Variation::Tangent => (x.sin() / y.cos(), y.tan()),
+            Variation::Cross => {
+                let scale = 1.0 / (x * x - y * y).abs();
+                (x * scale, y * scale)
+            }
+        }
+    }
+}
+
+/// One transform of a flame: an affine map, whose weight is the
+/// transform's, then the sum of some variations each scaled by an amount,
+/// then an optional post-transform. Points it makes have their color
+/// coordinate moved halfway to its own.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Transform {
+    pub affine: AffineMap,
+    pub variations: Vec<(Variation, f64)>,
+    pub post: Option<AffineMap>,
+    pub color: f64,
+}
+
+impl Transform {
+    /// Apply the transform to a point.
+    pub fn apply<R: Rng>(&self, point: (f64, f64), rng: &mut R) -> (f64, f64) {
+        let point = self.affine.apply(point);
+        let mut sum = (0.0, 0.0);
+        for &(variation, amount) in &self.variations {
+            let (x, y) = variation.apply(point, rng);
+            sum.0 += amount * x;
+            sum.1 += amount * y;
+        }
+        match &self.post {
+            Some(post) => post.apply(sum),
+            None => sum,
+        }
+    }
+}
+
+/// Transforms written the way `Flame::parse` reads them.
+impl fmt::Display for Transform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let m = &self.affine;
+        write!(
+            f,
+            "{} {} {} {} {} {} weight={} color={}",
+            m.a, m.b, m.c, m.d, m.e, m.f, m.weight, self.color
+        )?;
+        for (variation, amount) in &self.variations {
+            write!(f, " {}={}", variation.name(), amount)?;
+        }
+        if let Some(m) = &self.post {
+            write!(f, " post={},{},{},{},{},{}", m.a, m.b, m.c, m.d, m.e, m.f)?;
+        }
+        Ok(())
+    }
+}
+
+/// A fractal flame and the extent of its attractor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Flame {
+    /// Preset name, or "custom"
+    pub name: String,
+    pub transforms: Vec<Transform>,
+    /// Transform applied to every point as it is plotted, but not fed back
+    pub final_transform: Option<Transform>,
+    pub bounds: Bounds,
+}
+
+/// Every preset by the name the `flame` parameter takes, written the way
+/// a flame file is.
+pub const FLAME_PRESETS: [(&str, &str); 6] = [
+    (
+        "swirl-triangle",
+        "0.5 0 0 0.5 -0.5 -0.4 color=0 linear=0.6 swirl=0.4
+         0.5 0 0 0.5 0.5 -0.4 color=0.5 linear=0.6 swirl=0.4
+         0.5 0 0 0.5 0 0.47 color=1 linear=0.6 swirl=0.4",
+    ),
+    (
+        "julia-rings",
+        "0.8 0.3 -0.3 0.8 0.2 0 color=0 julia=1
+         0.4 0 0 0.4 0.6 0.2 color=1 spherical=0.8 linear=0.2
+         final 1 0 0 1 0 0 color=0.5 linear=0.7 bubble=0.3",
+    ),
+    (
+        "eclipse",
+        "0.75 0.35 -0.35 0.75 0.3 0 color=0 spherical=0.8 linear=0.2
+         0.5 0 0 0.5 -0.7 0.3 color=1 sinusoidal=1
+         final 1 0 0 1 0 0 color=0.5 spherical=0.5 linear=0.5",
+    ),
+    (
+        "horseshoe-bloom",
+        "0.7 -0.5 0.5 0.7 0 0 color=0 horseshoe=0.7 linear=0.3
+         0.4 0.3 -0.3 0.4 0.5 0.5 color=0.5 polar=0.4 linear=0.6
+         0.3 0 0 0.3 -0.6 0.4 weight=0.6 color=1 disc=1",
+    ),
+    (
+        "heart-spiral",
+        "0.7 0.4 -0.4 0.7 0.1 0 color=0 spiral=0.3 linear=0.7
+         0.45 0 0 0.45 0.8 0.2 color=0.8 heart=1
+         0.3 -0.2 0.2 0.3 -0.6 -0.5 weight=0.5 color=1 handkerchief=1",
+    ),
+    (
+        "polar-shards",
+        "0.5 0.5 -0.5 0.5 0 0 color=0 polar=1
+         0.6 0 0 0.6 0.4 0.3 color=1 spherical=1",
+    ),
+];
+
+impl Flame {
+    /// A flame from its transforms, measuring its attractor. Fails for
+    /// flames whose points fly off rather than settle onto an attractor.
+    pub fn new(
+        name: &str,
+        transforms: Vec<Transform>,
+        final_transform: Option<Transform>,
+    ) -> Result<Flame, String> {
+        if transforms.is_empty() || transforms.len() > MAX_TRANSFORMS {
+            return Err(format!(
+                "A flame needs from 1 to {} transforms, not {}",
+                MAX_TRANSFORMS,
+                transforms.len()
+            ));
+        }
+        let total: f64 = transforms.iter().map(|t| t.affine.weight).sum();
+        let valid = transforms
+            .iter()
+            .all(|t| t.affine.weight >= 0.0 && t.affine.weight.is_finite());
+        if !valid || total <= 0.0 {
+            return Err("Flame transform weights must be positive numbers".to_string());
+        }
+        let colors_valid = transforms
+            .iter()
+            .chain(&final_transform)
+            .all(|t| t.color >= 0.0 && t.color <= 1.0);
+        if !colors_valid {
+            return Err("Flame transform colors must be from 0 to 1".to_string());
+        }
+        let mut flame = Flame {
+            name: name.to_string(),
+            transforms,
+            final_transform,
+            bounds: Bounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 0.0,
+                max_y: 0.0,
+            },
+        };
+        flame.bounds = flame.measure_bounds()?;
+        Ok(flame)
+    }
+
+    /// One of the presets by name.
+    pub fn preset(name: &str) -> Result<Flame, String> {
+        let (preset, text) = chaos::find_preset("flame", &FLAME_PRESETS, name)?;
+        Ok(Flame {
+            name: preset.to_string(),
+            ..Flame::parse(text)?
+        })
+    }
+
+    /// Read transforms written one per line or separated by `;`, each as
+    /// the six affine coefficients a b c d e f, separated by spaces or
+    /// commas, followed by `name=value` options: `weight`, `color` from
+    /// 0 to 1, `post=a,b,c,d,e,f` for a post-transform, and the amount of
+    /// any variation, e.g. `swirl=0.5`. Transforms without variations are
+    /// linear, without weights weigh 1, and without colors are spread
+    /// evenly from 0 to 1. A transform starting with `final` is the final
+    /// transform. Anything after a `#` is a comment.
+    pub fn parse(text: &str) -> Result<Flame, String> {
+        let mut transforms = Vec::new();
+        let mut final_transform = None;
+        let lines = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""));
+        for written in lines.flat_map(|line| line.split(';')) {
+            let mut words = written.split_whitespace().peekable();
+            if words.peek().is_none() {
+                continue;
+            }
+            let is_final = words.peek() == Some(&"final");
+            if is_final {
+                words.next();
+            }
+            let (options, coefficients): (Vec<&str>, Vec<&str>) =
+                words.partition(|word| word.contains('='));
+            let invalid = || {
+                format!(
+                    "Invalid flame transform '{}', expected a b c d e f followed by name=value options",
+                    written.trim()
+                )
+            };
+            let affine = match parse_coefficients(&coefficients.join(","), 1.0) {
+                Some(affine) => affine,
+                None => return Err(invalid()),
+            };
+            let mut transform = (
+                Transform {
+                    affine,
+                    variations: Vec::new(),
+                    post: None,
+                    color: 0.0,
+                },
+                None,
+            );
+            for option in options {
+                let (name, value) = option.split_once('=').unwrap_or((option, ""));
+                let number = || parse_number(name, value);
+                match name {
+                    "weight" => transform.0.affine.weight = number()?,
+                    "color" => transform.1 = Some(number()?),
+                    "post" => {
+                        transform.0.post = match parse_coefficients(value, 1.0) {
+                            Some(post) => Some(post),
+                            None => {
+                                return Err(format!(
+                                    "Invalid post-transform '{}', expected a,b,c,d,e,f",
+                                    value
+                                ))
+                            }
+                        }
+                    }
+                    _ => transform
+                        .0
+                        .variations
+                        .push((Variation::parse(name)?, number()?)),
+                }
+            }
+            if transform.0.variations.is_empty() {
+                transform.0.variations.push((Variation::Linear, 1.0));
+            }
+            if is_final {
+                if final_transform.is_some() {
+                    return Err("A flame can only have one final transform".to_string());
+                }
+                final_transform = Some(transform);
+            } else {
+                transforms.push(transform);
+            }
+        }
+
+        // Colors left out are spread evenly across the transforms.
+        let last = transforms.len().saturating_sub(1).max(1) as f64;
+        let transforms = transforms
+            .into_iter()
+            .enumerate()
+            .map(|(i, (transform, color))| Transform {
+                color: color.unwrap_or(i as f64 / last),
+                ..transform
+            })
+            .collect();
+        let final_transform = final_transform.map(|(transform, color)| Transform {
+            color: color.unwrap_or(0.5),
+            ..transform
+        });
+        Flame::new("custom", transforms, final_transform)
+    }
+
+    /// Play the chaos game from a random point and color: call `plot`
+    /// with every point after the first few, passed through the final
+    /// transform, and its color coordinate. A point thrown to infinity
+    /// starts over.
+    pub fn play<R: Rng, F: FnMut((f64, f64), f64)>(&self, rng: &mut R, points: u64, mut plot: F) {
+        let weights: Vec<f64> = self.transforms.iter().map(|t| t.affine.weight).collect();
+        chaos::play(
+            rng,
+            &weights,
+            points,
+            |rng| {
+                let point = (rng.gen_range(-1.0, 1.0), rng.gen_range(-1.0, 1.0));
+                (point, rng.gen::<f64>())
+            },
+            |rng, (point, color), index| {
+                let transform = &self.transforms[index];
+                let point = transform.apply(point, rng);
+                let color = (color + transform.color) / 2.0;
+                Some((point, color)).filter(|_| point.0.is_finite() && point.1.is_finite())
+            },
+            |rng, (point, color), _| match &self.final_transform {
+                Some(last) => plot(last.apply(point, rng), (color + last.color) / 2.0),
+                None => plot(point, color),
+            },
+        );
+    }
+
+    /// Find the extent of the attractor from a sample of its points,
+    /// leaving out the farthest few.
+    fn measure_bounds(&self) -> Result<Bounds, String> {
+        chaos::measure_bounds(BOUNDS_OUTLIERS, |points, plot| {
+            self.play(&mut rand::thread_rng(), points, |point, _| plot(point));
+        })
+        .ok_or_else(|| {
+            "The flame transforms do not settle onto an attractor, try other coefficients"
+                .to_string()
+        })
+    }
+}
+
+/// Transforms written the way `Flame::parse` reads them.
+impl fmt::Display for Flame {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let mut transforms: Vec<String> = self.transforms.iter().map(|t| t.to_string()).collect();
+        if let Some(last) = &self.final_transform {
+            transforms.push(format!("final {}", last));
+        }
+        write!(f, "{}", transforms.join("; "))
+    }
+}
+
+/// Six finite affine coefficients separated by commas, with a weight.
+fn parse_coefficients(text: &str, weight: f64) -> Option<AffineMap> {
+    let numbers = text
+        .split(',')
+        .filter(|number| !number.is_empty())
+        .map(|number| number.parse::<f64>().ok().filter(|n| n.is_finite()))
+        .collect::<Option<Vec<f64>>>()?;
+    match numbers.as_slice() {
+        &[a, b, c, d, e, f] => Some(AffineMap::new(a, b, c, d, e, f, weight)),
+        _ => None,
+    }
+}
+
+/// A finite number given as the value of a transform option.
+fn parse_number(name: &str, value: &str) -> Result<f64, String> {
+    match value.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!(
+            "Invalid flame {} '{}', expected a number",
+            name, value
+        )),
+    }
+}
+
+/// Pick one of the presets at random.
+pub fn random_flame() -> Flame {
+    Flame::preset(chaos::random_preset(&FLAME_PRESETS))
+        .expect("presets settle onto their attractors")
+}
+
+/// Any fractal flame, drawn by `flame_fractal`.
+pub struct FlameFractal;
+
+impl Fractal for FlameFractal {
+    fn name(&self) -> &'static str {
+        "flame"
+    }
+
+    /// Flames are colored by density, so any color works.
+    fn colors(&self) -> &'static [Color] {
+        &ALL_COLORS
+    }
+
+    fn supports_color(&self, _color: Color) -> bool {
+        true
+    }
+
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Normal
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = common_params();
+        params.extend_from_slice(&DENSITY_PARAMS);
+        params.extend_from_slice(&FLAME_PARAMS);
+        params
+    }
+
+    fn body_param(&self) -> Option<&'static str> {
+        Some("transforms")
+    }
+
+    fn randomize(&self, scheme: &mut Scheme) {
+        scheme.flame = Some(random_flame());
+    }
+
+    fn metadata(&self, scheme: &Scheme) -> Vec<(&'static str, String)> {
+        match &scheme.flame {
+            Some(flame) => vec![
+                ("Flame", flame.name.clone()),
+                ("Flame transforms", flame.to_string()),
+            ],
+            None => Vec::new(),
+        }
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let flame = scheme.flame.clone().unwrap_or_else(random_flame);
+        flame_fractal(&flame, imgx, imgy, scheme)
+    }
+}
+
+/// Draw a flame by the density and color of its points, fitted into the
+/// image like an IFS, then smoothed by density estimation.
+pub fn flame_fractal(flame: &Flame, imgx: u32, imgy: u32, scheme: &Scheme) -> RgbaImage {
+    let samples = density_samples(scheme, imgx, imgy);
+    let histogram = Histogram::with_colors(imgx * samples, imgy * samples);
+    let mapping = PlotMapping::fit(&flame.bounds, histogram.width, histogram.height, MARGIN);
+    play_on_threads(scheme, |points| {
+        flame.play(&mut rand::thread_rng(), points, |point, color| {
+            histogram.plot_colored(&mapping, point, color);
+        });
+    });
+    let density = histogram
+        .density()
+        .estimate(scheme.estimator * f64::from(samples), scheme.threads);
+    tone_map(&density, samples, scheme)
+}
+
+#[cfg(test)]
+mod flame_tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_settle_in_their_bounds() {
+        for (name, _) in FLAME_PRESETS.iter() {
+            let flame = Flame::preset(name).unwrap();
+            let bounds = flame.bounds;
+            assert!(bounds.min_x < bounds.max_x && bounds.min_y < bounds.max_y);
+            let mut inside = 0;
+            flame.play(&mut rand::thread_rng(), 1000, |(x, y), color| {
+                assert!((0.0..=1.0).contains(&color));
+                if x >= bounds.min_x && x <= bounds.max_x && y >= bounds.min_y && y <= bounds.max_y
+                {
+                    inside += 1;
+                }
+            });
+            assert!(inside > 950, "{} has {} points inside", name, inside);
+        }
+        assert!(Flame::preset("phoenix").is_err());
+    }
+
+    #[test]
+    fn test_parse_transforms() {
+        let flame = Flame::parse(
+            "# a flame file
+             0.5 0 0 0.5 0 0 swirl=0.5 linear=0.5
+             0.5,0,0,0.5,0.5,0 weight=2 post=1,0,0,1,0.1,0; 0.5 0 0 0.5 0 0.5 color=0.9
+             final 1 0 0 1 0 0 spherical=1",
+        )
+        .unwrap();
+        assert_eq!(3, flame.transforms.len());
+        let first = &flame.transforms[0];
+        assert_eq!(
+            vec![(Variation::Swirl, 0.5), (Variation::Linear, 0.5)],
+            first.variations
+        );
+        assert_eq!((1.0, 0.0), (first.affine.weight, first.color));
+        assert_eq!(2.0, flame.transforms[1].affine.weight);
+        assert_eq!(0.5, flame.transforms[1].color);
+        assert_eq!(0.9, flame.transforms[2].color);
+        assert_eq!(Some(0.1), flame.transforms[1].post.map(|post| post.e));
+        let last = flame.final_transform.as_ref().unwrap();
+        assert_eq!(vec![(Variation::Spherical, 1.0)], last.variations);
+
+        let written = Flame::parse(&flame.to_string()).unwrap();
+        assert_eq!(flame.transforms, written.transforms);
+        assert_eq!(flame.final_transform, written.final_transform);
+
+        assert!(Flame::parse("").is_err());
+        assert!(Flame::parse("0.5 0 0 0.5 0 twirl=1").is_err());
+        assert!(Flame::parse("0.5 0 0 0.5 0 0 twirl=1").is_err());
+        assert!(Flame::parse("0.5 0 0 0.5 0 0 color=2").is_err());
+        assert!(Flame::parse("1 0 0 1 0 0; final 1 0 0 1 0 0; final 1 0 0 1 0 0").is_err());
+        assert!(Flame::parse("3 0 0 3 1 1").is_err());
+
+        let file = Flame::parse(include_str!("../resources/flames/swirled-dragon.flame")).unwrap();
+        assert!(file.final_transform.is_some());
+    }
+
+    #[test]
+    fn test_variations() {
+        let mut rng = rand::thread_rng();
+        let close = |(x, y): (f64, f64), (ex, ey): (f64, f64)| {
+            (x - ex).abs() < 1e-12 && (y - ey).abs() < 1e-12
+        };
+        assert!(close(
+            Variation::Spherical.apply((2.0, 0.0), &mut rng),
+            (0.5, 0.0)
+        ));
+        assert!(close(
+            Variation::Polar.apply((0.0, 2.0), &mut rng),
+            (0.0, 1.0)
+        ));
+        assert!(close(
+            Variation::Bubble.apply((2.0, 0.0), &mut rng),
+            (1.0, 0.0)
+        ));
+        assert!(close(
+            Variation::Bent.apply((-1.0, -1.0), &mut rng),
+            (-2.0, -0.5)
+        ));
+        let (x, y) = Variation::Julia.apply((0.0, 4.0), &mut rng);
+        assert!(close((x.abs(), y), (2.0, 0.0)));
+        for (name, variation) in VARIATIONS.iter() {
+            assert_eq!(Ok(*variation), Variation::parse(name));
+            assert_eq!(*name, variation.name());
+        }
+    }
+}
@@ -0,0 +1,327 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! The `Fractal` trait every fractal implements, and the registry
+//! used to look fractals up by name. Adding a new fractal means
+//! writing one type that implements `Fractal` and listing it in
+//! `BASE_FRACTALS`.
+
+use crate::barnsley::Barnsley;
+use crate::buddhabrot::{AntiBuddhabrot, Buddhabrot, Nebulabrot};
+use crate::flame::FlameFractal;
+use crate::ifs::IfsFractal;
+use crate::julia_sets::{Julia, MultiJulia, Multibrot};
+use crate::layers::BlendMode;
+use crate::mandelbrot::Mandelbrot;
+use crate::newton::Newton;
+use crate::util::*;
+use crate::variants::VARIANTS;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use rand::seq::IteratorRandom;
+
+/// Description of a parameter a fractal reads from its scheme.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Param {
+    pub name: &'static str,
+    pub description: &'static str,
+    pub default: &'static str,
+}
+
+/// Parameters every fractal understands.
+pub const COMMON_PARAMS: [Param; 9] = [
+    Param {
+        name: "color",
+        description: "Color of the fractal",
+        default: "random",
+    },
+    Param {
+        name: "bg",
+        description: "Solid background color",
+        default: "random",
+    },
+    Param {
+        name: "bg-gradient",
+        description: "Gradient background stops, e.g. navy,orange@0.7,white",
+        default: "random",
+    },
+    Param {
+        name: "bg-shape",
+        description: "Shape of a gradient background: linear, radial or conic",
+        default: "random",
+    },
+    Param {
+        name: "bg-angle",
+        description: "Counterclockwise angle of a linear or conic gradient background in degrees",
+        default: "random",
+    },
+    Param {
+        name: "transform",
+        description: "Image transformation applied after drawing",
+        default: "random",
+    },
+    Param {
+        name: "blend",
+        description: "Blend mode over the background: normal, multiply, screen or overlay",
+        default: "fractal dependent",
+    },
+    Param {
+        name: "fractal-only",
+        description: "Draw the fractal alone on a transparent background",
+        default: "false",
+    },
+    Param {
+        name: "size",
+        description: "Image dimensions as <width>x<height>",
+        default: "1000x1000",
+    },
+];
+
+/// Parameters choosing the palette a fractal is colored with.
+pub const PALETTE_PARAMS: [Param; 6] = [
+    Param {
+        name: "palette",
+        description: "Named palette or color stops, e.g. fire or black,red@0.3,yellow",
+        default: "fade to color",
+    },
+    Param {
+        name: "interpolation",
+        description: "Color space blended in between stops: linear, hsv or lab",
+        default: "palette dependent",
+    },
+    Param {
+        name: "mapping",
+        description: "Whether the palette repeats (cyclic) or stops at its ends (clamped)",
+        default: "palette dependent",
+    },
+    Param {
+        name: "offset",
+        description: "Shift of the colors along the palette",
+        default: "0",
+    },
+    Param {
+        name: "density",
+        description: "Number of times the palette is passed through",
+        default: "1",
+    },
+    Param {
+        name: "interior",
+        description: "Color of points inside an escape time set, or transparent",
+        default: "palette dependent",
+    },
+];
+
+/// Parameters of the fractals drawn from a viewport onto the complex plane.
+pub const VIEWPORT_PARAMS: [Param; 4] = [
+    Param {
+        name: "center",
+        description: "Complex point in the middle of the image as <re>,<im>",
+        default: "fractal dependent",
+    },
+    Param {
+        name: "span",
+        description: "Distance across the shorter side of the image",
+        default: "fractal dependent",
+    },
+    Param {
+        name: "zoom",
+        description: "Magnification of the default span",
+        default: "1",
+    },
+    Param {
+        name: "rotation",
+        description: "Counterclockwise rotation in degrees",
+        default: "0",
+    },
+];
+
+/// Parameters controlling how long escape time fractals iterate.
+pub const ITERATION_PARAMS: [Param; 6] = [
+    Param {
+        name: "iterations",
+        description: "Most iterations tried per point",
+        default: "255",
+    },
+    Param {
+        name: "bailout",
+        description: "Escape radius past which a point has escaped",
+        default: "2",
+    },
+    Param {
+        name: "smooth",
+        description: "Color by the fractional iteration count to remove banding",
+        default: "false",
+    },
+    Param {
+        name: "samples",
+        description: "Samples per side of a pixel for anti-aliasing, from 1 to 8",
+        default: "1",
+    },
+    Param {
+        name: "sampling",
+        description: "How samples are placed: grid, jittered or adaptive",
+        default: "grid",
+    },
+    Param {
+        name: "precision",
+        description: "Number type to iterate with: auto, f32, f64, arbitrary or perturbation",
+        default: "auto",
+    },
+];
+
+/// Parameters every fractal understands, including its palette.
+pub fn common_params() -> Vec<Param> {
+    let mut params = COMMON_PARAMS.to_vec();
+    params.extend_from_slice(&PALETTE_PARAMS);
+    params
+}
+
+/// Parameters of the escape time fractals: the common ones plus a viewport
+/// and iteration limits.
+pub fn escape_time_params() -> Vec<Param> {
+    let mut params = common_params();
+    params.extend_from_slice(&VIEWPORT_PARAMS);
+    params.extend_from_slice(&ITERATION_PARAMS);
+    params
+}
+
+/// A fractal that can be drawn from a scheme.
+pub trait Fractal: Sync {
+    /// Name used to select the fractal, e.g. "mandelbrot".
+    fn name(&self) -> &'static str;
+
+    /// Parameters the fractal reads from its scheme.
+    fn params(&self) -> Vec<Param> {
+        common_params()
+    }
+
+    /// Parameter filled from the body of a POST to `/gen`, for fractals
+    /// described by more text than fits in a query string.
+    fn body_param(&self) -> Option<&'static str> {
+        None
+    }
+
+    /// Pick the fractal's own random settings, such as a preset constant,
+    /// so what is drawn is part of the scheme and ends up in its log.
+    fn randomize(&self, _scheme: &mut Scheme) {}
+
+    /// Settings of the fractal's own worth keeping with its image, beside
+    /// the ones every scheme records.
+    fn metadata(&self, _scheme: &Scheme) -> Vec<(&'static str, String)> {
+        Vec::new()
+    }
+
+    /// Colors offered for the fractal by the menus and randomization.
+    fn colors(&self) -> &'static [Color] {
+        &FRACTAL_COLORS
+    }
+
+    /// Whether the fractal can be drawn in some color, named or not. Most
+    /// fractals fade in from black, so black itself does not show.
+    fn supports_color(&self, color: Color) -> bool {
+        visible_on_black(color)
+    }
+
+    /// Region of the complex plane drawn when the scheme has no viewport,
+    /// or `None` for fractals that are not drawn from a viewport.
+    fn default_viewport(&self) -> Option<Viewport> {
+        None
+    }
+
+    /// Blend mode the fractal is composited over its background with
+    /// when the scheme does not pick one. Screen lets the background show
+    /// through the dark parts of a fractal fading in from black.
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Screen
+    }
+
+    /// Draw the fractal layer in memory, transparent wherever there is no
+    /// fractal. The background is composited beneath it by `render_layers`.
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage;
+}
+
+/// Fractals registered one by one; the Mandelbrot variants are added
+/// from `VARIANTS` by `registry`.
+static BASE_FRACTALS: [&dyn Fractal; 11] = [
+    &Barnsley,
+    &IfsFractal,
+    &FlameFractal,
+    &Mandelbrot,
+    &Julia,
+    &MultiJulia,
+    &Multibrot,
+    &Newton,
+    &Buddhabrot,
+    &AntiBuddhabrot,
+    &Nebulabrot,
+];
+
+/// Every fractal this program can draw.
+fn registry() -> impl Iterator<Item = &'static dyn Fractal> {
+    BASE_FRACTALS
+        .iter()
+        .copied()
+        .chain(VARIANTS.iter().map(|variant| variant as &dyn Fractal))
+}
+
+/// Names of all registered fractals.
+pub fn names() -> Vec<&'static str> {
+    registry().map(|fractal| fractal.name()).collect()
+}
+
+/// Find a registered fractal by name.
+pub fn lookup(name: &str) -> Option<&'static dyn Fractal> {
+    registry().find(|fractal| fractal.name() == name)
+}
+
+/// Pick one of the registered fractals at random.
+pub fn random() -> &'static dyn Fractal {
+    registry().choose(&mut rand::thread_rng()).unwrap()
+}
+
+#[cfg(test)]
+mod fractal_tests {
+    use super::*;
+
+    #[test]
+    fn test_lookup_by_name() {
+        for name in names() {
+            assert_eq!(name, lookup(name).unwrap().name());
+        }
+        assert!(lookup("sierpinski").is_none());
+    }
+
+    #[test]
+    fn test_render_dimensions() {
+        let escape_time = registry().filter(|fractal| fractal.default_viewport().is_some());
+        for fractal in escape_time {
+            let mut scheme = Scheme {
+                fractal: fractal.name().to_string(),
+                // Keeps the Buddhabrots, which have viewports too, quick.
+                points: 10_000,
+                ..Default::default()
+            };
+            if let Some(&color) = fractal.colors().first() {
+                scheme.color = color;
+            }
+            let image = fractal.render(&scheme, 40, 30);
+            assert_eq!((40, 30), image.dimensions());
+        }
+    }
+
+    #[test]
+    fn test_threaded_render_is_identical() {
+        let mandelbrot = lookup("mandelbrot").unwrap();
+        let single = Scheme {
+            threads: 1,
+            ..Default::default()
+        };
+        let threaded = Scheme {
+            threads: 6,
+            ..Default::default()
+        };
+        assert_eq!(
+            mandelbrot.render(&single, 64, 48).into_raw(),
+            mandelbrot.render(&threaded, 64, 48).into_raw()
+        );
+    }
+}
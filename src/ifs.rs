@@ -0,0 +1,390 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Iterated function systems: a list of affine maps, each picked at
+//! random with some weight and applied to the last point, whose points
+//! settle onto the system's attractor (the chaos game). Barnsley's fern
+//! is one of them.
+
+// IFS: https://en.wikipedia.org/wiki/Iterated_function_system
+// Fern variants: https://en.wikipedia.org/wiki/Barnsley_fern#Mutant_varieties
+// Other presets: http://paulbourke.net/fractals/ifs/
+
+use crate::chaos;
+use crate::density::*;
+use crate::fractal::*;
+use crate::layers::BlendMode;
+use crate::plot::*;
+use crate::util::*;
+use image::RgbaImage;
+use rand::Rng;
+use std::fmt;
+
+/// Most maps an IFS may have.
+pub const MAX_MAPS: usize = 64;
+
+/// Parameters of the IFS fractal.
+pub const IFS_PARAMS: [Param; 2] = [
+    Param {
+        name: "ifs",
+        description: "Preset system, e.g. fern, sierpinski-carpet or heighway-dragon",
+        default: "random",
+    },
+    Param {
+        name: "maps",
+        description: "Affine maps as a b c d e f [weight] separated by ;",
+        default: "",
+    },
+];
+
+/// The affine map (x, y) -> (a x + b y + e, c x + d y + f), picked with
+/// some weight relative to the other maps of its system.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AffineMap {
+    pub a: f64,
+    pub b: f64,
+    pub c: f64,
+    pub d: f64,
+    pub e: f64,
+    pub f: f64,
+    pub weight: f64,
+}
+
+impl AffineMap {
+    /// A map from its six coefficients and weight.
+    pub const fn new(a: f64, b: f64, c: f64, d: f64, e: f64, f: f64, weight: f64) -> AffineMap {
+        AffineMap {
+            a,
+            b,
+            c,
+            d,
+            e,
+            f,
+            weight,
+        }
+    }
+
+    /// Apply the map to a point.
+    pub fn apply(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            self.a * x + self.b * y + self.e,
+            self.c * x + self.d * y + self.f,
+        )
+    }
+
+    /// Weight of a map given without one: the area it keeps, so every part
+    /// of the attractor gets about as many points, with a floor so maps
+    /// squashing everything onto a line still get some.
+    fn area_weight(&self) -> f64 {
+        (self.a * self.d - self.b * self.c).abs().max(0.01)
+    }
+}
+
+/// An iterated function system and the extent of its attractor.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Ifs {
+    /// Preset name, or "custom"
+    pub name: String,
+    pub maps: Vec<AffineMap>,
+    pub bounds: Bounds,
+}
+
+/// Barnsley's fern.
+const FERN: [AffineMap; 4] = [
+    AffineMap::new(0.0, 0.0, 0.0, 0.16, 0.0, 0.0, 0.01),
+    AffineMap::new(0.85, 0.04, -0.04, 0.85, 0.0, 1.6, 0.85),
+    AffineMap::new(0.2, -0.26, 0.23, 0.22, 0.0, 1.6, 0.07),
+    AffineMap::new(-0.15, 0.28, 0.26, 0.24, 0.0, 0.44, 0.07),
+];
+/// A mutant fern like the Cyclosorus genus.
+const CYCLOSORUS: [AffineMap; 4] = [
+    AffineMap::new(0.0, 0.0, 0.0, 0.25, 0.0, -0.4, 0.02),
+    AffineMap::new(0.95, 0.005, -0.005, 0.93, -0.002, 0.5, 0.84),
+    AffineMap::new(0.035, -0.2, 0.16, 0.04, -0.09, 0.02, 0.07),
+    AffineMap::new(-0.04, 0.2, 0.16, 0.04, 0.083, 0.12, 0.07),
+];
+/// A mutant fern like the Culcita genus.
+const CULCITA: [AffineMap; 4] = [
+    AffineMap::new(0.0, 0.0, 0.0, 0.25, 0.0, -0.14, 0.02),
+    AffineMap::new(0.85, 0.02, -0.02, 0.83, 0.0, 1.0, 0.84),
+    AffineMap::new(0.09, -0.28, 0.3, 0.11, 0.0, 0.6, 0.07),
+    AffineMap::new(-0.09, 0.28, 0.3, 0.09, 0.0, 0.7, 0.07),
+];
+/// A thin fern with short, straight leaves.
+const FISHBONE: [AffineMap; 4] = [
+    AffineMap::new(0.0, 0.0, 0.0, 0.25, 0.0, -0.4, 0.02),
+    AffineMap::new(0.95, 0.002, -0.002, 0.93, -0.002, 0.5, 0.84),
+    AffineMap::new(0.035, -0.11, 0.27, 0.01, -0.05, 0.005, 0.07),
+    AffineMap::new(-0.04, 0.11, 0.27, 0.01, 0.047, 0.06, 0.07),
+];
+const SIERPINSKI_TRIANGLE: [AffineMap; 3] = [
+    AffineMap::new(0.5, 0.0, 0.0, 0.5, 0.0, 0.0, 1.0),
+    AffineMap::new(0.5, 0.0, 0.0, 0.5, 0.5, 0.0, 1.0),
+    AffineMap::new(0.5, 0.0, 0.0, 0.5, 0.25, 0.433, 1.0),
+];
+const THIRD: f64 = 1.0 / 3.0;
+const TWO_THIRDS: f64 = 2.0 / 3.0;
+const SIERPINSKI_CARPET: [AffineMap; 8] = [
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, 0.0, 0.0, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, THIRD, 0.0, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, TWO_THIRDS, 0.0, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, 0.0, THIRD, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, TWO_THIRDS, THIRD, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, 0.0, TWO_THIRDS, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, THIRD, TWO_THIRDS, 1.0),
+    AffineMap::new(THIRD, 0.0, 0.0, THIRD, TWO_THIRDS, TWO_THIRDS, 1.0),
+];
+const HEIGHWAY_DRAGON: [AffineMap; 2] = [
+    AffineMap::new(0.5, -0.5, 0.5, 0.5, 0.0, 0.0, 1.0),
+    AffineMap::new(-0.5, -0.5, 0.5, -0.5, 1.0, 0.0, 1.0),
+];
+const LEVY_C: [AffineMap; 2] = [
+    AffineMap::new(0.5, -0.5, 0.5, 0.5, 0.0, 0.0, 1.0),
+    AffineMap::new(0.5, 0.5, -0.5, 0.5, 0.5, 0.5, 1.0),
+];
+const MAPLE_LEAF: [AffineMap; 4] = [
+    AffineMap::new(0.14, 0.01, 0.0, 0.51, -0.08, -1.31, 0.1),
+    AffineMap::new(0.43, 0.52, -0.45, 0.5, 1.49, -0.75, 0.35),
+    AffineMap::new(0.45, -0.49, 0.47, 0.47, -1.62, -0.74, 0.35),
+    AffineMap::new(0.49, 0.0, 0.0, 0.51, 0.02, 1.62, 0.2),
+];
+
+/// Every preset by the name the `ifs` parameter takes.
+pub const IFS_PRESETS: [(&str, &[AffineMap]); 9] = [
+    ("fern", &FERN),
+    ("cyclosorus-fern", &CYCLOSORUS),
+    ("culcita-fern", &CULCITA),
+    ("fishbone-fern", &FISHBONE),
+    ("sierpinski-triangle", &SIERPINSKI_TRIANGLE),
+    ("sierpinski-carpet", &SIERPINSKI_CARPET),
+    ("heighway-dragon", &HEIGHWAY_DRAGON),
+    ("levy-c", &LEVY_C),
+    ("maple-leaf", &MAPLE_LEAF),
+];
+
+impl Ifs {
+    /// A system from its maps, measuring its attractor. Fails for systems
+    /// whose points fly off rather than settle onto an attractor.
+    pub fn new(name: &str, maps: Vec<AffineMap>) -> Result<Ifs, String> {
+        if maps.is_empty() || maps.len() > MAX_MAPS {
+            return Err(format!(
+                "An IFS needs from 1 to {} maps, not {}",
+                MAX_MAPS,
+                maps.len()
+            ));
+        }
+        let total: f64 = maps.iter().map(|map| map.weight).sum();
+        let valid = maps
+            .iter()
+            .all(|map| map.weight >= 0.0 && map.weight.is_finite());
+        if !valid || total <= 0.0 {
+            return Err("IFS map weights must be positive numbers".to_string());
+        }
+        let mut ifs = Ifs {
+            name: name.to_string(),
+            maps,
+            bounds: Bounds {
+                min_x: 0.0,
+                min_y: 0.0,
+                max_x: 0.0,
+                max_y: 0.0,
+            },
+        };
+        ifs.bounds = ifs.measure_bounds()?;
+        Ok(ifs)
+    }
+
+    /// One of the presets by name.
+    pub fn preset(name: &str) -> Result<Ifs, String> {
+        let (preset, maps) = chaos::find_preset("IFS", &IFS_PRESETS, name)?;
+        Ifs::new(preset, maps.to_vec())
+    }
+
+    /// Read maps written one per line or separated by `;`, each as the
+    /// six coefficients a b c d e f and an optional weight, separated by
+    /// spaces or commas. Without weights, maps are weighted by area.
+    /// Anything after a `#` is a comment, so an IFS file can explain itself.
+    pub fn parse(text: &str) -> Result<Ifs, String> {
+        let mut maps = Vec::new();
+        let lines = text
+            .lines()
+            .map(|line| line.split('#').next().unwrap_or(""));
+        for map in lines.flat_map(|line| line.split(';')) {
+            let numbers = map
+                .split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|number| !number.is_empty())
+                .map(|number| number.parse::<f64>().ok().filter(|n| n.is_finite()))
+                .collect::<Option<Vec<f64>>>();
+            let map = match numbers.as_deref() {
+                Some([]) => continue,
+                Some(&[a, b, c, d, e, f]) => {
+                    let map = AffineMap::new(a, b, c, d, e, f, 0.0);
+                    AffineMap {
+                        weight: map.area_weight(),
+                        ..map
+                    }
+                }
+                Some(&[a, b, c, d, e, f, weight]) => AffineMap::new(a, b, c, d, e, f, weight),
+                _ => {
+                    return Err(format!(
+                        "Invalid IFS map '{}', expected a b c d e f [weight]",
+                        map.trim()
+                    ))
+                }
+            };
+            maps.push(map);
+        }
+        Ifs::new("custom", maps)
+    }
+
+    /// Play the chaos game from the origin: call `plot` with every point
+    /// after the first few and the index of the map that made it.
+    pub fn play<R: Rng, F: FnMut((f64, f64), usize)>(&self, rng: &mut R, points: u64, mut plot: F) {
+        let weights: Vec<f64> = self.maps.iter().map(|map| map.weight).collect();
+        chaos::play(
+            rng,
+            &weights,
+            points,
+            |_| (0.0, 0.0),
+            |_, point, index| Some(self.maps[index].apply(point)),
+            |_, point, index| plot(point, index),
+        );
+    }
+
+    /// Find the extent of the attractor from a sample of its points.
+    fn measure_bounds(&self) -> Result<Bounds, String> {
+        chaos::measure_bounds(0.0, |points, plot| {
+            self.play(&mut rand::thread_rng(), points, |point, _| plot(point));
+        })
+        .ok_or_else(|| {
+            "The IFS maps do not settle onto an attractor, try smaller coefficients".to_string()
+        })
+    }
+}
+
+/// Maps written the way `Ifs::parse` reads them, with their weights.
+impl fmt::Display for Ifs {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let maps: Vec<String> = self
+            .maps
+            .iter()
+            .map(|m| {
+                format!(
+                    "{} {} {} {} {} {} {}",
+                    m.a, m.b, m.c, m.d, m.e, m.f, m.weight
+                )
+            })
+            .collect();
+        write!(f, "{}", maps.join("; "))
+    }
+}
+
+/// Pick one of the presets at random.
+pub fn random_ifs() -> Ifs {
+    Ifs::preset(chaos::random_preset(&IFS_PRESETS)).expect("presets settle onto their attractors")
+}
+
+/// Any iterated function system, drawn by `ifs_fractal`.
+pub struct IfsFractal;
+
+impl Fractal for IfsFractal {
+    fn name(&self) -> &'static str {
+        "ifs"
+    }
+
+    /// Fades in with density like Barnsley's fern, so any color works.
+    fn colors(&self) -> &'static [Color] {
+        &ALL_COLORS
+    }
+
+    fn supports_color(&self, _color: Color) -> bool {
+        true
+    }
+
+    fn blend_mode(&self) -> BlendMode {
+        BlendMode::Normal
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = common_params();
+        params.extend_from_slice(&DENSITY_PARAMS);
+        params.extend_from_slice(&IFS_PARAMS);
+        params
+    }
+
+    fn body_param(&self) -> Option<&'static str> {
+        Some("maps")
+    }
+
+    fn randomize(&self, scheme: &mut Scheme) {
+        scheme.ifs = Some(random_ifs());
+    }
+
+    fn metadata(&self, scheme: &Scheme) -> Vec<(&'static str, String)> {
+        match &scheme.ifs {
+            Some(ifs) => vec![("IFS", ifs.name.clone()), ("IFS maps", ifs.to_string())],
+            None => Vec::new(),
+        }
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let ifs = scheme.ifs.clone().unwrap_or_else(random_ifs);
+        ifs_fractal(&ifs, imgx, imgy, scheme)
+    }
+}
+
+/// Draw an IFS by the density of its points, scaled to fit its attractor
+/// into the image with the same scale across and up so it keeps its shape.
+pub fn ifs_fractal(ifs: &Ifs, imgx: u32, imgy: u32, scheme: &Scheme) -> RgbaImage {
+    density_fractal(imgx, imgy, scheme, |points, histogram| {
+        let mapping = PlotMapping::fit(&ifs.bounds, histogram.width, histogram.height, MARGIN);
+        // Points outside the bounds, which were measured from a sample of
+        // the points, may fall in the margin or be clipped.
+        ifs.play(&mut rand::thread_rng(), points, |point, _| {
+            histogram.plot(&mapping, point);
+        });
+    })
+}
+
+#[cfg(test)]
+mod ifs_tests {
+    use super::*;
+
+    #[test]
+    fn test_presets_settle_in_their_bounds() {
+        for (name, _) in &IFS_PRESETS {
+            let ifs = Ifs::preset(name).unwrap();
+            let (width, height) = ifs.bounds.size();
+            assert!(width > 0.1 && height > 0.1, "{}", name);
+        }
+        let triangle = Ifs::preset("sierpinski-triangle").unwrap().bounds;
+        assert!(triangle.min_x >= 0.0 && triangle.max_x <= 1.0);
+        assert!(triangle.min_y >= 0.0 && triangle.max_y <= 0.433 * 2.0);
+    }
+
+    #[test]
+    fn test_parse_maps() {
+        let ifs = Ifs::parse(
+            "# Sierpinski triangle\n0.5 0 0 0.5 0 0 1\n0.5,0,0,0.5,0.5,0,1; 0.5 0 0 0.5 0.25 0.5",
+        )
+        .unwrap();
+        assert_eq!(3, ifs.maps.len());
+        assert_eq!(0.25, ifs.maps[2].weight);
+        assert_eq!(ifs.maps, Ifs::parse(&ifs.to_string()).unwrap().maps);
+        assert!(Ifs::parse("").is_err());
+        assert!(Ifs::parse("0.5 0 0 0.5 0").is_err());
+        assert!(Ifs::parse("0.5 0 0 0.5 0 0 -1").is_err());
+        assert!(Ifs::parse("2 0 0 2 1 1").is_err());
+    }
+
+    #[test]
+    fn test_points_stay_in_the_image() {
+        let scheme = Scheme {
+            points: 10_000,
+            ..Default::default()
+        };
+        for &(imgx, imgy) in &[(1, 1), (7, 3), (40, 90)] {
+            let image = ifs_fractal(&random_ifs(), imgx, imgy, &scheme);
+            assert_eq!((imgx, imgy), image.dimensions());
+        }
+    }
+}
@@ -5,56 +5,276 @@
 //base code credited to: https://crates.io/crates/image
 //resource on julia_set fractals: https://en.wikipedia.org/wiki/Julia_set#Pseudocode_for_normal_Julia_sets
 
+use crate::escape::Escape;
+use crate::fractal::*;
 use crate::julias::*;
+use crate::precision::*;
+use crate::sampling::supersample;
 
-use crate::util::Color::*;
 use crate::util::*;
-use image::Rgba;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use num::{Complex, Float};
 use rand::Rng;
 
-///Julia_fractal is a middle man function for both the julia sets fractal and the multi julia sets fractal. This fuction handles
-///the generation of the intial background image and then cycles through each pixel in the image. Sending the pixel to the appropriate
-///function in julias.rs based on the fractal type to run through the correct formula to alter the pixel and draw the fractal.
+/// Region showing a whole Julia or multi-Julia set.
+pub const JULIA_VIEWPORT: Viewport = Viewport::new(0.0, 0.0, 3.0);
+/// Region showing a whole Multibrot set, including the Mandelbrot set at n = 2.
+pub const MULTIBROT_VIEWPORT: Viewport = Viewport::new(-0.2, 0.0, 3.6);
 
-pub fn julia_fractal(julia_type: &str, imgy: u32, imgx: u32, filename: &str, scheme: &Scheme) {
-    let mut imgbuf = image::ImageBuffer::new(imgx, imgy); // Create a new ImgBuf and apply our dimensions to it.
-    let scaleset = ((3.0 / imgx as f32), (3.0 / imgy as f32));
+/// The quadratic Julia sets.
+pub struct Julia;
 
-    apply_background(&mut imgbuf, &scheme); //set the intial background of the image based on the users choice
+/// The multi-Julia sets z^n + c with c fixed.
+pub struct MultiJulia;
 
-    let mut rng = rand::thread_rng();
-    let randjulia = match julia_type {
-        //determine random value that will choose which julia set will be generated
-        "julia" => rng.gen_range(1, 11),
-        "multi-julia" => rng.gen_range(2, 8),
-        _ => rng.gen_range(1, 11),
+/// The Multibrot sets z^n + c with c the pixel.
+pub struct Multibrot;
+
+impl Fractal for Julia {
+    fn name(&self) -> &'static str {
+        "julia"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = escape_time_params();
+        params.extend_from_slice(&JULIA_PARAMS);
+        params
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(JULIA_VIEWPORT)
+    }
+
+    fn randomize(&self, scheme: &mut Scheme) {
+        //the constant c is part of the scheme, so a random preset ends up in its log
+        let preset = rand::thread_rng().gen_range(0, JULIA_PRESETS.len());
+        scheme.julia_c = Some(JULIA_PRESETS[preset].1);
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let c = scheme.julia_c.unwrap_or(DEFAULT_JULIA);
+        julia_fractal(&QuadraticFormula(c), imgy, imgx, scheme)
+    }
+}
+
+impl Fractal for MultiJulia {
+    fn name(&self) -> &'static str {
+        "multi-julia"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = escape_time_params();
+        params.extend_from_slice(&JULIA_PARAMS);
+        params.extend_from_slice(&EXPONENT_PARAMS);
+        params
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(JULIA_VIEWPORT)
+    }
+
+    fn randomize(&self, scheme: &mut Scheme) {
+        let preset = rand::thread_rng().gen_range(0, MULTI_JULIA_PRESETS.len());
+        let (power, c) = MULTI_JULIA_PRESETS[preset];
+        scheme.julia_c = Some(Complex::new(c, 0.0));
+        scheme.exponent = Complex::new(f64::from(power), 0.0);
+    }
+
+    fn metadata(&self, scheme: &Scheme) -> Vec<(&'static str, String)> {
+        exponent_metadata(scheme)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        let formula = MultiJuliaFormula {
+            c: scheme
+                .julia_c
+                .unwrap_or_else(|| Complex::new(MULTI_JULIA_PRESETS[0].1, 0.0)),
+            exponent: Exponent::new(scheme.exponent),
+        };
+        julia_fractal(&formula, imgy, imgx, scheme)
+    }
+}
+
+impl Fractal for Multibrot {
+    fn name(&self) -> &'static str {
+        "multibrot"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = escape_time_params();
+        params.extend_from_slice(&EXPONENT_PARAMS);
+        params
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(MULTIBROT_VIEWPORT)
+    }
+
+    fn randomize(&self, scheme: &mut Scheme) {
+        let power = rand::thread_rng().gen_range(2, 8);
+        scheme.exponent = Complex::new(f64::from(power), 0.0);
+    }
+
+    fn metadata(&self, scheme: &Scheme) -> Vec<(&'static str, String)> {
+        exponent_metadata(scheme)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        julia_fractal(
+            &MultibrotFormula(Exponent::new(scheme.exponent)),
+            imgy,
+            imgx,
+            scheme,
+        )
+    }
+}
+
+/// The exponent of a multi-julia or multibrot scheme, as it is written.
+fn exponent_metadata(scheme: &Scheme) -> Vec<(&'static str, String)> {
+    let n = scheme.exponent;
+    let exponent = if n.im == 0.0 {
+        n.re.to_string()
+    } else {
+        format!("{}{:+}i", n.re, n.im)
     };
+    vec![("Exponent", exponent)]
+}
 
-    //cycle through every pixel, send to fractal formula function pixel_setter, set the pixel based on result of that function
-    for x in 0..imgx {
-        for y in 0..imgy {
-            let complex_pos = ((y as f32 * scaleset.0 - 1.5), (x as f32 * scaleset.1 - 1.5)); //determines position in frame
-
-            let result = match julia_type {
-                "julia" => pixel_setter(complex_pos, 0, randjulia), //run pixel through fractal formula in Julias.rs
-                "multi-julia" => pixel_set_multi(complex_pos, 0, randjulia),
-                _ => pixel_setter(complex_pos, 0, randjulia), //default is normal julia set
-            };
-
-            let pixel = imgbuf.get_pixel_mut(x, y); //pull out pixel data
-            let Rgba(data) = *pixel; //set pixel data onto the rgb array
-
-            match scheme.color {
-                //apply the pixel shade on the result from pixel setter
-                Red => *pixel = Rgba([result as u8, data[1], data[2], 255]), //apply it to the channel the user chose
-                Green => *pixel = Rgba([data[0], result as u8, data[2], 255]),
-                Blue => *pixel = Rgba([data[0], data[1], result as u8, 255]),
-                White => *pixel = Rgba([result as u8, result as u8, result as u8, 255]),
-                _ => panic!("Unsupported color"),
-            }
-        }
+/// The formula of a julia type, run on one point in whichever number type
+/// the view needs.
+pub trait Formula: Sync {
+    /// Run a point through the formula in a float type.
+    fn escape<T: Float>(&self, complex_pos: (T, T), max: u64, bailout: T) -> Escape;
+
+    /// Run a point through the formula in fixed point, which is only done
+    /// when `fixed_power` is some.
+    fn escape_fixed(&self, pos: FixedComplex, max: u64, bailout: f64) -> Escape;
+
+    /// Power z is raised to, if it is whole and so can be raised to in
+    /// fixed point.
+    fn fixed_power(&self) -> Option<u32> {
+        Some(2)
+    }
+
+    /// Region drawn when the scheme has no viewport.
+    fn default_viewport(&self) -> Viewport {
+        JULIA_VIEWPORT
+    }
+}
+
+/// The quadratic julia set z^2 + c of a constant c.
+struct QuadraticFormula(Complex<f64>);
+
+/// The multi-julia set z^n + c of a constant c and exponent n.
+struct MultiJuliaFormula {
+    c: Complex<f64>,
+    exponent: Exponent,
+}
+
+/// The multibrot set z^n + c of an exponent n, with c the point itself.
+struct MultibrotFormula(Exponent);
+
+/// Whole exponents of at least 1, the only ones raised to in fixed point.
+fn whole_power(exponent: Exponent) -> Option<u32> {
+    match exponent {
+        Exponent::Integer(n) if n > 0 => Some(n as u32),
+        _ => None,
+    }
+}
+
+impl Formula for QuadraticFormula {
+    fn escape<T: Float>(&self, complex_pos: (T, T), max: u64, bailout: T) -> Escape {
+        let c = Complex::new(real::<T>(self.0.re), real::<T>(self.0.im));
+        pixel_setter(complex_pos, 0, c, max, bailout) //run pixel through fractal formula in Julias.rs
+    }
+
+    fn escape_fixed(&self, pos: FixedComplex, max: u64, bailout: f64) -> Escape {
+        let c = FixedComplex::new(self.0.re, self.0.im, pos.bits);
+        pixel_setter_fixed(pos, &c, max, bailout)
+    }
+}
+
+impl Formula for MultiJuliaFormula {
+    fn escape<T: Float>(&self, complex_pos: (T, T), max: u64, bailout: T) -> Escape {
+        let c = Complex::new(real::<T>(self.c.re), real::<T>(self.c.im));
+        pixel_set_multi(complex_pos, 0, c, self.exponent, max, bailout)
+    }
+
+    fn escape_fixed(&self, pos: FixedComplex, max: u64, bailout: f64) -> Escape {
+        let c = FixedComplex::new(self.c.re, self.c.im, pos.bits);
+        let power = self.fixed_power().unwrap_or(2);
+        pixel_set_multi_fixed(pos, &c, power, max, bailout)
     }
 
-    // Save the image
-    imgbuf.save(filename).unwrap();
+    fn fixed_power(&self) -> Option<u32> {
+        whole_power(self.exponent)
+    }
+}
+
+impl Formula for MultibrotFormula {
+    fn escape<T: Float>(&self, complex_pos: (T, T), max: u64, bailout: T) -> Escape {
+        let c = Complex::new(complex_pos.0, complex_pos.1);
+        pixel_set_multi(complex_pos, 0, c, self.0, max, bailout)
+    }
+
+    fn escape_fixed(&self, pos: FixedComplex, max: u64, bailout: f64) -> Escape {
+        let power = self.fixed_power().unwrap_or(2);
+        pixel_set_multi_fixed(pos.clone(), &pos, power, max, bailout)
+    }
+
+    fn fixed_power(&self) -> Option<u32> {
+        whole_power(self.0)
+    }
+
+    fn default_viewport(&self) -> Viewport {
+        MULTIBROT_VIEWPORT
+    }
+}
+
+///Julia_fractal is a middle man function for the julia sets, multi julia sets and multibrot sets fractals. This fuction handles
+///the generation of the intial background image and then cycles through each pixel in the image. Sending the pixel to the
+///formula of the fractal type, `formula`, to alter the pixel and draw the fractal.
+pub fn julia_fractal<F: Formula>(formula: &F, imgy: u32, imgx: u32, scheme: &Scheme) -> RgbaImage {
+    let mut imgbuf = RgbaImage::new(imgx, imgy); // Create a new transparent layer and apply our dimensions to it.
+    let viewport = scheme
+        .viewport
+        .unwrap_or_else(|| formula.default_viewport());
+    let palette = scheme_palette(scheme);
+
+    //pick the number type from how far the view is zoomed in
+    let arithmetic = choose_arithmetic(scheme.precision, &viewport, imgx, imgy);
+    let max = u64::from(scheme.max_iterations);
+    let center = match (arithmetic, formula.fixed_power()) {
+        (Arithmetic::Fixed(bits), Some(_)) | (Arithmetic::Perturbation(bits), Some(_)) => {
+            Some(fixed_center(scheme, &viewport, bits))
+        }
+        _ => None,
+    };
+
+    //cycle through every pixel on the worker threads, send each sample point to fractal formula,
+    //color the sample based on result of that function
+    supersample(&mut imgbuf, scheme.threads, scheme.sampling, |px, py| {
+        let escape = match (arithmetic, &center) {
+            //there is no perturbation renderer for these sets, so deep views iterate every point in fixed point,
+            //unless the exponent isn't whole and they have to make do with f64
+            (_, Some(center)) => {
+                let pos = center.offset_by(&viewport, px, py, imgx, imgy);
+                formula.escape_fixed(pos, max, scheme.bailout)
+            }
+            (Arithmetic::F32, None) => {
+                let pos = float_point::<f32>(&viewport, px, py, imgx, imgy); //determines position in frame
+                formula.escape((pos.re, pos.im), max, scheme.bailout as f32)
+            }
+            (_, None) => {
+                let pos = float_point::<f64>(&viewport, px, py, imgx, imgy);
+                formula.escape((pos.re, pos.im), max, scheme.bailout)
+            }
+        };
+        //look the iteration count up in the palette
+        let color = palette.escape_color(&escape, scheme.max_iterations, scheme.smooth);
+        escape_pixel(&escape, color, scheme)
+    });
+
+    imgbuf
 }
@@ -4,87 +4,334 @@
 //resource on julia_set fractals: https://en.wikipedia.org/wiki/Julia_set#Pseudocode_for_normal_Julia_sets
 //resource on multi-julia set / multibrot set: https://en.wikipedia.org/wiki/Multibrot_set
 
+use crate::escape::Escape;
+use crate::fractal::Param;
+use crate::precision::*;
+use crate::util::unknown_name;
+use crate::viewport::parse_point;
+use num::{Complex, Float};
+
+// The Julia constants drawn by default, from the examples on Wikipedia
+// under "Quadratic polynomials".
+pub const CHAIN: Complex<f64> = Complex { re: -0.8, im: 0.0 };
+pub const CAULIFLOWER: Complex<f64> = Complex { re: 0.285, im: 0.0 };
+pub const SPIRAL: Complex<f64> = Complex { re: -0.4, im: 0.6 };
+pub const DUST: Complex<f64> = Complex {
+    re: 0.45,
+    im: 0.1428,
+};
+pub const SEAHORSE: Complex<f64> = Complex {
+    re: 0.285,
+    im: 0.01,
+};
+pub const DRAGON: Complex<f64> = Complex {
+    re: -0.70176,
+    im: -0.3842,
+};
+pub const GALAXY: Complex<f64> = Complex {
+    re: -0.835,
+    im: -0.2321,
+};
+pub const LACE: Complex<f64> = Complex {
+    re: -0.8,
+    im: 0.156,
+};
+pub const FILIGREE: Complex<f64> = Complex {
+    re: -0.7269,
+    im: 0.1889,
+};
+pub const PINWHEEL: Complex<f64> = Complex { re: 0.0, im: -0.8 };
+
+/// The Julia constants above by the names the `c` parameter takes.
+pub const JULIA_PRESETS: [(&str, Complex<f64>); 10] = [
+    ("chain", CHAIN),
+    ("cauliflower", CAULIFLOWER),
+    ("spiral", SPIRAL),
+    ("dust", DUST),
+    ("seahorse", SEAHORSE),
+    ("dragon", DRAGON),
+    ("galaxy", GALAXY),
+    ("lace", LACE),
+    ("filigree", FILIGREE),
+    ("pinwheel", PINWHEEL),
+];
+
+/// Constant of the julia fractal when a scheme has none.
+pub const DEFAULT_JULIA: Complex<f64> = SPIRAL;
+
+/// Power n and constant c of the multi-julia sets z^n + c drawn by default.
+/// src of what powers to use: (https://en.wikipedia.org/wiki/Julia_set) under example julia-sets
+pub const MULTI_JULIA_PRESETS: [(u32, f64); 6] = [
+    (2, 0.279),
+    (3, 0.400),
+    (4, 0.484),
+    (5, 0.544),
+    (6, 0.590),
+    (7, 0.626),
+];
+
+/// Parameter of the multi-Julia and Multibrot sets z^n + c.
+pub const EXPONENT_PARAMS: [Param; 1] = [Param {
+    name: "exponent",
+    description: "Exponent n of z^n + c, real or complex, e.g. 3, 2.5 or 2+0.5i",
+    default: "random whole number",
+}];
+
+/// Parameter of the fractals drawn as a Julia set.
+pub const JULIA_PARAMS: [Param; 1] = [Param {
+    name: "c",
+    description: "Constant c of the Julia set as <re>,<im>, or a preset such as lace",
+    default: "random preset",
+}];
+
+/// Look up a Julia constant by preset name or read it as <re>,<im>.
+pub fn parse_julia_constant(text: &str) -> Result<Complex<f64>, String> {
+    let name = text.trim().to_lowercase();
+    if let Some((_, c)) = JULIA_PRESETS.iter().find(|(preset, _)| *preset == name) {
+        return Ok(*c);
+    }
+    match parse_point(text) {
+        Ok((re, im)) => Ok(Complex::new(re, im)),
+        Err(_) => {
+            let names: Vec<&str> = JULIA_PRESETS.iter().map(|(name, _)| *name).collect();
+            Err(unknown_name("Julia constant", text, &names) + " or <re>,<im>")
+        }
+    }
+}
+
 ///Julia Set Fractal - "the Julia set consists of values such that an arbitrarily
 /// small perturbation can cause drastic changes in the sequence of iterated function values.
 /// Thus the behavior of the Julia set is "chaotic"." (src: https://en.wikipedia.org/wiki/Julia_set)
-
+///
 /// Each pixel in the user specified dimensions runs through
 /// the loop that calculates the Julia set formula of (f(z) = z^2 + c), and will continue to
 /// do so until the value is outside the appropriate range where it can still generate
-/// correctly. The int value that is broken out of the function is returned
+/// correctly, at most `max_iterations` times or until |z| passes the `bailout` radius.
+/// The iteration count (and its smooth counterpart) broken out of the function is returned
 /// and used for the color shade of the currently specfied pixel.
-pub fn pixel_setter((complex_x, complex_y): (f32, f32), mut iteration: u64, randjulia: u64) -> u64 {
-    //determine which julia_set fractal will be generated (On the wiki page source under "Quadraic polynomials")
-    let complex_num = match randjulia {
-        //every stage of the julia set is listed as a possible option
-        1 => num::Complex::new(-0.8, 0.0),
-        2 => num::Complex::new(0.285, 0.0),
-        3 => num::Complex::new(-0.4, 0.6),
-        4 => num::Complex::new(0.45, 0.1428),
-        5 => num::Complex::new(0.285, 0.01),
-        6 => num::Complex::new(-0.70176, -0.3842),
-        7 => num::Complex::new(-0.835, -0.2321),
-        8 => num::Complex::new(-0.8, 0.156),
-        9 => num::Complex::new(-0.7269, 0.1889),
-        10 => num::Complex::new(0.0, -0.8),
-        _ => num::Complex::new(-0.4, 0.6),
-    };
-
-    let mut value = num::Complex::new(complex_x, complex_y);
-
-    while iteration < 255 && value.norm() <= 2.0 {
+pub fn pixel_setter<T: Float>(
+    (complex_x, complex_y): (T, T),
+    mut iteration: u64,
+    complex_num: Complex<T>,
+    max_iterations: u64,
+    bailout: T,
+) -> Escape {
+    let mut value = Complex::new(complex_x, complex_y);
+
+    while iteration < max_iterations && value.norm() <= bailout {
         //the julia fractal formula (f(z) = z^2 + c)
         value = value * value + complex_num;
         iteration += 1;
     }
 
-    iteration
+    finish(value, iteration, max_iterations, bailout, 2.0)
+}
+
+/// `pixel_setter` in fixed point, for views too deep for `f64`.
+pub fn pixel_setter_fixed(
+    mut value: FixedComplex,
+    complex_num: &FixedComplex,
+    max_iterations: u64,
+    bailout: f64,
+) -> Escape {
+    let bailout_sqr = to_fixed(bailout * bailout, value.bits);
+
+    let mut iteration = 0;
+    while iteration < max_iterations && value.norm_sqr() <= bailout_sqr {
+        value = value.square().add(complex_num);
+        iteration += 1;
+    }
+
+    finish_fixed(&value, iteration, max_iterations, bailout, 2.0)
+}
+
+/// Largest whole exponent raised to by repeated multiplication.
+const MAX_INTEGER_EXPONENT: f64 = 64.0;
+
+/// Exponent n of z^n + c. Whole numbers are raised to by multiplying,
+/// which is faster and more accurate than going through polar form.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Exponent {
+    Integer(i32),
+    Real(f64),
+    Complex(Complex<f64>),
+}
+
+impl Exponent {
+    /// The fastest way of raising to some exponent.
+    pub fn new(n: Complex<f64>) -> Exponent {
+        if n.im != 0.0 {
+            Exponent::Complex(n)
+        } else if n.re.fract() == 0.0 && n.re.abs() <= MAX_INTEGER_EXPONENT {
+            Exponent::Integer(n.re as i32)
+        } else {
+            Exponent::Real(n.re)
+        }
+    }
+
+    /// z^n, on the principal branch for non-integer n.
+    pub fn raise<T: Float>(self, z: Complex<T>) -> Complex<T> {
+        match self {
+            Exponent::Integer(n) => integer_power(z, n),
+            Exponent::Real(n) => z.powf(real(n)),
+            Exponent::Complex(n) => z.powc(Complex::new(real(n.re), real(n.im))),
+        }
+    }
+
+    /// Power |z| grows by each step far from the origin, for smooth coloring.
+    pub fn growth(self) -> f64 {
+        match self {
+            Exponent::Integer(n) => f64::from(n),
+            Exponent::Real(n) => n,
+            Exponent::Complex(n) => n.re,
+        }
+    }
+}
+
+/// z^n by repeated squaring, taking the reciprocal for negative n.
+pub fn integer_power<T: Float>(z: Complex<T>, n: i32) -> Complex<T> {
+    let mut base = z;
+    let mut result = Complex::new(T::one(), T::zero());
+    let mut exponent = n.unsigned_abs();
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            result = result * base;
+        }
+        base = base * base;
+        exponent >>= 1;
+    }
+    if n < 0 {
+        result.inv()
+    } else {
+        result
+    }
 }
 
 ///Multi-Julia set or Multibrot set Fractal-
 /// "A multibrot set is the set of values in the complex plane whose absolute value remains below
 /// some finite value throughout iterations by a member of the general monic univariate polynomial
 /// family of recursions." (src: https://en.wikipedia.org/wiki/Multibrot_set)
-
+///
 /// Each pixel in the user specified dimensions runs through
-/// the loop that calculates the multi-Julia or Multibro set formula of (f(z) = z^n + c), and will continue to
+/// the loop that calculates the multi-Julia or Multibrot set formula of (f(z) = z^n + c), and will continue to
 /// do so until the value is outside the appropriate range where it can still generate
-/// correctly. The int value that is broken out of the function is returned
+/// correctly, at most `max_iterations` times or until |z| passes the `bailout` radius.
+/// A multi-Julia set starts z at the pixel with c fixed, a Multibrot set starts z at c,
+/// the pixel, so negative exponents never divide by zero.
+/// The iteration count (and its smooth counterpart) broken out of the function is returned
 /// and used for the color shade of the currently specfied pixel.
-
-pub fn pixel_set_multi(
-    (complex_x, complex_y): (f32, f32),
+pub fn pixel_set_multi<T: Float>(
+    (complex_x, complex_y): (T, T),
     mut iteration: u64,
-    randjulia: u64,
-) -> u64 {
-    //determine what complex number to use based on
-    let complex_num = match randjulia {
-        //every stage of the multi-julia set is listed as a possible option src: https://en.wikipedia.org/wiki/Julia_set
-        2 => 0.279,
-        3 => 0.400,
-        4 => 0.484,
-        5 => 0.544,
-        6 => 0.590,
-        7 => 0.626,
-        _ => 0.279,
-    };
-
-    let mut value = num::Complex::new(complex_x, complex_y);
-
-    while iteration < 255 && value.norm() <= 2.0 {
+    complex_num: Complex<T>,
+    exponent: Exponent,
+    max_iterations: u64,
+    bailout: T,
+) -> Escape {
+    let mut value = Complex::new(complex_x, complex_y);
+
+    while iteration < max_iterations && value.norm() <= bailout {
         //the multi-julia fractal formula (f(z) = z^n + c),
-        value = match randjulia {
-            2 => (value.powf(2.0)) + complex_num, // src of what powers to use: (https://en.wikipedia.org/wiki/Julia_set) under example julia-sets
-            3 => (value.powf(3.0)) + complex_num,
-            4 => (value.powf(4.0)) + complex_num,
-            5 => (value.powf(5.0)) + complex_num,
-            6 => (value.powf(6.0)) + complex_num,
-            7 => (value.powf(7.0)) + complex_num,
-            _ => (value.powf(2.0)) + complex_num,
-        };
+        value = exponent.raise(value) + complex_num;
+        iteration += 1;
+    }
+
+    finish(value, iteration, max_iterations, bailout, exponent.growth())
+}
+
+/// `pixel_set_multi` in fixed point, for views too deep for `f64`,
+/// with a whole exponent of at least 1.
+pub fn pixel_set_multi_fixed(
+    mut value: FixedComplex,
+    complex_num: &FixedComplex,
+    power: u32,
+    max_iterations: u64,
+    bailout: f64,
+) -> Escape {
+    let bailout_sqr = to_fixed(bailout * bailout, value.bits);
 
+    let mut iteration = 0;
+    while iteration < max_iterations && value.norm_sqr() <= bailout_sqr {
+        value = value.powi(power).add(complex_num);
         iteration += 1;
     }
 
-    iteration
+    finish_fixed(&value, iteration, max_iterations, bailout, f64::from(power))
+}
+
+/// Turn the last value and iteration count of a julia loop into an escape result.
+fn finish<T: Float>(
+    value: Complex<T>,
+    iteration: u64,
+    max_iterations: u64,
+    bailout: T,
+    power: f64,
+) -> Escape {
+    if value.norm() > bailout {
+        Escape::escaped(
+            iteration as u32,
+            value.norm_sqr().to_f64().unwrap_or(f64::INFINITY),
+            bailout.to_f64().unwrap_or(2.0),
+            power,
+        )
+    } else {
+        Escape::inside(max_iterations as u32)
+    }
+}
+
+/// `finish` for the fixed point julia loops.
+fn finish_fixed(
+    value: &FixedComplex,
+    iteration: u64,
+    max_iterations: u64,
+    bailout: f64,
+    power: f64,
+) -> Escape {
+    let norm_sqr = value.norm_sqr();
+    if norm_sqr > to_fixed(bailout * bailout, value.bits) {
+        let norm_sqr = from_fixed(&norm_sqr, value.bits);
+        Escape::escaped(iteration as u32, norm_sqr, bailout, power)
+    } else {
+        Escape::inside(max_iterations as u32)
+    }
+}
+
+#[cfg(test)]
+mod julias_tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_julia_constant() {
+        assert_eq!(Ok(LACE), parse_julia_constant("lace"));
+        assert_eq!(Ok(DRAGON), parse_julia_constant(" Dragon"));
+        assert_eq!(
+            Ok(Complex::new(-0.123, 0.745)),
+            parse_julia_constant("-0.123,0.745")
+        );
+        assert!(parse_julia_constant("rabbit").is_err());
+        assert!(parse_julia_constant("0.5").is_err());
+    }
+
+    #[test]
+    fn test_exponents() {
+        let z = Complex::new(0.6, -0.8);
+        assert_eq!(Exponent::Integer(3), Exponent::new(Complex::new(3.0, 0.0)));
+        assert_eq!(Exponent::Real(2.5), Exponent::new(Complex::new(2.5, 0.0)));
+        for &n in &[-3.0, 0.0, 1.0, 2.0, 7.0] {
+            let fast = Exponent::new(Complex::new(n, 0.0)).raise(z);
+            let slow = Exponent::Real(n).raise(z);
+            assert!((fast - slow).norm() < 1e-12, "{}", n);
+        }
+        let complex = Exponent::new(Complex::new(2.0, 0.5)).raise(z);
+        assert!((complex - z.powc(Complex::new(2.0, 0.5))).norm() < 1e-12);
+    }
+
+    #[test]
+    fn test_constant_changes_the_set() {
+        let point = (0.3, 0.1);
+        let inside = pixel_setter(point, 0, Complex::new(0.0, 0.0), 100, 2.0);
+        let outside = pixel_setter(point, 0, Complex::new(0.5, 0.5), 100, 2.0);
+        assert!(!inside.escaped);
+        assert!(outside.escaped);
+    }
 }
@@ -0,0 +1,163 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Layered drawing of a fractal. The background and the fractal are
+//! drawn on separate layers, each with its own per-pixel alpha, and the
+//! fractal layer is composited over the background with the Porter-Duff
+//! "source over" operator and a blend mode. The fractal layer may also
+//! be exported alone, on a transparent background.
+
+// Compositing and blend modes: https://www.w3.org/TR/compositing-1/
+
+use crate::fractal::Fractal;
+use crate::util::*;
+use image::{Rgba, RgbaImage};
+
+/// Names of the blend modes, for input and messages.
+pub const BLEND_NAMES: [&str; 4] = ["normal", "multiply", "screen", "overlay"];
+
+/// How the colors of the fractal layer mix with the background beneath.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BlendMode {
+    /// The fractal covers the background.
+    Normal,
+    /// Colors are multiplied, so the result is never lighter than either layer.
+    Multiply,
+    /// Inverted colors are multiplied, so the result is never darker than either layer.
+    Screen,
+    /// Multiply where the background is dark and screen where it is light.
+    Overlay,
+}
+
+impl BlendMode {
+    /// Blend a background channel with a fractal channel, both from 0 to 1.
+    fn blend(self, backdrop: f32, source: f32) -> f32 {
+        let screen = |a: f32, b: f32| a + b - a * b;
+        match self {
+            BlendMode::Normal => source,
+            BlendMode::Multiply => backdrop * source,
+            BlendMode::Screen => screen(backdrop, source),
+            BlendMode::Overlay => {
+                if backdrop <= 0.5 {
+                    2.0 * backdrop * source
+                } else {
+                    screen(source, 2.0 * backdrop - 1.0)
+                }
+            }
+        }
+    }
+}
+
+/// Parse the name of a blend mode.
+pub fn parse_blend_mode(name: &str) -> Result<BlendMode, String> {
+    match name.trim().to_lowercase().as_str() {
+        "normal" => Ok(BlendMode::Normal),
+        "multiply" => Ok(BlendMode::Multiply),
+        "screen" => Ok(BlendMode::Screen),
+        "overlay" => Ok(BlendMode::Overlay),
+        other => Err(unknown_name("blend mode", other, &BLEND_NAMES)),
+    }
+}
+
+/// Composite one pixel of the fractal layer over one of the background.
+pub fn composite_pixel(backdrop: Rgba<u8>, source: Rgba<u8>, mode: BlendMode) -> Rgba<u8> {
+    let alpha_b = f32::from(backdrop[3]) / 255.0;
+    let alpha_s = f32::from(source[3]) / 255.0;
+    let alpha_o = alpha_s + alpha_b * (1.0 - alpha_s);
+    if alpha_o <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+
+    let channel = |i: usize| {
+        let cb = f32::from(backdrop[i]) / 255.0;
+        let cs = f32::from(source[i]) / 255.0;
+        // Where the backdrop is transparent the fractal's own color shows.
+        let mixed = (1.0 - alpha_b) * cs + alpha_b * mode.blend(cb, cs);
+        let co = (alpha_s * mixed + alpha_b * cb * (1.0 - alpha_s)) / alpha_o;
+        (co * 255.0).round().clamp(0.0, 255.0) as u8
+    };
+    Rgba([
+        channel(0),
+        channel(1),
+        channel(2),
+        (alpha_o * 255.0).round() as u8,
+    ])
+}
+
+/// Composite a whole fractal layer over a background layer of the same size.
+pub fn composite(background: &mut RgbaImage, layer: &RgbaImage, mode: BlendMode) {
+    for (backdrop, source) in background.pixels_mut().zip(layer.pixels()) {
+        *backdrop = composite_pixel(*backdrop, *source, mode);
+    }
+}
+
+/// Draw the background layer of a scheme on its own.
+pub fn background_layer(scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+    let mut imgbuf = RgbaImage::new(imgx, imgy);
+    apply_background(&mut imgbuf, scheme);
+    imgbuf
+}
+
+/// Draw a fractal and, unless the scheme asks for the fractal layer alone,
+/// composite it over the scheme's background with the scheme's blend mode
+/// or the fractal's own.
+pub fn render_layers(fractal: &dyn Fractal, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+    let layer = fractal.render(scheme, imgx, imgy);
+    if scheme.fractal_only {
+        return layer;
+    }
+    let mut imgbuf = background_layer(scheme, imgx, imgy);
+    composite(
+        &mut imgbuf,
+        &layer,
+        scheme.blend.unwrap_or_else(|| fractal.blend_mode()),
+    );
+    imgbuf
+}
+
+#[cfg(test)]
+mod layers_tests {
+    use super::*;
+
+    const GRAY: Rgba<u8> = Rgba([128, 128, 128, 255]);
+    const CLEAR: Rgba<u8> = Rgba([0, 0, 0, 0]);
+
+    #[test]
+    fn test_normal_source_over() {
+        let red = Rgba([255, 0, 0, 255]);
+        assert_eq!(red, composite_pixel(GRAY, red, BlendMode::Normal));
+        assert_eq!(GRAY, composite_pixel(GRAY, CLEAR, BlendMode::Normal));
+        assert_eq!(red, composite_pixel(CLEAR, red, BlendMode::Multiply));
+        assert_eq!(CLEAR, composite_pixel(CLEAR, CLEAR, BlendMode::Screen));
+        // Half covered white over black gives gray.
+        let half_white = Rgba([255, 255, 255, 128]);
+        let black = Rgba([0, 0, 0, 255]);
+        assert_eq!(
+            Rgba([128, 128, 128, 255]),
+            composite_pixel(black, half_white, BlendMode::Normal)
+        );
+    }
+
+    #[test]
+    fn test_blend_modes() {
+        let source = Rgba([255, 128, 0, 255]);
+        assert_eq!(
+            Rgba([128, 64, 0, 255]),
+            composite_pixel(GRAY, source, BlendMode::Multiply)
+        );
+        assert_eq!(
+            Rgba([255, 192, 128, 255]),
+            composite_pixel(GRAY, source, BlendMode::Screen)
+        );
+        let dark = Rgba([64, 64, 192, 255]);
+        assert_eq!(
+            Rgba([128, 64, 129, 255]),
+            composite_pixel(dark, source, BlendMode::Overlay)
+        );
+    }
+
+    #[test]
+    fn test_parse_blend_mode() {
+        assert_eq!(Ok(BlendMode::Overlay), parse_blend_mode("Overlay"));
+        assert!(parse_blend_mode("dissolve").is_err());
+    }
+}
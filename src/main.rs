@@ -11,18 +11,51 @@
 #[macro_use] extern crate rocket;
 
 mod auto_random;
+mod background;
 mod barnsley;
+mod buddhabrot;
+mod chaos;
+mod cli;
+mod css_colors;
+mod density;
+mod escape;
+mod flame;
+mod fractal;
+mod ifs;
 mod julia_sets;
 mod julias;
+mod layers;
 mod mandelbrot;
 mod menu;
+mod newton;
+mod output;
+mod palette;
+mod params;
+mod perturbation;
+mod plot;
+mod precision;
+mod render;
+mod sampling;
 mod util;
+mod variants;
+mod viewport;
 
 use crate::auto_random::*;
-use rocket::response::NamedFile;
+use crate::cli::Command;
+use crate::output::FractalStore;
+use crate::params::GenParams;
+use rocket::http::{ContentType, Status};
+use rocket::request::{FromQuery, Query};
+use rocket::response::status::Custom;
+use rocket::response::{self, NamedFile, Responder, Response};
+use rocket::{Request, State};
 use rocket_contrib::templates::Template;
 use std::collections::HashMap;
+use std::convert::Infallible;
+use std::io::Cursor;
 use std::path::Path;
+use std::process;
+use std::sync::Arc;
 
 /// Start a web server and display a cool fractal on root page
 /// Allow user to send a GET to create a new fractal as much as they want
@@ -33,12 +66,70 @@ fn index() -> Template {
     Template::render("index", &context)
 }
 
-#[get("/gen")]
-fn generate() -> Template {
-    auto_random(1, "cool");
+/// All key/value pairs of a query string, URL decoded.
+struct QueryPairs(Vec<(String, String)>);
+
+impl<'q> FromQuery<'q> for QueryPairs {
+    type Error = Infallible;
+
+    fn from_query(query: Query<'q>) -> Result<QueryPairs, Infallible> {
+        Ok(QueryPairs(query.map(|item| item.key_value_decoded()).collect()))
+    }
+}
+
+/// Generate a fractal from the query parameters, e.g.
+/// `/gen?fractal=julia&width=800&height=800&color=blue&bg=black&transform=invert`
+/// or `/gen?fractal=mandelbrot&center=-0.745,0.113&zoom=200&rotation=30`.
+/// Parameters left out are randomized, invalid ones are a 400.
+/// Each generation is rendered in memory, kept under its own id
+/// and served from `/fractal/<id>.png`.
+#[get("/gen?<query..>")]
+fn generate(query: QueryPairs, store: State<FractalStore>) -> Result<Template, Custom<String>> {
+    let params = GenParams::from_pairs(query.0).map_err(bad_request)?;
+    render_page(params, store)
+}
+
+/// Generate an IFS fractal from maps sent as the request body, one
+/// `a b c d e f [weight]` per line, or a flame from its transforms,
+/// with the other parameters in the query, e.g.
+/// `curl --data-binary @dragon.ifs '/gen?fractal=ifs&width=800&height=800'`.
+#[post("/gen?<query..>", data = "<maps>")]
+fn generate_maps(
+    query: QueryPairs,
+    maps: String,
+    store: State<FractalStore>,
+) -> Result<Template, Custom<String>> {
+    let mut params = GenParams::from_pairs(query.0).map_err(bad_request)?;
+    params.set_body(&maps).map_err(bad_request)?;
+    render_page(params, store)
+}
+
+/// A 400 answer explaining what was wrong with a request.
+fn bad_request(message: String) -> Custom<String> {
+    Custom(Status::BadRequest, message)
+}
+
+/// Draw the fractal some parameters describe, keep it in the store and
+/// answer with the page showing it.
+fn render_page(params: GenParams, store: State<FractalStore>) -> Result<Template, Custom<String>> {
+    let mut generation = params.to_generation().map_err(bad_request)?;
+    generation.scheme.threads = generation.scheme.threads.min(render::MAX_WEB_THREADS);
+
+    let image = generate_fractal(&generation.scheme, generation.width, generation.height);
+    let metadata = output::scheme_metadata(&generation.scheme);
+    let png = output::encode_png_with_text(&image, &metadata)
+        .map_err(|message| Custom(Status::InternalServerError, message))?;
+    let id = store.insert(png);
+
     let mut context = HashMap::new();
-    context.insert("foo".to_string(), "baz".to_string());
-    Template::render("gen", &context)
+    context.insert("id".to_string(), id);
+    let details: Vec<String> = metadata
+        .iter()
+        .skip(1)
+        .map(|(key, value)| format!("{}: {}", key, value))
+        .collect();
+    context.insert("details".to_string(), details.join(", "));
+    Ok(Template::render("gen", &context))
 }
 
 #[get("/about")]
@@ -54,10 +145,32 @@ fn cool() -> Option<NamedFile> {
     NamedFile::open(&path).ok()
 }
 
-#[get("/cool0.png")]
-fn cool0() -> Option<NamedFile> {
-    let path = Path::new("cool0.png");
-    NamedFile::open(&path).ok()
+/// Serve a generated fractal by its file name, `<id>.png`,
+/// straight from memory.
+#[get("/fractal/<file>")]
+fn fractal(file: String, store: State<FractalStore>) -> Option<SharedPng> {
+    if !file.ends_with(".png") {
+        return None;
+    }
+    store.get(file.trim_end_matches(".png")).map(SharedPng)
+}
+
+/// PNG bytes shared with the `FractalStore`, served without copying them.
+struct SharedPng(Arc<Vec<u8>>);
+
+impl AsRef<[u8]> for SharedPng {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl<'r> Responder<'r> for SharedPng {
+    fn respond_to(self, _: &Request) -> response::Result<'r> {
+        Response::build()
+            .header(ContentType::PNG)
+            .sized_body(Cursor::new(self))
+            .ok()
+    }
 }
 
 #[get("/templates/cool.css", format = "text/css")]
@@ -84,11 +197,29 @@ fn favicon() -> Option<NamedFile> {
     NamedFile::open(&path).ok()
 }
 
-fn main() {
+/// Launch the web server.
+fn serve() {
     rocket::ignite()
-        .mount("/", rocket::routes![index, generate, about,
-                                    cool, cool0, style, 
+        .mount("/", rocket::routes![index, generate, generate_maps, about,
+                                    cool, fractal, style, 
                                     rust_logo, nav_bars, favicon])
+        .manage(FractalStore::default())
         .attach(Template::fairing())
         .launch();
 }
+
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = match cli::parse_args(&args) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("error: {}\n\n{}", message, cli::USAGE);
+            process::exit(cli::EXIT_USAGE);
+        }
+    };
+
+    match command {
+        Command::Serve => serve(),
+        command => process::exit(cli::run(command)),
+    }
+}
@@ -2,50 +2,172 @@
 
 //! Mandelbrot set implementation.
 
-use crate::util::Color::*;
+use crate::escape::Escape;
+use crate::fractal::*;
+use crate::perturbation::ReferenceOrbit;
+use crate::precision::*;
+use crate::sampling::supersample;
 use crate::util::*;
-use image::Rgba;
-use num::Complex;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use num::{Complex, Float};
+
+/// Region showing the whole Mandelbrot set.
+pub const MANDELBROT_VIEWPORT: Viewport = Viewport::new(-0.5, 0.0, 3.0);
+
+/// The Mandelbrot set, drawn by `mandelbrot_fractal`.
+pub struct Mandelbrot;
+
+impl Fractal for Mandelbrot {
+    fn name(&self) -> &'static str {
+        "mandelbrot"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        escape_time_params()
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(MANDELBROT_VIEWPORT)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        mandelbrot_fractal(imgx, imgy, scheme)
+    }
+}
 
 /// Mandelbrot - fractal pattern representing the escape time of
 /// a complex number being squared plus some constant to infinity.
-pub fn mandelbrot_fractal(imgx: u32, imgy: u32, filename: &str, scheme: &mut Scheme) {
-    let complex_x_min = -2_f32;
-    let complex_x_max = 1_f32;
-    let complex_y_min = -1.4_f32;
-    let complex_y_max = 1_f32;
-    let scalex = (complex_x_max - complex_x_min) / imgx as f32;
-    let scaley = (complex_y_max - complex_y_min) / imgx as f32;
+/// The iteration count, smooth if the scheme asks for it, is scaled to
+/// the scheme's maximum and looked up in the scheme's palette, so any
+/// maximum fits the palette. Pixels are colored from one or more samples
+/// as the scheme's sampling asks, in the number type its precision asks
+/// for; views too deep for `f64` are drawn by perturbation. Only the
+/// fractal layer is drawn.
+pub fn mandelbrot_fractal(imgx: u32, imgy: u32, scheme: &Scheme) -> RgbaImage {
+    let viewport = scheme.viewport.unwrap_or(MANDELBROT_VIEWPORT);
+    let palette = scheme_palette(scheme);
+    let arithmetic = choose_arithmetic(scheme.precision, &viewport, imgx, imgy);
+    let max = scheme.max_iterations;
+    let center = match arithmetic {
+        Arithmetic::Fixed(bits) | Arithmetic::Perturbation(bits) => {
+            Some(fixed_center(scheme, &viewport, bits))
+        }
+        _ => None,
+    };
+    let reference = match (arithmetic, &center) {
+        (Arithmetic::Perturbation(_), Some(center)) => {
+            Some(ReferenceOrbit::new(center, max, scheme.bailout))
+        }
+        _ => None,
+    };
+
+    let mut imgbuf = RgbaImage::new(imgx, imgy);
 
-    let mut imgbuf = image::ImageBuffer::new(imgx, imgy);
+    supersample(&mut imgbuf, scheme.threads, scheme.sampling, |px, py| {
+        let escape = match (&reference, &center) {
+            (Some(reference), _) => {
+                let (dx, dy) = viewport.offset(px, py, imgx, imgy);
+                reference.escape_time(Complex::new(dx, dy), max, scheme.bailout)
+            }
+            (None, Some(center)) => {
+                let c = center.offset_by(&viewport, px, py, imgx, imgy);
+                escape_time_fixed(&c, max, scheme.bailout)
+            }
+            (None, None) if arithmetic == Arithmetic::F32 => {
+                let c = float_point::<f32>(&viewport, px, py, imgx, imgy);
+                escape_time(c, max, scheme.bailout as f32)
+            }
+            (None, None) => {
+                let c = float_point::<f64>(&viewport, px, py, imgx, imgy);
+                escape_time(c, max, scheme.bailout)
+            }
+        };
+        let color = palette.escape_color(&escape, scheme.max_iterations, scheme.smooth);
+        escape_pixel(&escape, color, scheme)
+    });
 
-    apply_background(&mut imgbuf, &scheme);
+    imgbuf
+}
 
-    for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let cx = complex_x_min + x as f32 * scalex;
-        let cy = complex_y_min + y as f32 * scaley;
+/// Count the iterations of z = z^2 + c, starting from zero, before |z|
+/// grows past the bailout radius. Points that never escape report
+/// `max_iterations`.
+pub fn escape_time<T: Float>(c: Complex<T>, max_iterations: u32, bailout: T) -> Escape {
+    escape_orbit(c, max_iterations, bailout, |_| {})
+}
 
-        let c = Complex::new(cx, cy);
-        let mut z = Complex::new(0_f32, 0_f32);
+/// `escape_time`, calling `visit` with every point of the orbit after the
+/// first, z = c, up to and including the one that escaped.
+pub fn escape_orbit<T: Float, F: FnMut(Complex<T>)>(
+    c: Complex<T>,
+    max_iterations: u32,
+    bailout: T,
+    mut visit: F,
+) -> Escape {
+    let bailout_sqr = bailout * bailout;
+    let mut z = Complex::new(T::zero(), T::zero());
+    for i in 0..max_iterations {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr {
+            return Escape::escaped(
+                i,
+                norm_sqr.to_f64().unwrap_or(f64::INFINITY),
+                bailout.to_f64().unwrap_or(2.0),
+                2.0,
+            );
+        }
+        z = z * z + c;
+        visit(z);
+    }
+    Escape::inside(max_iterations)
+}
 
-        let mut i = 0;
-        for t in 0..255 {
-            if z.norm() > 2.0 {
-                break;
-            }
-            z = z * z + c;
-            i = t;
+/// `escape_time` in fixed point, for views too deep for `f64`.
+pub fn escape_time_fixed(c: &FixedComplex, max_iterations: u32, bailout: f64) -> Escape {
+    let bailout_sqr = to_fixed(bailout * bailout, c.bits);
+    let mut z = FixedComplex::new(0.0, 0.0, c.bits);
+    for i in 0..max_iterations {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr {
+            return Escape::escaped(i, from_fixed(&norm_sqr, c.bits), bailout, 2.0);
         }
+        z = z.square().add(c);
+    }
+    Escape::inside(max_iterations)
+}
+
+#[cfg(test)]
+mod mandelbrot_tests {
+    use super::*;
 
-        let Rgba(data) = *pixel;
-        match scheme.color {
-            Red => *pixel = Rgba([i, data[1], data[2], 255]),
-            Green => *pixel = Rgba([data[0], i, data[2], 255]),
-            Blue => *pixel = Rgba([data[0], data[1], i, 255]),
-            White => *pixel = Rgba([i, i, i, 255]),
-            _ => panic!("Unsupported color"),
+    #[test]
+    fn test_precisions_agree_on_shallow_views() {
+        for &(re, im) in &[(-0.75, 0.1), (0.3, 0.5), (-1.2, 0.0), (0.26, 0.0)] {
+            let double = escape_time(Complex::new(re, im), 500, 2.0);
+            let fixed = escape_time_fixed(&FixedComplex::new(re, im, 96), 500, 2.0);
+            assert_eq!(double.iterations, fixed.iterations);
+            assert_eq!(double.escaped, fixed.escaped);
+            assert!((double.smooth - fixed.smooth).abs() < 1e-6);
         }
     }
 
-    imgbuf.save(filename).expect("Image write failed...");
+    #[test]
+    fn test_deep_views_still_differ_between_pixels() {
+        let viewport = Viewport::new(-0.743_643_887_037_151, 0.131_825_904_205_33, 1e-17);
+        let bits = match choose_arithmetic(Precision::Arbitrary, &viewport, 8, 8) {
+            Arithmetic::Fixed(bits) => bits,
+            other => panic!("expected fixed point, got {:?}", other),
+        };
+        let escape = |x: f64| {
+            let c = FixedComplex::new(viewport.center_re, viewport.center_im, bits)
+                .offset_by(&viewport, x, 4.0, 8, 8);
+            escape_time_fixed(&c, 4000, 2.0)
+        };
+        assert!(escape(0.5).escaped);
+        assert!(escape(0.5).smooth > escape(1.5).smooth);
+        // f64 sees the same point at both pixels.
+        let double = |x: f64| escape_time(float_point::<f64>(&viewport, x, 4.0, 8, 8), 4000, 2.0);
+        assert_eq!(double(0.5), double(1.5));
+    }
 }
@@ -2,6 +2,9 @@
 
 //! Contains all user interaction (UI) menus to utilize the fractal generator program. Covering user input for fractal color,
 //! background colors, background color styling, image trasformation additions, and general user notification on the process of the program.
+use crate::background::{BackgroundGradient, Shape};
+use crate::fractal::{self, Fractal};
+use crate::mandelbrot::Mandelbrot;
 use crate::util::*;
 use std::io;
 use std::io::Write;
@@ -9,8 +12,8 @@ use std::io::Write;
 /// The user_menu function is the first entry point for the user on the firstly deciding on the initial style of background they will wish to use,
 /// normal which limits you to only changing the background color on a black background, custom that allows the fractal color, background color & styling,
 /// and random which gives no user customization and instead randomly selects the input styling attributes and creates a random styled fractal
-/// of which can be determined in its log file. Reference README.md
-pub fn user_menu(mut scheme: &mut Scheme) {
+/// of which can be found in the image's metadata. Reference README.md
+pub fn user_menu(scheme: &mut Scheme) {
     let mut input = String::new();
 
     print!(
@@ -33,12 +36,15 @@ pub fn user_menu(mut scheme: &mut Scheme) {
     println!("\n========================================================================================================================================\n");
 
     match trimmed {
-        "normal" | "1" => normal_menu(&mut scheme),
+        "normal" | "1" => normal_menu(scheme),
         "custom" | "2" => {
-            normal_menu(&mut scheme);
-            custom_menu(&mut scheme)
+            normal_menu(scheme);
+            custom_menu(scheme)
+        }
+        "random" | "3" => {
+            randomize(scheme);
+            scheme.random_transforms = pick_random_transforms();
         }
-        "random" | "3" => randomize(&mut scheme),
         _ => println!("Unrecognized input... running default."),
     }
 
@@ -46,28 +52,24 @@ pub fn user_menu(mut scheme: &mut Scheme) {
 }
 
 /// Allows a user to select only the color of the fractal they will generate upon a black background.
-/// It should be known that the julia sets, multi julia sets, and mandelbrot fractals are given a limited fractal color optionality due to implementation constrictions.
-pub fn normal_menu(mut scheme: &mut Scheme) {
-    let mut input;
-    let scheme_type;
+/// The options offered are the colors the chosen fractal supports, e.g. the julia sets, multi julia sets,
+/// and mandelbrot fractals are given a limited fractal color optionality due to implementation constrictions.
+pub fn normal_menu(scheme: &mut Scheme) {
+    let fractal = fractal::lookup(&scheme.fractal).unwrap_or(&Mandelbrot);
+    if fractal.colors().is_empty() {
+        println!(
+            "\n\no The {} fractal colors itself, so there is no fractal color to pick.",
+            fractal.name()
+        );
+        return;
+    }
+
     println!(
         "\n\no FRACTAL COLOR MENU: \n
         o What color would you like the fractal to be? Please select from the following option. \n"
     );
 
-    if scheme.fractal == "barnsley" {
-        scheme_type = true;
-        input = color_options_extensive();
-    } else {
-        scheme_type = false;
-        input = color_options_rgb(false);
-    }
-
-    input = color_determine(input, scheme_type);
-
-    let color: &str = &input;
-
-    scheme.color = str_to_color(color);
+    scheme.color = color_options_for(fractal);
 
     println!("\n========================================================================================================================================\n");
 }
@@ -75,7 +77,7 @@ pub fn normal_menu(mut scheme: &mut Scheme) {
 /// Allows a user to select the background styling between solid color or transitional between two user selected colors.
 /// After the user moves through those options they are also prompted if they wish to apply image atrributes which can alter the final image.
 /// We have a default set to make the background a solid black for unrecognized input.
-pub fn custom_menu(mut scheme: &mut Scheme) {
+pub fn custom_menu(scheme: &mut Scheme) {
     let mut input = String::new();
 
     print!(
@@ -99,10 +101,7 @@ pub fn custom_menu(mut scheme: &mut Scheme) {
             println!("\n\no SOLID BACKGROUND COLOR MENU: \n
             o What solid color background would you like? Keep in mind some fractal colors are easier to see on certain colors\n ");
 
-            input = color_options_extensive();
-            input = color_determine(input, true);
-
-            scheme.bg_color = str_to_color(&input);
+            scheme.bg_color = color_options_extensive();
         }
 
         "2" | "transition" => {
@@ -114,27 +113,21 @@ pub fn custom_menu(mut scheme: &mut Scheme) {
             o Please select one of the following colors: "
             );
 
-            input = color_options_rgb(true);
-            input = color_determine(input, false);
-
-            scheme.bg_color = str_to_color(&input);
-
-            input.clear();
+            scheme.bg_color = color_options_rgb(true);
 
             println!("\n\to Choose one color that is not the same as the first: ");
 
-            input = color_options_rgb(true);
-            input = color_determine(input, false);
+            scheme.bg_color_2 = color_options_rgb(true);
 
-            scheme.bg_color_2 = str_to_color(&input);
+            let mut gradient = BackgroundGradient::two_color(scheme.bg_color, scheme.bg_color_2);
+            gradient.shape = shape_options();
+            scheme.bg_gradient = Some(gradient);
         }
 
         _ => {
             println!("Unrecognized input... running default black background.");
 
-            let color: &str = "black";
-
-            scheme.bg_color = str_to_color(color);
+            scheme.bg_color = Color::Black;
         }
     }
 
@@ -146,7 +139,7 @@ pub fn custom_menu(mut scheme: &mut Scheme) {
 
     if trimmed == "y" || trimmed == "yes" {
         scheme.do_transform = true;
-        transform_options(&mut scheme);
+        transform_options(scheme);
     }
 
     println!("\n========================================================================================================================================\n");
@@ -154,10 +147,8 @@ pub fn custom_menu(mut scheme: &mut Scheme) {
 
 /// Utilized to print the repeatedly used color print options based on the fractal type. The extensive menu is used
 /// for the barnsley fractal color and the background colors for the solid background on the custom menu.
-
-pub fn color_options_extensive() -> String {
-    let mut input = String::new();
-
+/// Any other color may be written out instead, e.g. "#ff8800", "rgb(255, 136, 0)" or "coral".
+pub fn color_options_extensive() -> Color {
     print!(
         "
         \t1) Red\n
@@ -168,39 +159,23 @@ pub fn color_options_extensive() -> String {
         \t6) Violet\n
         \t7) White\n
         \t8) Black\n
+        \tor any color, e.g. #ff8800, rgb(255, 136, 0) or coral\n
         o Input: "
     );
-    io::stdout().flush().unwrap();
-    io::stdin().read_line(&mut input).ok();
-
-    let trimmed: &str = &input.trim().to_lowercase();
 
-    if trimmed == "1"
-        || trimmed == "2"
-        || trimmed == "3"
-        || trimmed == "4"
-        || trimmed == "5"
-        || trimmed == "6"
-        || trimmed == "7"
-        || trimmed == "8"
-    {
-        input.to_string()
-    } else {
-        println!("\n\to Non-allowed option selected, running default color RED \n");
-        "1".to_string()
-    }
+    read_color(true)
 }
 
-/// Used for the julia, multi-julia set, and mandelbrot fractal colors, and the transitional background style menu.
-pub fn color_options_rgb(transitional: bool) -> String {
-    let mut input = String::new();
-
+/// Used for the transitional background style menu, listing only red, green and blue,
+/// plus white when not transitional. Any other color may be written out instead.
+pub fn color_options_rgb(transitional: bool) -> Color {
     if transitional {
         print!(
             "
         \t1) Red\n
         \t2) Green\n
         \t3) Blue\n
+        \tor any color, e.g. #ff8800, rgb(255, 136, 0) or coral\n
         o Input: "
         );
     } else {
@@ -210,19 +185,106 @@ pub fn color_options_rgb(transitional: bool) -> String {
         \t2) Green\n
         \t3) Blue\n
         \t4) White\n
+        \tor any color, e.g. #ff8800, rgb(255, 136, 0) or coral\n
         o Input: "
         );
     }
+
+    read_color(false)
+}
+
+/// Read menu input until it names a color, either by its number in the menu just
+/// printed or written out. Parse errors are shown and the user is asked again.
+/// Defaults to red if the input runs out.
+fn read_color(scheme_type: bool) -> Color {
+    loop {
+        let mut input = String::new();
+        io::stdout().flush().unwrap();
+        if io::stdin().read_line(&mut input).unwrap_or(0) == 0 {
+            println!("\n\to No input, running default color RED \n");
+            return Color::Red;
+        }
+
+        match parse_color(&color_determine(input, scheme_type)) {
+            Ok(color) => return color,
+            Err(message) => print!("\n\to {}\n        o Input: ", message),
+        }
+    }
+}
+
+/// Prompt for the shape of a transitional background. Defaults to linear for unrecognized input.
+fn shape_options() -> Shape {
+    let mut input = String::new();
+
+    print!(
+        "\n\to Which shape should the transition take?\n
+        \t1) Linear: from one corner to the other\n
+        \t2) Radial: out from the middle\n
+        \t3) Conic: around the middle\n
+        o Input: "
+    );
     io::stdout().flush().unwrap();
     io::stdin().read_line(&mut input).ok();
 
     let trimmed: &str = &input.trim().to_lowercase();
+    match trimmed {
+        "2" | "radial" => Shape::Radial,
+        "3" | "conic" => Shape::Conic,
+        "1" | "linear" => Shape::Linear,
+        _ => {
+            println!("\n\to Non-allowed option selected, running default shape LINEAR \n");
+            Shape::Linear
+        }
+    }
+}
 
-    if trimmed == "1" || trimmed == "2" || trimmed == "3" || trimmed == "4" {
-        input.to_string()
+/// Print a numbered menu of the colors offered for a fractal and return the one the user picked,
+/// by number, by name, or written out as any color the fractal supports.
+/// Defaults to the first color for anything else.
+pub fn color_options_for(fractal: &dyn Fractal) -> Color {
+    let mut input = String::new();
+    let colors = fractal.colors();
+
+    for (i, color) in colors.iter().enumerate() {
+        print!("\n        \t{}) {}\n", i + 1, color_to_str(*color));
+    }
+    print!("\n        \tor any other color, e.g. #ff8800, rgb(255, 136, 0) or coral\n");
+    print!("\n        o Input: ");
+    io::stdout().flush().unwrap();
+    io::stdin().read_line(&mut input).ok();
+
+    match pick_color(fractal, &input) {
+        Ok(color) => color,
+        Err(message) => {
+            println!(
+                "\n\to {}, running default color {} \n",
+                message,
+                color_to_str(colors[0]).to_uppercase()
+            );
+            colors[0]
+        }
+    }
+}
+
+/// Match menu input against the colors offered for a fractal by 1-based number,
+/// or parse it as any color the fractal supports.
+fn pick_color(fractal: &dyn Fractal, input: &str) -> Result<Color, String> {
+    let trimmed: &str = &input.trim().to_lowercase();
+    if let Ok(n) = trimmed.parse::<usize>() {
+        return match n.checked_sub(1).and_then(|i| fractal.colors().get(i)) {
+            Some(color) => Ok(*color),
+            None => Err(format!("Non-allowed option {} selected", n)),
+        };
+    }
+    let color = parse_color(trimmed)?;
+    if fractal.supports_color(color) {
+        Ok(color)
     } else {
-        println!("\n\to Non-allowed option selected, running default color RED \n");
-        "1".to_string()
+        Err(format!(
+            "The {} fractal can not be drawn in {}",
+            fractal.name(),
+            color_to_str(color)
+        ))
     }
 }
 
@@ -336,6 +398,19 @@ mod menu_tests {
         );
     }
 
+    #[test]
+    fn test_pick_color() {
+        assert_eq!(Ok(Color::Orange), pick_color(&Mandelbrot, "2"));
+        assert_eq!(Ok(Color::White), pick_color(&Mandelbrot, "White\n"));
+        assert_eq!(
+            Ok(Color::Rgb([255, 127, 80])),
+            pick_color(&Mandelbrot, "coral")
+        );
+        assert!(pick_color(&Mandelbrot, "black").is_err());
+        assert!(pick_color(&Mandelbrot, "0").is_err());
+        assert!(pick_color(&Mandelbrot, "plaid").is_err());
+    }
+
     #[test]
     fn test_color_determine_bad_input() {
         assert_eq!("huh".to_string(), color_determine("Huh".to_string(), false));
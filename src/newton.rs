@@ -0,0 +1,494 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Newton fractals. Every point of the plane is moved by Newton's method
+//! towards a root of a polynomial, z = z - a p(z) / p'(z), and colored by
+//! the root it settles on, darker the longer it took. The polynomial is
+//! written like `z^3 - 1` or given by its roots, and a relaxation factor
+//! a other than 1 gives the "Nova" style of Newton fractal.
+
+// Newton fractals: https://en.wikipedia.org/wiki/Newton_fractal
+// Finding the roots: https://en.wikipedia.org/wiki/Durand%E2%80%93Kerner_method
+
+use crate::escape::Escape;
+use crate::fractal::*;
+use crate::palette::Palette;
+use crate::sampling::supersample;
+use crate::util::*;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use num::Complex;
+
+/// Region showing the roots of the default polynomial.
+pub const NEWTON_VIEWPORT: Viewport = Viewport::new(0.0, 0.0, 4.0);
+/// Polynomial drawn when the scheme has none.
+pub const DEFAULT_POLYNOMIAL: &str = "z^3 - 1";
+/// Default distance between steps at which a point has converged.
+pub const DEFAULT_TOLERANCE: f64 = 1e-6;
+/// Highest degree of polynomial accepted.
+pub const MAX_DEGREE: usize = 32;
+/// Iterations after which a converged point is half as bright.
+const FADE_ITERATIONS: f64 = 10.0;
+
+/// Parameters of the Newton fractal on top of the escape time ones.
+pub const NEWTON_PARAMS: [Param; 4] = [
+    Param {
+        name: "polynomial",
+        description: "Polynomial in z whose roots are found, e.g. z^3 - 1",
+        default: DEFAULT_POLYNOMIAL,
+    },
+    Param {
+        name: "roots",
+        description: "Roots of the polynomial instead, e.g. 1,-1,i,-i",
+        default: "",
+    },
+    Param {
+        name: "relaxation",
+        description: "Factor a of the Newton step z - a p(z)/p'(z), real or complex",
+        default: "1",
+    },
+    Param {
+        name: "tolerance",
+        description: "Step size below which a point has converged",
+        default: "0.000001",
+    },
+];
+
+/// A polynomial with complex coefficients, and its roots.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Polynomial {
+    /// Coefficients from the constant term up.
+    pub coefficients: Vec<Complex<f64>>,
+    pub roots: Vec<Complex<f64>>,
+}
+
+impl Polynomial {
+    /// The polynomial with some roots, (z - r1)(z - r2)...
+    pub fn from_roots(roots: Vec<Complex<f64>>) -> Polynomial {
+        let mut coefficients = vec![Complex::new(1.0, 0.0)];
+        for root in &roots {
+            // Multiply by (z - root).
+            let mut next = vec![Complex::new(0.0, 0.0); coefficients.len() + 1];
+            for (i, &coefficient) in coefficients.iter().enumerate() {
+                next[i + 1] += coefficient;
+                next[i] -= coefficient * root;
+            }
+            coefficients = next;
+        }
+        Polynomial {
+            coefficients,
+            roots,
+        }
+    }
+
+    /// Parse a polynomial in z, such as "z^3 - 1" or "(1+i)z^4 - 2.5z + i".
+    pub fn parse(text: &str) -> Result<Polynomial, String> {
+        let invalid = || {
+            format!(
+                "Invalid polynomial '{}', expected terms like 2z^3, (1+i)z or -1 joined by + and -",
+                text
+            )
+        };
+        let compact: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut coefficients = Vec::new();
+        for (sign, term) in split_terms(&compact).ok_or_else(invalid)? {
+            let (coefficient, power) = match term.find('z') {
+                Some(z) => {
+                    let power = match &term[z + 1..] {
+                        "" => 1,
+                        power if power.starts_with('^') => {
+                            power[1..].parse::<usize>().map_err(|_| invalid())?
+                        }
+                        _ => return Err(invalid()),
+                    };
+                    (term[..z].trim_end_matches('*'), power)
+                }
+                None => (term, 0),
+            };
+            if power > MAX_DEGREE {
+                return Err(format!(
+                    "Invalid polynomial '{}', expected a degree of at most {}",
+                    text, MAX_DEGREE
+                ));
+            }
+            let coefficient = match coefficient {
+                "" => Complex::new(1.0, 0.0),
+                c if c.starts_with('(') && c.ends_with(')') => {
+                    parse_complex(&c[1..c.len() - 1]).map_err(|_| invalid())?
+                }
+                c => parse_complex(c).map_err(|_| invalid())?,
+            };
+            if coefficients.len() <= power {
+                coefficients.resize(power + 1, Complex::new(0.0, 0.0));
+            }
+            coefficients[power] += coefficient * sign;
+        }
+        while coefficients.last().is_some_and(|c| c.norm_sqr() == 0.0) {
+            coefficients.pop();
+        }
+        if coefficients.len() < 2 {
+            return Err(format!(
+                "Invalid polynomial '{}', expected z in it so it has roots",
+                text
+            ));
+        }
+        let roots = find_roots(&coefficients);
+        Ok(Polynomial {
+            coefficients,
+            roots,
+        })
+    }
+
+    /// Parse a comma separated list of roots, such as "1, -1, i, -i".
+    pub fn parse_roots(text: &str) -> Result<Polynomial, String> {
+        let roots = text
+            .split(',')
+            .map(parse_complex)
+            .collect::<Result<Vec<_>, _>>()?;
+        if roots.is_empty() || roots.len() > MAX_DEGREE {
+            return Err(format!(
+                "Invalid roots '{}', expected from 1 to {} complex numbers",
+                text, MAX_DEGREE
+            ));
+        }
+        Ok(Polynomial::from_roots(roots))
+    }
+
+    /// The polynomial and its derivative at z, by Horner's method.
+    pub fn evaluate(&self, z: Complex<f64>) -> (Complex<f64>, Complex<f64>) {
+        let mut value = Complex::new(0.0, 0.0);
+        let mut derivative = Complex::new(0.0, 0.0);
+        for &coefficient in self.coefficients.iter().rev() {
+            derivative = derivative * z + value;
+            value = value * z + coefficient;
+        }
+        (value, derivative)
+    }
+}
+
+impl Default for Polynomial {
+    /// z^3 - 1, from its roots, the cube roots of unity.
+    fn default() -> Polynomial {
+        let height = 3.0_f64.sqrt() / 2.0;
+        Polynomial::from_roots(vec![
+            Complex::new(1.0, 0.0),
+            Complex::new(-0.5, height),
+            Complex::new(-0.5, -height),
+        ])
+    }
+}
+
+/// Split a polynomial without spaces into signed terms, at the + and -
+/// signs outside parentheses that do not belong to an exponent.
+fn split_terms(text: &str) -> Option<Vec<(f64, &str)>> {
+    let mut terms = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    let mut sign = 1.0;
+    let mut previous = None;
+    for (i, c) in text.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            '+' | '-' if depth == 0 && !matches!(previous, Some('e') | Some('^')) => {
+                if i > start {
+                    terms.push((sign, &text[start..i]));
+                } else if i > 0 {
+                    return None;
+                }
+                sign = if c == '-' { -1.0 } else { 1.0 };
+                start = i + 1;
+            }
+            _ => (),
+        }
+        previous = Some(c);
+    }
+    if start >= text.len() || depth != 0 {
+        return None;
+    }
+    terms.push((sign, &text[start..]));
+    Some(terms)
+}
+
+/// Parse a complex number such as "2", "-1.5i", "i" or "0.5-2i".
+pub fn parse_complex(text: &str) -> Result<Complex<f64>, String> {
+    let invalid = || {
+        format!(
+            "Invalid complex number '{}', expected e.g. 1, 2i or 0.5-2i",
+            text
+        )
+    };
+    let text: String = text.chars().filter(|c| !c.is_whitespace()).collect();
+    let number = |part: &str| match part {
+        "" | "+" => Ok(1.0),
+        "-" => Ok(-1.0),
+        part => part.parse::<f64>().map_err(|_| invalid()),
+    };
+    let value = if text.ends_with('i') {
+        let body = &text[..text.len() - 1];
+        // The imaginary part starts at the last sign that is neither
+        // leading nor part of an exponent.
+        let bytes = body.as_bytes();
+        let split = (1..body.len())
+            .rev()
+            .find(|&i| (bytes[i] == b'+' || bytes[i] == b'-') && bytes[i - 1] != b'e');
+        match split {
+            Some(i) => Complex::new(
+                body[..i].parse::<f64>().map_err(|_| invalid())?,
+                number(&body[i..])?,
+            ),
+            None => Complex::new(0.0, number(body)?),
+        }
+    } else {
+        Complex::new(text.parse::<f64>().map_err(|_| invalid())?, 0.0)
+    };
+    if value.re.is_finite() && value.im.is_finite() {
+        Ok(value)
+    } else {
+        Err(invalid())
+    }
+}
+
+/// Find all roots of a polynomial at once by the Durand-Kerner method.
+fn find_roots(coefficients: &[Complex<f64>]) -> Vec<Complex<f64>> {
+    let degree = coefficients.len() - 1;
+    let leading = coefficients[degree];
+    let monic: Vec<Complex<f64>> = coefficients.iter().map(|c| c / leading).collect();
+    let polynomial = Polynomial {
+        coefficients: monic,
+        roots: vec![],
+    };
+    // Powers of a number that is neither real nor a root of unity.
+    let seed = Complex::new(0.4, 0.9);
+    let mut roots: Vec<Complex<f64>> = (0..degree).map(|k| seed.powf(k as f64)).collect();
+    for _ in 0..500 {
+        let mut moved: f64 = 0.0;
+        for i in 0..degree {
+            let mut denominator = Complex::new(1.0, 0.0);
+            for j in 0..degree {
+                if i != j {
+                    denominator *= roots[i] - roots[j];
+                }
+            }
+            let step = polynomial.evaluate(roots[i]).0 / denominator;
+            if step.re.is_finite() && step.im.is_finite() {
+                roots[i] -= step;
+                moved = moved.max(step.norm());
+            }
+        }
+        if moved < 1e-14 {
+            break;
+        }
+    }
+    roots
+}
+
+/// The Newton fractal of a polynomial, drawn by `newton_fractal`.
+pub struct Newton;
+
+impl Fractal for Newton {
+    fn name(&self) -> &'static str {
+        "newton"
+    }
+
+    fn params(&self) -> Vec<Param> {
+        // Newton's method has no escape radius and runs in f64.
+        let mut params: Vec<Param> = escape_time_params()
+            .into_iter()
+            .filter(|param| param.name != "bailout" && param.name != "precision")
+            .collect();
+        params.extend_from_slice(&NEWTON_PARAMS);
+        params
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(NEWTON_VIEWPORT)
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        newton_fractal(imgx, imgy, scheme)
+    }
+}
+
+/// How a point moved under Newton's method.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Convergence {
+    /// Index of the root the point settled on, if it did.
+    pub root: Option<usize>,
+    /// Iterations taken, with a fraction if asked for.
+    pub escape: Escape,
+}
+
+/// Run Newton's method from z until a step is shorter than `tolerance`,
+/// then find the nearest root. The smooth count adds the fraction of the
+/// last step needed to get within tolerance, assuming the error squares
+/// each step as it does near a simple root.
+pub fn converge(
+    polynomial: &Polynomial,
+    mut z: Complex<f64>,
+    relaxation: Complex<f64>,
+    tolerance: f64,
+    max_iterations: u32,
+) -> Convergence {
+    let mut previous = f64::MAX;
+    for i in 0..max_iterations {
+        let (value, derivative) = polynomial.evaluate(z);
+        let step = relaxation * value / derivative;
+        let distance = step.norm();
+        if !distance.is_finite() {
+            break;
+        }
+        z -= step;
+        if distance < tolerance {
+            let root = polynomial
+                .roots
+                .iter()
+                .map(|root| (root - z).norm_sqr())
+                .enumerate()
+                .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(index, _)| index);
+            let fraction = (tolerance.ln() - previous.ln()) / (distance.ln() - previous.ln());
+            let smooth = f64::from(i) + fraction.clamp(0.0, 1.0);
+            return Convergence {
+                root,
+                escape: Escape {
+                    iterations: i,
+                    escaped: true,
+                    smooth: if smooth.is_finite() {
+                        smooth
+                    } else {
+                        f64::from(i)
+                    },
+                },
+            };
+        }
+        previous = distance;
+    }
+    Convergence {
+        root: None,
+        escape: Escape::inside(max_iterations),
+    }
+}
+
+/// Draw the Newton fractal of the scheme's polynomial, or z^3 - 1. Each
+/// root takes a color from the scheme's palette, or from a rainbow, and
+/// points fade to black the more iterations they took. Points that never
+/// converge are colored as the interior of an escape time set.
+pub fn newton_fractal(imgx: u32, imgy: u32, scheme: &Scheme) -> RgbaImage {
+    let viewport = scheme.viewport.unwrap_or(NEWTON_VIEWPORT);
+    let polynomial = scheme.polynomial.clone().unwrap_or_default();
+    let palette = match &scheme.palette {
+        Some(palette) => palette.clone(),
+        None => Palette::named("rainbow").unwrap_or_else(|| Palette::single_color(scheme.color)),
+    };
+    let count = polynomial.roots.len().max(1) as f64;
+    let root_colors: Vec<[u8; 3]> = (0..polynomial.roots.len())
+        .map(|k| palette.color((k as f64 + 0.5) / count))
+        .collect();
+    let interior = palette.interior.unwrap_or([0, 0, 0]);
+    let fade = fade_iterations(scheme.relaxation);
+
+    let mut imgbuf = RgbaImage::new(imgx, imgy);
+
+    supersample(&mut imgbuf, scheme.threads, scheme.sampling, |px, py| {
+        let (re, im) = viewport.to_complex(px, py, imgx, imgy);
+        let convergence = converge(
+            &polynomial,
+            Complex::new(re, im),
+            scheme.relaxation,
+            scheme.tolerance,
+            scheme.max_iterations,
+        );
+        let color = match convergence.root {
+            Some(root) => {
+                let speed = convergence.escape.value(scheme.smooth);
+                let brightness = 0.5_f64.powf(speed / fade);
+                let [r, g, b] = root_colors[root];
+                let shade = |c: u8| (f64::from(c) * brightness).round() as u8;
+                [shade(r), shade(g), shade(b)]
+            }
+            None => interior,
+        };
+        escape_pixel(&convergence.escape, color, scheme)
+    });
+
+    imgbuf
+}
+
+/// Iterations over which a root's color fades to half brightness. With
+/// relaxation a the error only shrinks by |1 - a| each step rather than
+/// squaring, so the fade is stretched to match.
+fn fade_iterations(relaxation: Complex<f64>) -> f64 {
+    let shrink = (Complex::new(1.0, 0.0) - relaxation).norm();
+    if shrink > 0.0 && shrink < 1.0 {
+        FADE_ITERATIONS * (-1.0 / shrink.ln()).max(1.0)
+    } else {
+        FADE_ITERATIONS
+    }
+}
+
+#[cfg(test)]
+mod newton_tests {
+    use super::*;
+
+    fn close(a: Complex<f64>, b: Complex<f64>) -> bool {
+        (a - b).norm() < 1e-9
+    }
+
+    #[test]
+    fn test_parse_polynomials() {
+        let cubic = Polynomial::parse("z^3 - 1").unwrap();
+        assert_eq!(4, cubic.coefficients.len());
+        assert_eq!(Complex::new(-1.0, 0.0), cubic.coefficients[0]);
+        let mixed = Polynomial::parse("(1+i)z^2 - 2.5*z + 3i - z^2").unwrap();
+        assert_eq!(Complex::new(0.0, 1.0), mixed.coefficients[2]);
+        assert_eq!(Complex::new(-2.5, 0.0), mixed.coefficients[1]);
+        assert_eq!(Complex::new(0.0, 3.0), mixed.coefficients[0]);
+        assert_eq!(
+            Complex::new(1e-3, 0.0),
+            Polynomial::parse("1e-3z^2").unwrap().coefficients[2]
+        );
+        for bad in &["", "5", "z^", "z^3 +", "2x + 1", "(1+i z", "z^99"] {
+            assert!(Polynomial::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_roots() {
+        let cubic = Polynomial::parse("z^3 - 1").unwrap();
+        assert_eq!(3, cubic.roots.len());
+        for root in &cubic.roots {
+            assert!(close(Complex::new(0.0, 0.0), cubic.evaluate(*root).0));
+        }
+        let given = Polynomial::parse_roots("1, -1, i, -0.5-2i").unwrap();
+        assert_eq!(5, given.coefficients.len());
+        for root in &given.roots {
+            assert!(close(Complex::new(0.0, 0.0), given.evaluate(*root).0));
+        }
+        assert_eq!(Ok(Complex::new(0.5, -2.0)), parse_complex("0.5-2i"));
+        assert_eq!(Ok(Complex::new(0.0, -1.0)), parse_complex("-i"));
+        assert_eq!(Ok(Complex::new(1e-3, 2.0)), parse_complex("1e-3+2i"));
+        assert!(parse_complex("1+").is_err());
+    }
+
+    #[test]
+    fn test_points_converge_to_nearest_root() {
+        let polynomial = Polynomial::parse_roots("1, -1").unwrap();
+        let one = Complex::new(1.0, 0.0);
+        let near_one = converge(&polynomial, Complex::new(0.9, 0.1), one, 1e-9, 50);
+        assert_eq!(Some(0), near_one.root);
+        let near_minus_one = converge(&polynomial, Complex::new(-2.0, 0.5), one, 1e-9, 50);
+        assert_eq!(Some(1), near_minus_one.root);
+        // Points on the line between two roots never settle.
+        let between = converge(&polynomial, Complex::new(0.0, 0.3), one, 1e-9, 50);
+        assert_eq!(None, between.root);
+        // Relaxation slows convergence down.
+        let relaxed = converge(
+            &polynomial,
+            Complex::new(0.9, 0.1),
+            Complex::new(0.5, 0.0),
+            1e-9,
+            200,
+        );
+        assert!(relaxed.escape.iterations > near_one.escape.iterations);
+    }
+}
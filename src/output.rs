@@ -0,0 +1,256 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! In-memory storage for fractals generated by the web server.
+//! Every generation is kept as encoded PNG bytes under its own id so
+//! concurrent visitors never see each other's images, and nothing is
+//! written to disk. Old images are dropped once there are too many
+//! of them or they get too old.
+
+use crate::fractal;
+use crate::util::{scheme_transforms, Scheme};
+use image::{DynamicImage, ImageOutputFormat};
+use rand::Rng;
+use std::collections::VecDeque;
+use std::convert::TryFrom;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Most fractals kept around at once.
+pub const MAX_FILES: usize = 50;
+/// Fractals older than this are dropped on the next cleanup.
+pub const MAX_AGE: Duration = Duration::from_secs(60 * 60);
+/// Bytes of the signature every PNG starts with.
+const PNG_SIGNATURE_LEN: usize = 8;
+/// Bytes of the IHDR chunk following it: length, type, 13 bytes of data and CRC.
+const IHDR_CHUNK_LEN: usize = 25;
+
+/// Make a new id for a generated fractal from the current
+/// time and some random bits, e.g. "16d5f0b2a41-3fa29c1e".
+pub fn new_id() -> String {
+    let millis = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0);
+    let salt: u32 = rand::thread_rng().gen();
+    format!("{:x}-{:08x}", millis, salt)
+}
+
+/// Encode an image as PNG bytes, ready to be sent as an HTTP body.
+pub fn encode_png(image: &DynamicImage) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::new();
+    image
+        .write_to(&mut bytes, ImageOutputFormat::PNG)
+        .map_err(|e| format!("PNG encoding failed: {}", e))?;
+    Ok(bytes)
+}
+
+/// Encode an image as PNG bytes with `tEXt` chunks holding some
+/// key/value pairs, such as the `scheme_metadata` it was drawn from.
+pub fn encode_png_with_text(
+    image: &DynamicImage,
+    text: &[(&str, String)],
+) -> Result<Vec<u8>, String> {
+    let png = encode_png(image)?;
+    // The signature and the IHDR chunk always come first.
+    let (head, tail) = png.split_at(PNG_SIGNATURE_LEN + IHDR_CHUNK_LEN);
+    let mut bytes = head.to_vec();
+    for (key, value) in text {
+        let mut data = latin1(key)?;
+        data.push(0);
+        data.extend(latin1(value)?);
+        let len = u32::try_from(data.len())
+            .map_err(|_| format!("PNG text '{}' is too long to write", key))?;
+        bytes.extend_from_slice(&len.to_be_bytes());
+        let start = bytes.len();
+        bytes.extend_from_slice(b"tEXt");
+        bytes.extend_from_slice(&data);
+        let crc = crc32(&bytes[start..]);
+        bytes.extend_from_slice(&crc.to_be_bytes());
+    }
+    bytes.extend_from_slice(tail);
+    Ok(bytes)
+}
+
+/// The settings of a scheme worth keeping with its image, mostly the
+/// randomly chosen ones needed to draw it again.
+pub fn scheme_metadata(scheme: &Scheme) -> Vec<(&'static str, String)> {
+    let mut metadata = vec![("Fractal", scheme.fractal.clone())];
+    if let Some(c) = scheme.julia_c {
+        metadata.push(("Julia constant", format!("{},{}", c.re, c.im)));
+    }
+    if let Some(fractal) = fractal::lookup(&scheme.fractal) {
+        metadata.extend(fractal.metadata(scheme));
+        if fractal.params().iter().any(|param| param.name == "points") {
+            metadata.push(("Points", scheme.points.to_string()));
+        }
+    }
+    if let Some([red, green, blue]) = scheme.rgb_iterations {
+        metadata.push(("RGB iterations", format!("{},{},{}", red, green, blue)));
+    }
+    let transforms = scheme_transforms(scheme);
+    if !transforms.is_empty() {
+        metadata.push(("Transforms", transforms.join(", ")));
+    }
+    metadata
+}
+
+/// Bytes of a `tEXt` chunk's text, which has to be Latin-1.
+fn latin1(text: &str) -> Result<Vec<u8>, String> {
+    text.chars()
+        .map(|c| u8::try_from(u32::from(c)))
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("PNG text '{}' is not Latin-1", text))
+}
+
+/// CRC-32 of a PNG chunk's type and data.
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            let mask = (crc & 1).wrapping_neg();
+            crc = (crc >> 1) ^ (0xedb8_8320 & mask);
+        }
+    }
+    !crc
+}
+
+/// A generated fractal waiting to be fetched.
+struct StoredFractal {
+    id: String,
+    created: Instant,
+    png: Arc<Vec<u8>>,
+}
+
+/// Generated fractals by id, shared between requests.
+pub struct FractalStore {
+    max_files: usize,
+    max_age: Duration,
+    fractals: Mutex<VecDeque<StoredFractal>>,
+}
+
+impl FractalStore {
+    /// An empty store keeping at most `max_files` fractals, none older than `max_age`.
+    pub fn new(max_files: usize, max_age: Duration) -> FractalStore {
+        FractalStore {
+            max_files,
+            max_age,
+            fractals: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Keep the PNG bytes of a fractal under a new id and return the id.
+    /// The cleanup policy is applied on every insert.
+    pub fn insert(&self, png: Vec<u8>) -> String {
+        let id = new_id();
+        let mut fractals = self.fractals.lock().unwrap();
+        fractals.push_back(StoredFractal {
+            id: id.clone(),
+            created: Instant::now(),
+            png: Arc::new(png),
+        });
+
+        let max_age = self.max_age;
+        fractals.retain(|fractal| fractal.created.elapsed() < max_age);
+        while fractals.len() > self.max_files {
+            fractals.pop_front();
+        }
+        id
+    }
+
+    /// The PNG bytes of the fractal with the given id, if it is still around.
+    pub fn get(&self, id: &str) -> Option<Arc<Vec<u8>>> {
+        let fractals = self.fractals.lock().unwrap();
+        fractals
+            .iter()
+            .find(|fractal| fractal.id == id && fractal.created.elapsed() < self.max_age)
+            .map(|fractal| Arc::clone(&fractal.png))
+    }
+}
+
+impl Default for FractalStore {
+    fn default() -> FractalStore {
+        FractalStore::new(MAX_FILES, MAX_AGE)
+    }
+}
+
+#[cfg(test)]
+mod output_tests {
+    use super::*;
+
+    /// Number of fractals currently kept.
+    fn count(store: &FractalStore) -> usize {
+        store.fractals.lock().unwrap().len()
+    }
+
+    #[test]
+    fn test_ids_are_unique() {
+        assert_ne!(new_id(), new_id());
+    }
+
+    #[test]
+    fn test_store_returns_inserted_bytes() {
+        let store = FractalStore::default();
+        let id = store.insert(vec![1, 2, 3]);
+        assert_eq!(vec![1, 2, 3], *store.get(&id).unwrap());
+        assert!(store.get("unknown").is_none());
+    }
+
+    #[test]
+    fn test_store_keeps_max_files() {
+        let store = FractalStore::new(2, MAX_AGE);
+        let oldest = store.insert(vec![0]);
+        store.insert(vec![1]);
+        store.insert(vec![2]);
+        assert_eq!(2, count(&store));
+        assert!(store.get(&oldest).is_none());
+    }
+
+    #[test]
+    fn test_store_drops_old_fractals() {
+        let store = FractalStore::new(MAX_FILES, Duration::from_secs(0));
+        let id = store.insert(vec![0]);
+        assert!(store.get(&id).is_none());
+    }
+
+    #[test]
+    fn test_encode_png_signature() {
+        let image = DynamicImage::new_rgba8(4, 3);
+        let bytes = encode_png(&image).unwrap();
+        assert_eq!(&[0x89, b'P', b'N', b'G'], &bytes[..4]);
+    }
+
+    #[test]
+    fn test_png_text_chunks() {
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+        let image = DynamicImage::new_rgba8(4, 3);
+        let text = [("Julia constant", "-0.8,0.156".to_string())];
+        let bytes = encode_png_with_text(&image, &text).unwrap();
+        assert_eq!(&b"tEXtJulia constant\0-0.8,0.156"[..], &bytes[37..66]);
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.raw_pixels(), decoded.raw_pixels());
+    }
+
+    #[test]
+    fn test_png_text_must_be_latin1() {
+        let image = DynamicImage::new_rgba8(4, 3);
+        let latin1 = [("Fractal", "m\u{e9}lange".to_string())];
+        let bytes = encode_png_with_text(&image, &latin1).unwrap();
+        assert_eq!(&b"tEXtFractal\0m\xe9lange"[..], &bytes[37..56]);
+        let not_latin1 = [("Fractal", "\u{3bb}".to_string())];
+        assert!(encode_png_with_text(&image, &not_latin1).is_err());
+    }
+
+    #[test]
+    fn test_metadata_records_transforms() {
+        let mut scheme = Scheme::default();
+        assert!(scheme_metadata(&scheme)
+            .iter()
+            .all(|(key, _)| *key != "Transforms"));
+        scheme.do_transform = true;
+        scheme.transform = "invert".to_string();
+        scheme.random_transforms = vec!["blur".to_string(), "rotate180".to_string()];
+        assert!(scheme_metadata(&scheme)
+            .contains(&("Transforms", "invert, blur, rotate180".to_string())));
+    }
+}
@@ -0,0 +1,496 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Palettes for coloring fractals. A palette is a gradient of color
+//! stops, blended in RGB, HSV or CIE Lab space, which a value in 0..1
+//! is looked up in after being scaled by a density and shifted by an
+//! offset. The result either wraps around (cyclic) or sticks at the
+//! ends of the gradient (clamped).
+
+// Color space conversions: https://en.wikipedia.org/wiki/HSL_and_HSV
+// and https://en.wikipedia.org/wiki/CIELAB_color_space
+
+use crate::escape::Escape;
+use crate::util::*;
+
+/// Names of the built in palettes.
+pub const PALETTE_NAMES: [&str; 8] = [
+    "fire",
+    "ocean",
+    "grayscale",
+    "rainbow",
+    "electric",
+    "forest",
+    "sunset",
+    "ultra",
+];
+
+/// Color space the colors between two stops are blended in.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Interpolation {
+    /// Straight blend of the red, green and blue channels.
+    Linear,
+    /// Blend of hue, saturation and value, going the short way around the hue wheel.
+    Hsv,
+    /// Blend in the perceptually uniform CIE Lab space.
+    Lab,
+}
+
+/// What happens to values that fall outside of 0..1.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapping {
+    /// Values wrap around, repeating the gradient.
+    Cyclic,
+    /// Values stick to the first or last color.
+    Clamped,
+}
+
+/// A color at some position of a gradient.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stop {
+    /// Position from 0 (start) to 1 (end).
+    pub position: f64,
+    pub color: [u8; 3],
+}
+
+/// Colors blended between a list of stops.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Gradient {
+    /// Stops sorted by position.
+    pub stops: Vec<Stop>,
+    pub interpolation: Interpolation,
+}
+
+impl Gradient {
+    /// A gradient with evenly spaced stops.
+    pub fn even(colors: &[[u8; 3]], interpolation: Interpolation) -> Gradient {
+        let last = (colors.len().max(2) - 1) as f64;
+        let stops = colors
+            .iter()
+            .enumerate()
+            .map(|(i, color)| Stop {
+                position: i as f64 / last,
+                color: *color,
+            })
+            .collect();
+        Gradient {
+            stops,
+            interpolation,
+        }
+    }
+
+    /// The color at position `t`, clamped to 0..1.
+    pub fn sample(&self, t: f64) -> [u8; 3] {
+        let t = if t.is_nan() { 0.0 } else { t.clamp(0.0, 1.0) };
+        let first = match self.stops.first() {
+            Some(stop) => stop,
+            None => return [0, 0, 0],
+        };
+        if t <= first.position {
+            return first.color;
+        }
+
+        for pair in self.stops.windows(2) {
+            let (from, to) = (pair[0], pair[1]);
+            if t <= to.position {
+                let width = to.position - from.position;
+                let local = if width > 0.0 {
+                    (t - from.position) / width
+                } else {
+                    1.0
+                };
+                return blend(from.color, to.color, local, self.interpolation);
+            }
+        }
+        self.stops[self.stops.len() - 1].color
+    }
+}
+
+/// A gradient plus how values are mapped onto it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Palette {
+    pub gradient: Gradient,
+    pub mapping: Mapping,
+    /// Shift added to every value after scaling, moving colors along the gradient.
+    pub offset: f64,
+    /// How many times the gradient is passed through over 0..1.
+    pub density: f64,
+    /// Color of points inside an escape time set, or `None` to use the end of the gradient.
+    pub interior: Option<[u8; 3]>,
+}
+
+impl Palette {
+    /// A clamped palette over a gradient, with no offset and a density of 1.
+    pub fn new(gradient: Gradient) -> Palette {
+        Palette {
+            gradient,
+            mapping: Mapping::Clamped,
+            offset: 0.0,
+            density: 1.0,
+            interior: None,
+        }
+    }
+
+    /// A palette fading from black to a single color, the way
+    /// fractals were colored before palettes existed.
+    pub fn single_color(color: Color) -> Palette {
+        Palette::new(Gradient::even(
+            &[[0, 0, 0], color_to_rgb(color)],
+            Interpolation::Linear,
+        ))
+    }
+
+    /// Look up one of the built in palettes by name.
+    pub fn named(name: &str) -> Option<Palette> {
+        let (colors, interpolation): (&[[u8; 3]], Interpolation) = match name {
+            "fire" => (
+                &[
+                    [0, 0, 0],
+                    [128, 0, 0],
+                    [255, 64, 0],
+                    [255, 200, 0],
+                    [255, 255, 220],
+                ],
+                Interpolation::Linear,
+            ),
+            "ocean" => (
+                &[[0, 7, 30], [0, 60, 120], [0, 160, 200], [180, 240, 255]],
+                Interpolation::Lab,
+            ),
+            "grayscale" => (&[[0, 0, 0], [255, 255, 255]], Interpolation::Linear),
+            "rainbow" => (
+                &[
+                    [255, 0, 0],
+                    [255, 255, 0],
+                    [0, 255, 0],
+                    [0, 255, 255],
+                    [0, 0, 255],
+                    [255, 0, 255],
+                ],
+                Interpolation::Hsv,
+            ),
+            "electric" => (
+                &[[0, 0, 0], [40, 0, 120], [0, 120, 255], [255, 255, 255]],
+                Interpolation::Lab,
+            ),
+            "forest" => (
+                &[[10, 20, 5], [30, 90, 30], [140, 190, 60], [240, 230, 160]],
+                Interpolation::Lab,
+            ),
+            "sunset" => (
+                &[[20, 10, 60], [150, 30, 110], [250, 90, 60], [255, 210, 120]],
+                Interpolation::Lab,
+            ),
+            "ultra" => (
+                &[
+                    [0, 7, 100],
+                    [32, 107, 203],
+                    [237, 255, 255],
+                    [255, 170, 0],
+                    [0, 2, 0],
+                ],
+                Interpolation::Linear,
+            ),
+            _ => return None,
+        };
+        let mut palette = Palette::new(Gradient::even(colors, interpolation));
+        if name == "ultra" || name == "rainbow" {
+            palette.mapping = Mapping::Cyclic;
+        }
+        palette.interior = Some([0, 0, 0]);
+        Some(palette)
+    }
+
+    /// Parse either a built in palette name or a list of color stops such as
+    /// "black,red@0.3,yellow". Stops may be any color `parse_color` accepts,
+    /// e.g. "#000,rgb(255, 136, 0)@0.4,coral". Stops without a position are spaced evenly.
+    pub fn parse(spec: &str) -> Result<Palette, String> {
+        let spec = spec.trim().to_lowercase();
+        if let Some(palette) = Palette::named(&spec) {
+            return Ok(palette);
+        }
+
+        let fields: Vec<&str> = split_list(&spec, ',');
+        if fields.len() < 2 {
+            return Err(format!(
+                "{}, or at least two comma separated colors",
+                unknown_name("palette", &spec, &PALETTE_NAMES)
+            ));
+        }
+
+        let last = (fields.len() - 1) as f64;
+        let mut stops = Vec::new();
+        for (i, field) in fields.iter().enumerate() {
+            let mut parts = field.splitn(2, '@');
+            let color = parse_color(parts.next().unwrap_or(""))?;
+            let position = match parts.next() {
+                Some(position) => match position.trim().parse::<f64>() {
+                    Ok(p) if (0.0..=1.0).contains(&p) => p,
+                    _ => {
+                        return Err(format!(
+                            "Invalid stop position '{}', expected a number from 0 to 1",
+                            position
+                        ))
+                    }
+                },
+                None => i as f64 / last,
+            };
+            if stops
+                .last()
+                .is_some_and(|stop: &Stop| stop.position > position)
+            {
+                return Err(format!("Palette stops in '{}' are out of order", spec));
+            }
+            stops.push(Stop {
+                position,
+                color: color_to_rgb(color),
+            });
+        }
+
+        Ok(Palette::new(Gradient {
+            stops,
+            interpolation: Interpolation::Linear,
+        }))
+    }
+
+    /// The color for a value, normally in 0..1.
+    pub fn color(&self, t: f64) -> [u8; 3] {
+        let t = t * self.density + self.offset;
+        let t = match self.mapping {
+            Mapping::Cyclic => t.rem_euclid(1.0),
+            Mapping::Clamped => t,
+        };
+        self.gradient.sample(t)
+    }
+
+    /// The color for a point of an escape time fractal.
+    pub fn escape_color(&self, escape: &Escape, max_iterations: u32, smooth: bool) -> [u8; 3] {
+        match self.interior {
+            Some(interior) if !escape.escaped => interior,
+            _ => self.color(escape.value(smooth) / f64::from(max_iterations.max(1))),
+        }
+    }
+}
+
+/// Parse the color space used to blend between palette stops.
+pub fn parse_interpolation(name: &str) -> Result<Interpolation, String> {
+    match name.trim().to_lowercase().as_str() {
+        "linear" | "rgb" => Ok(Interpolation::Linear),
+        "hsv" => Ok(Interpolation::Hsv),
+        "lab" => Ok(Interpolation::Lab),
+        other => Err(unknown_name(
+            "interpolation",
+            other,
+            &["linear", "hsv", "lab"],
+        )),
+    }
+}
+
+/// Parse how values outside of 0..1 are mapped onto a palette.
+pub fn parse_mapping(name: &str) -> Result<Mapping, String> {
+    match name.trim().to_lowercase().as_str() {
+        "cyclic" => Ok(Mapping::Cyclic),
+        "clamped" => Ok(Mapping::Clamped),
+        other => Err(unknown_name("mapping", other, &["cyclic", "clamped"])),
+    }
+}
+
+/// Blend two colors, `t` of the way from `from` to `to`.
+fn blend(from: [u8; 3], to: [u8; 3], t: f64, interpolation: Interpolation) -> [u8; 3] {
+    let mix = |a: f64, b: f64| a + (b - a) * t;
+    match interpolation {
+        Interpolation::Linear => {
+            let channel = |i: usize| mix(f64::from(from[i]), f64::from(to[i])).round() as u8;
+            [channel(0), channel(1), channel(2)]
+        }
+        Interpolation::Hsv => {
+            let (h1, s1, v1) = rgb_to_hsv(from);
+            let (h2, s2, v2) = rgb_to_hsv(to);
+            let mut dh = h2 - h1;
+            if dh > 180.0 {
+                dh -= 360.0;
+            } else if dh < -180.0 {
+                dh += 360.0;
+            }
+            hsv_to_rgb((h1 + dh * t).rem_euclid(360.0), mix(s1, s2), mix(v1, v2))
+        }
+        Interpolation::Lab => {
+            let (l1, a1, b1) = rgb_to_lab(from);
+            let (l2, a2, b2) = rgb_to_lab(to);
+            lab_to_rgb(mix(l1, l2), mix(a1, a2), mix(b1, b2))
+        }
+    }
+}
+
+/// Convert a 0..1 channel value to a u8, rounding and clamping.
+fn to_channel(value: f64) -> u8 {
+    (value * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+/// RGB to hue (degrees), saturation and value (0..1).
+fn rgb_to_hsv(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let [r, g, b] = [
+        f64::from(rgb[0]) / 255.0,
+        f64::from(rgb[1]) / 255.0,
+        f64::from(rgb[2]) / 255.0,
+    ];
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    let hue = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let saturation = if max == 0.0 { 0.0 } else { delta / max };
+    (hue, saturation, max)
+}
+
+/// Hue (degrees), saturation and value (0..1) to RGB.
+fn hsv_to_rgb(hue: f64, saturation: f64, value: f64) -> [u8; 3] {
+    let chroma = value * saturation;
+    let sector = hue / 60.0;
+    let x = chroma * (1.0 - (sector.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match sector as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let m = value - chroma;
+    [to_channel(r + m), to_channel(g + m), to_channel(b + m)]
+}
+
+/// D65 reference white in XYZ.
+const WHITE: [f64; 3] = [0.95047, 1.0, 1.08883];
+
+/// sRGB to CIE Lab.
+fn rgb_to_lab(rgb: [u8; 3]) -> (f64, f64, f64) {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(rgb[0]), linear(rgb[1]), linear(rgb[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / WHITE[0];
+    let y = (0.2126 * r + 0.7152 * g + 0.0722 * b) / WHITE[1];
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / WHITE[2];
+
+    let f = |t: f64| {
+        let delta: f64 = 6.0 / 29.0;
+        if t > delta.powi(3) {
+            t.cbrt()
+        } else {
+            t / (3.0 * delta * delta) + 4.0 / 29.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
+
+/// CIE Lab to sRGB, clamping colors outside of the sRGB gamut.
+fn lab_to_rgb(l: f64, a: f64, b: f64) -> [u8; 3] {
+    let f_inv = |t: f64| {
+        let delta: f64 = 6.0 / 29.0;
+        if t > delta {
+            t.powi(3)
+        } else {
+            3.0 * delta * delta * (t - 4.0 / 29.0)
+        }
+    };
+    let fy = (l + 16.0) / 116.0;
+    let x = WHITE[0] * f_inv(fy + a / 500.0);
+    let y = WHITE[1] * f_inv(fy);
+    let z = WHITE[2] * f_inv(fy - b / 200.0);
+
+    let gamma = |c: f64| {
+        if c <= 0.003_130_8 {
+            12.92 * c
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    };
+    let r = 3.2406 * x - 1.5372 * y - 0.4986 * z;
+    let g = -0.9689 * x + 1.8758 * y + 0.0415 * z;
+    let b = 0.0557 * x - 0.2040 * y + 1.0570 * z;
+    [
+        to_channel(gamma(r)),
+        to_channel(gamma(g)),
+        to_channel(gamma(b)),
+    ]
+}
+
+#[cfg(test)]
+mod palette_tests {
+    use super::*;
+
+    #[test]
+    fn test_gradient_ends_and_middle() {
+        let gradient = Gradient::even(&[[0, 0, 0], [200, 100, 50]], Interpolation::Linear);
+        assert_eq!([0, 0, 0], gradient.sample(-1.0));
+        assert_eq!([100, 50, 25], gradient.sample(0.5));
+        assert_eq!([200, 100, 50], gradient.sample(2.0));
+    }
+
+    #[test]
+    fn test_color_spaces_keep_stop_colors() {
+        for &interpolation in &[
+            Interpolation::Linear,
+            Interpolation::Hsv,
+            Interpolation::Lab,
+        ] {
+            let gradient = Gradient::even(&[[255, 0, 0], [0, 0, 255]], interpolation);
+            assert_eq!([255, 0, 0], gradient.sample(0.0));
+            assert_eq!([0, 0, 255], gradient.sample(1.0));
+        }
+    }
+
+    #[test]
+    fn test_hsv_goes_around_the_hue_wheel() {
+        // Red to blue the short way passes through magenta, not green.
+        let gradient = Gradient::even(&[[255, 0, 0], [0, 0, 255]], Interpolation::Hsv);
+        assert_eq!([255, 0, 255], gradient.sample(0.5));
+    }
+
+    #[test]
+    fn test_cyclic_and_clamped_mapping() {
+        let mut palette = Palette::single_color(Color::White);
+        palette.density = 2.0;
+        assert_eq!([255, 255, 255], palette.color(0.75));
+        palette.mapping = Mapping::Cyclic;
+        assert_eq!([128, 128, 128], palette.color(0.75));
+        palette.offset = 0.5;
+        assert_eq!([0, 0, 0], palette.color(0.75));
+    }
+
+    #[test]
+    fn test_parse_palettes() {
+        assert_eq!(Palette::named("fire"), Palette::parse("Fire").ok());
+        let palette = Palette::parse("black, red@0.25, white").unwrap();
+        assert_eq!(0.25, palette.gradient.stops[1].position);
+        assert_eq!(1.0, palette.gradient.stops[2].position);
+        assert!(Palette::parse("plaid").is_err());
+        assert!(Palette::parse("black,red@0.8,white@0.5").is_err());
+        assert!(Palette::parse("black,plaid").is_err());
+        let palette = Palette::parse("#000, rgb(255, 136, 0)@0.4, coral").unwrap();
+        assert_eq!([255, 136, 0], palette.gradient.stops[1].color);
+        assert_eq!([255, 127, 80], palette.gradient.stops[2].color);
+    }
+
+    #[test]
+    fn test_interior_color() {
+        let palette = Palette::named("ultra").unwrap();
+        assert_eq!(
+            [0, 0, 0],
+            palette.escape_color(&Escape::inside(100), 100, false)
+        );
+    }
+}
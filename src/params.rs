@@ -0,0 +1,1063 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Turns the query parameters of a `/gen` request into a fractal scheme.
+//! Any parameter left out is filled in randomly, while any parameter
+//! that is present but invalid is reported back to the user.
+
+use crate::background::*;
+use crate::density::{parse_estimator, parse_points};
+use crate::flame::Flame;
+use crate::fractal;
+use crate::ifs::Ifs;
+use crate::julias::parse_julia_constant;
+use crate::layers::*;
+use crate::newton::{parse_complex, Polynomial};
+use crate::palette::*;
+use crate::precision::*;
+use crate::sampling::*;
+use crate::util::*;
+use crate::viewport::parse_point;
+
+/// Width and height used when a request does not give one.
+pub const DEFAULT_DIMENSION: u32 = 1000;
+/// Largest width or height the web server is willing to draw.
+pub const MAX_DIMENSION: u32 = 4000;
+/// Largest iteration limit an escape time fractal may be given.
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Raw query parameters of a `/gen` request, all optional.
+#[derive(Debug, Default, PartialEq)]
+pub struct GenParams {
+    pub fractal: Option<String>,
+    pub width: Option<String>,
+    pub height: Option<String>,
+    pub color: Option<String>,
+    pub bg: Option<String>,
+    pub bg_gradient: Option<String>,
+    pub bg_shape: Option<String>,
+    pub bg_angle: Option<String>,
+    pub transform: Option<String>,
+    pub center: Option<String>,
+    pub span: Option<String>,
+    pub zoom: Option<String>,
+    pub rotation: Option<String>,
+    pub iterations: Option<String>,
+    pub bailout: Option<String>,
+    pub smooth: Option<String>,
+    pub samples: Option<String>,
+    pub sampling: Option<String>,
+    pub precision: Option<String>,
+    pub polynomial: Option<String>,
+    pub roots: Option<String>,
+    pub relaxation: Option<String>,
+    pub tolerance: Option<String>,
+    pub c: Option<String>,
+    pub exponent: Option<String>,
+    pub ifs: Option<String>,
+    pub maps: Option<String>,
+    pub points: Option<String>,
+    pub gamma: Option<String>,
+    pub flame: Option<String>,
+    pub transforms: Option<String>,
+    pub estimator: Option<String>,
+    pub rgb_iterations: Option<String>,
+    pub palette: Option<String>,
+    pub interpolation: Option<String>,
+    pub mapping: Option<String>,
+    pub offset: Option<String>,
+    pub density: Option<String>,
+    pub interior: Option<String>,
+    pub blend: Option<String>,
+    pub fractal_only: Option<String>,
+}
+
+/// Everything needed to draw the requested fractal.
+#[derive(Debug)]
+pub struct Generation {
+    pub scheme: Scheme,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl GenParams {
+    /// Collect parameters from key/value pairs such as a query string.
+    pub fn from_pairs<I>(pairs: I) -> Result<GenParams, String>
+    where
+        I: IntoIterator<Item = (String, String)>,
+    {
+        let mut params = GenParams::default();
+        for (key, value) in pairs {
+            params.set(&key, &value)?;
+        }
+        Ok(params)
+    }
+
+    /// Set one parameter by name.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let field = match key {
+            "fractal" => &mut self.fractal,
+            "width" => &mut self.width,
+            "height" => &mut self.height,
+            "color" => &mut self.color,
+            "bg" => &mut self.bg,
+            "bg-gradient" => &mut self.bg_gradient,
+            "bg-shape" => &mut self.bg_shape,
+            "bg-angle" => &mut self.bg_angle,
+            "transform" => &mut self.transform,
+            "center" => &mut self.center,
+            "span" => &mut self.span,
+            "zoom" => &mut self.zoom,
+            "rotation" => &mut self.rotation,
+            "iterations" => &mut self.iterations,
+            "bailout" => &mut self.bailout,
+            "smooth" => &mut self.smooth,
+            "samples" => &mut self.samples,
+            "sampling" => &mut self.sampling,
+            "precision" => &mut self.precision,
+            "polynomial" => &mut self.polynomial,
+            "roots" => &mut self.roots,
+            "relaxation" => &mut self.relaxation,
+            "tolerance" => &mut self.tolerance,
+            "c" => &mut self.c,
+            "exponent" => &mut self.exponent,
+            "ifs" => &mut self.ifs,
+            "maps" => &mut self.maps,
+            "points" => &mut self.points,
+            "gamma" => &mut self.gamma,
+            "flame" => &mut self.flame,
+            "transforms" => &mut self.transforms,
+            "estimator" => &mut self.estimator,
+            "rgb-iterations" => &mut self.rgb_iterations,
+            "palette" => &mut self.palette,
+            "interpolation" => &mut self.interpolation,
+            "mapping" => &mut self.mapping,
+            "offset" => &mut self.offset,
+            "density" => &mut self.density,
+            "interior" => &mut self.interior,
+            "blend" => &mut self.blend,
+            "fractal-only" => &mut self.fractal_only,
+            _ => return Err(format!("Unknown parameter '{}'", key)),
+        };
+        *field = Some(value.to_string());
+        Ok(())
+    }
+
+    /// Set the parameter the chosen fractal reads from a request body,
+    /// such as the maps of an IFS.
+    pub fn set_body(&mut self, body: &str) -> Result<(), String> {
+        let name = self
+            .fractal
+            .as_ref()
+            .ok_or("A request body needs the fractal it describes")?;
+        let fractal = parse_fractal(name)?;
+        match fractal.body_param() {
+            Some(key) => self.set(key, body),
+            None => Err(format!(
+                "The {} fractal does not take a request body",
+                fractal.name()
+            )),
+        }
+    }
+
+    /// Validate the parameters and build a scheme from them,
+    /// randomizing only the properties the user left out.
+    pub fn to_generation(&self) -> Result<Generation, String> {
+        let fractal = match &self.fractal {
+            Some(name) => parse_fractal(name)?,
+            None => fractal::random(),
+        };
+        let width = parse_dimension("width", &self.width)?;
+        let height = parse_dimension("height", &self.height)?;
+
+        let mut scheme = Scheme {
+            fractal: fractal.name().to_string(),
+            ..Default::default()
+        };
+        randomize(&mut scheme);
+
+        if let Some(color) = &self.color {
+            let color = parse_color(color)?;
+            if !fractal.supports_color(color) {
+                return Err(format!(
+                    "The {} fractal can not be drawn in {}",
+                    scheme.fractal,
+                    color_to_str(color)
+                ));
+            }
+            scheme.color = color;
+        }
+
+        if let Some(bg) = &self.bg {
+            scheme.fancy_background = false;
+            scheme.bg_color = parse_color(bg)?;
+        }
+
+        let gradient_params = [&self.bg_gradient, &self.bg_shape, &self.bg_angle];
+        if gradient_params.iter().any(|param| param.is_some()) {
+            if self.bg.is_some() {
+                return Err("A solid bg can not be given a gradient".to_string());
+            }
+            // A shape or angle alone reshapes the random background colors.
+            let mut gradient = match &self.bg_gradient {
+                Some(stops) => BackgroundGradient::parse(stops)?,
+                None => BackgroundGradient::two_color(scheme.bg_color, scheme.bg_color_2),
+            };
+            if let Some(shape) = &self.bg_shape {
+                gradient.shape = parse_shape(shape)?;
+            }
+            if let Some(angle) = &self.bg_angle {
+                gradient.angle = parse_number("bg-angle", angle)?;
+            }
+            scheme.fancy_background = true;
+            scheme.bg_gradient = Some(gradient);
+        }
+
+        match &self.transform {
+            Some(transform) => {
+                scheme.transform = parse_transform(transform)?;
+                scheme.do_transform = scheme.transform != "none";
+            }
+            // Only a fractal picked at random is given random transforms.
+            None if self.fractal.is_none() => scheme.random_transforms = pick_random_transforms(),
+            None => (),
+        }
+
+        let palette_params = [
+            &self.palette,
+            &self.interpolation,
+            &self.mapping,
+            &self.offset,
+            &self.density,
+            &self.interior,
+        ];
+        if palette_params.iter().any(|param| param.is_some()) {
+            // Tweaks without a palette adjust the fade to the fractal's color.
+            let mut palette = match &self.palette {
+                Some(palette) => Palette::parse(palette)?,
+                None => Palette::single_color(scheme.color),
+            };
+            if let Some(interpolation) = &self.interpolation {
+                palette.gradient.interpolation = parse_interpolation(interpolation)?;
+            }
+            if let Some(mapping) = &self.mapping {
+                palette.mapping = parse_mapping(mapping)?;
+            }
+            if let Some(offset) = &self.offset {
+                palette.offset = parse_number("offset", offset)?;
+            }
+            if let Some(density) = &self.density {
+                palette.density = parse_positive("density", density)?;
+            }
+            match self.interior.as_ref().map(|interior| interior.trim()) {
+                Some("transparent") => scheme.transparent_interior = true,
+                Some(interior) => palette.interior = Some(color_to_rgb(parse_color(interior)?)),
+                None => (),
+            }
+            scheme.palette = Some(palette);
+        }
+
+        if let Some(blend) = &self.blend {
+            scheme.blend = Some(parse_blend_mode(blend)?);
+        }
+        if let Some(fractal_only) = &self.fractal_only {
+            scheme.fractal_only = parse_bool("fractal-only", fractal_only)?;
+        }
+
+        let escape_time_params = [
+            &self.center,
+            &self.span,
+            &self.zoom,
+            &self.rotation,
+            &self.iterations,
+            &self.bailout,
+            &self.smooth,
+            &self.precision,
+        ];
+        if escape_time_params.iter().any(|param| param.is_some()) {
+            let mut viewport = fractal.default_viewport().ok_or_else(|| {
+                format!(
+                    "The {} fractal does not take a viewport or iterations",
+                    scheme.fractal
+                )
+            })?;
+            if let Some(center) = &self.center {
+                let (re, im) = parse_point(center)?;
+                viewport.center_re = re;
+                viewport.center_im = im;
+                let mut parts = center.split(',').map(|part| part.trim().to_string());
+                scheme.exact_center = parts.next().zip(parts.next());
+            }
+            if let Some(span) = &self.span {
+                viewport.span = parse_positive("span", span)?;
+            }
+            if let Some(zoom) = &self.zoom {
+                viewport = viewport.zoomed(parse_positive("zoom", zoom)?);
+            }
+            if let Some(rotation) = &self.rotation {
+                viewport.rotation = parse_number("rotation", rotation)?;
+            }
+            scheme.viewport = Some(viewport);
+
+            if let Some(iterations) = &self.iterations {
+                scheme.max_iterations = parse_iterations(iterations)?;
+            }
+            if let Some(bailout) = &self.bailout {
+                scheme.bailout = parse_positive("bailout", bailout)?;
+            }
+            if let Some(smooth) = &self.smooth {
+                scheme.smooth = parse_bool("smooth", smooth)?;
+            }
+            if let Some(precision) = &self.precision {
+                scheme.precision = parse_precision(precision)?;
+            }
+        }
+
+        // Escape time fractals take a sampling mode, density ones only samples.
+        if self.samples.is_some() || self.sampling.is_some() {
+            let takes = |name| fractal.params().iter().any(|param| param.name == name);
+            if !takes("samples") || (self.sampling.is_some() && !takes("sampling")) {
+                return Err(format!(
+                    "The {} fractal does not take {}",
+                    scheme.fractal,
+                    if takes("samples") {
+                        "a sampling mode"
+                    } else {
+                        "samples"
+                    }
+                ));
+            }
+            let samples = match &self.samples {
+                Some(samples) => parse_samples(samples)?,
+                None => DEFAULT_SAMPLES,
+            };
+            let mode = self.sampling.as_ref().map_or("grid", |mode| mode.as_str());
+            scheme.sampling = parse_sampling(mode, samples)?;
+        }
+
+        if self.points.is_some() || self.gamma.is_some() {
+            if !fractal.params().iter().any(|param| param.name == "points") {
+                return Err(format!(
+                    "The {} fractal does not take points or gamma",
+                    scheme.fractal
+                ));
+            }
+            if let Some(points) = &self.points {
+                scheme.points = parse_points(points)?;
+            }
+            if let Some(gamma) = &self.gamma {
+                scheme.gamma = parse_positive("gamma", gamma)?;
+            }
+        }
+
+        if let Some(rgb_iterations) = &self.rgb_iterations {
+            if !fractal
+                .params()
+                .iter()
+                .any(|param| param.name == "rgb-iterations")
+            {
+                return Err(format!(
+                    "The {} fractal does not take rgb-iterations",
+                    scheme.fractal
+                ));
+            }
+            let limits = rgb_iterations
+                .split(',')
+                .map(parse_iterations)
+                .collect::<Result<Vec<u32>, String>>()?;
+            scheme.rgb_iterations = match limits.as_slice() {
+                &[red, green, blue] => Some([red, green, blue]),
+                _ => {
+                    return Err(format!(
+                        "Invalid rgb-iterations '{}', expected <red>,<green>,<blue>",
+                        rgb_iterations
+                    ))
+                }
+            };
+        }
+
+        let newton_params = [
+            &self.polynomial,
+            &self.roots,
+            &self.relaxation,
+            &self.tolerance,
+        ];
+        if newton_params.iter().any(|param| param.is_some()) {
+            if !fractal
+                .params()
+                .iter()
+                .any(|param| param.name == "polynomial")
+            {
+                return Err(format!(
+                    "The {} fractal does not take a polynomial, relaxation or tolerance",
+                    scheme.fractal
+                ));
+            }
+            scheme.polynomial = match (&self.polynomial, &self.roots) {
+                (Some(_), Some(_)) => {
+                    return Err(
+                        "A polynomial can not be given both as terms and as roots".to_string()
+                    )
+                }
+                (Some(polynomial), None) => Some(Polynomial::parse(polynomial)?),
+                (None, Some(roots)) => Some(Polynomial::parse_roots(roots)?),
+                (None, None) => None,
+            };
+            if let Some(relaxation) = &self.relaxation {
+                scheme.relaxation = parse_complex(relaxation)?;
+                // A relaxation of zero would never move a point.
+                if scheme.relaxation.norm() == 0.0 {
+                    return Err(format!(
+                        "Invalid relaxation '{}', expected a nonzero complex number",
+                        relaxation
+                    ));
+                }
+            }
+            if let Some(tolerance) = &self.tolerance {
+                scheme.tolerance = parse_positive("tolerance", tolerance)?;
+            }
+        }
+
+        if let Some(c) = &self.c {
+            if !fractal.params().iter().any(|param| param.name == "c") {
+                return Err(format!(
+                    "The {} fractal does not take a Julia constant",
+                    scheme.fractal
+                ));
+            }
+            scheme.julia_c = Some(parse_julia_constant(c)?);
+        }
+        if let Some(exponent) = &self.exponent {
+            if !fractal
+                .params()
+                .iter()
+                .any(|param| param.name == "exponent")
+            {
+                return Err(format!(
+                    "The {} fractal does not take an exponent",
+                    scheme.fractal
+                ));
+            }
+            scheme.exponent = parse_complex(exponent)?;
+        }
+
+        if self.ifs.is_some() || self.maps.is_some() {
+            if !fractal.params().iter().any(|param| param.name == "maps") {
+                return Err(format!(
+                    "The {} fractal does not take IFS maps",
+                    scheme.fractal
+                ));
+            }
+            scheme.ifs = match (&self.ifs, &self.maps) {
+                (Some(_), Some(_)) => {
+                    return Err("An IFS can not be both a preset and maps".to_string())
+                }
+                (Some(preset), None) => Some(Ifs::preset(preset)?),
+                (_, Some(maps)) => Some(Ifs::parse(maps)?),
+                (None, None) => None,
+            };
+        }
+
+        if self.flame.is_some() || self.transforms.is_some() || self.estimator.is_some() {
+            if !fractal
+                .params()
+                .iter()
+                .any(|param| param.name == "transforms")
+            {
+                return Err(format!(
+                    "The {} fractal does not take flame transforms",
+                    scheme.fractal
+                ));
+            }
+            scheme.flame = match (&self.flame, &self.transforms) {
+                (Some(_), Some(_)) => {
+                    return Err("A flame can not be both a preset and transforms".to_string())
+                }
+                (Some(preset), None) => Some(Flame::preset(preset)?),
+                (_, Some(transforms)) => Some(Flame::parse(transforms)?),
+                (None, None) => None,
+            };
+            if let Some(estimator) = &self.estimator {
+                scheme.estimator = parse_estimator(estimator)?;
+            }
+        }
+
+        Ok(Generation {
+            scheme,
+            width,
+            height,
+        })
+    }
+}
+
+/// Look a fractal name up in the registry.
+fn parse_fractal(name: &str) -> Result<&'static dyn fractal::Fractal, String> {
+    let name = name.trim().to_lowercase();
+    fractal::lookup(&name).ok_or_else(|| unknown_name("fractal", &name, &fractal::names()))
+}
+
+/// Parse an image dimension, using the default if it was left out.
+fn parse_dimension(label: &str, value: &Option<String>) -> Result<u32, String> {
+    let value = match value {
+        Some(value) => value,
+        None => return Ok(DEFAULT_DIMENSION),
+    };
+    match value.trim().parse::<u32>() {
+        Ok(n) if n > 0 && n <= MAX_DIMENSION => Ok(n),
+        _ => Err(format!(
+            "Invalid {} '{}', expected a number from 1 to {}",
+            label, value, MAX_DIMENSION
+        )),
+    }
+}
+
+/// Parse an escape time iteration limit.
+fn parse_iterations(value: &str) -> Result<u32, String> {
+    match value.trim().parse::<u32>() {
+        Ok(n) if n > 0 && n <= MAX_ITERATIONS => Ok(n),
+        _ => Err(format!(
+            "Invalid iterations '{}', expected a number from 1 to {}",
+            value, MAX_ITERATIONS
+        )),
+    }
+}
+
+/// Parse a yes/no flag.
+fn parse_bool(label: &str, value: &str) -> Result<bool, String> {
+    match value.trim().to_lowercase().as_str() {
+        "true" | "yes" | "1" | "on" => Ok(true),
+        "false" | "no" | "0" | "off" => Ok(false),
+        _ => Err(format!(
+            "Invalid {} '{}', expected true or false",
+            label, value
+        )),
+    }
+}
+
+/// Parse a finite number.
+fn parse_number(label: &str, value: &str) -> Result<f64, String> {
+    match value.trim().parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!("Invalid {} '{}', expected a number", label, value)),
+    }
+}
+
+/// Parse a finite number greater than zero.
+fn parse_positive(label: &str, value: &str) -> Result<f64, String> {
+    match parse_number(label, value) {
+        Ok(n) if n > 0.0 => Ok(n),
+        _ => Err(format!(
+            "Invalid {} '{}', expected a number greater than 0",
+            label, value
+        )),
+    }
+}
+
+#[cfg(test)]
+mod params_tests {
+    use super::*;
+    use num::Complex;
+
+    #[test]
+    fn test_full_query() {
+        let params = GenParams {
+            fractal: Some("julia".to_string()),
+            width: Some("800".to_string()),
+            height: Some("600".to_string()),
+            color: Some("blue".to_string()),
+            bg: Some("black".to_string()),
+            transform: Some("invert".to_string()),
+            ..Default::default()
+        };
+        let generation = params.to_generation().unwrap();
+        assert_eq!("julia", generation.scheme.fractal);
+        assert_eq!((800, 600), (generation.width, generation.height));
+        assert_eq!(Color::Blue, generation.scheme.color);
+        assert_eq!(Color::Black, generation.scheme.bg_color);
+        let css_colors = GenParams {
+            color: Some("#ff8800".to_string()),
+            bg: Some("rgb(10, 20, 30)".to_string()),
+            ..Default::default()
+        };
+        let scheme = css_colors.to_generation().unwrap().scheme;
+        assert_eq!(Color::Rgb([255, 136, 0]), scheme.color);
+        assert_eq!(Color::Rgb([10, 20, 30]), scheme.bg_color);
+        assert!(!generation.scheme.fancy_background);
+        assert!(generation.scheme.do_transform);
+        assert_eq!("invert", generation.scheme.transform);
+        assert!(generation.scheme.random_transforms.is_empty());
+    }
+
+    #[test]
+    fn test_empty_query_is_randomized() {
+        let generation = GenParams::default().to_generation().unwrap();
+        assert!(fractal::lookup(&generation.scheme.fractal).is_some());
+        assert_eq!(DEFAULT_DIMENSION, generation.width);
+        assert!(!generation.scheme.do_transform);
+        assert!(!generation.scheme.random_transforms.is_empty());
+    }
+
+    #[test]
+    fn test_no_transform() {
+        let params = GenParams {
+            transform: Some("none".to_string()),
+            ..Default::default()
+        };
+        let scheme = params.to_generation().unwrap().scheme;
+        assert!(scheme_transforms(&scheme).is_empty());
+    }
+
+    #[test]
+    fn test_chosen_fractal_has_no_random_transforms() {
+        let params = GenParams {
+            fractal: Some("julia".to_string()),
+            ..Default::default()
+        };
+        let scheme = params.to_generation().unwrap().scheme;
+        assert!(scheme_transforms(&scheme).is_empty());
+    }
+
+    #[test]
+    fn test_bad_values_are_rejected() {
+        let bad_fractal = GenParams {
+            fractal: Some("sierpinski".to_string()),
+            ..Default::default()
+        };
+        let bad_width = GenParams {
+            width: Some("huge".to_string()),
+            ..Default::default()
+        };
+        let unsupported_color = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            color: Some("black".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_fractal.to_generation().is_err());
+        assert!(bad_width.to_generation().is_err());
+        assert!(unsupported_color.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_viewport_from_pairs() {
+        let pairs = vec![
+            ("fractal".to_string(), "mandelbrot".to_string()),
+            ("center".to_string(), "-0.75,0.1".to_string()),
+            ("zoom".to_string(), "30".to_string()),
+        ];
+        let generation = GenParams::from_pairs(pairs)
+            .unwrap()
+            .to_generation()
+            .unwrap();
+        let viewport = generation.scheme.viewport.unwrap();
+        assert_eq!((-0.75, 0.1), (viewport.center_re, viewport.center_im));
+        assert_eq!(0.1, viewport.span);
+    }
+
+    #[test]
+    fn test_iterations_and_bailout() {
+        let params = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            iterations: Some("20000".to_string()),
+            bailout: Some("100".to_string()),
+            smooth: Some("yes".to_string()),
+            ..Default::default()
+        };
+        let scheme = params.to_generation().unwrap().scheme;
+        assert_eq!(20000, scheme.max_iterations);
+        assert_eq!(100.0, scheme.bailout);
+        assert!(scheme.smooth);
+    }
+
+    #[test]
+    fn test_background_gradient() {
+        let params = GenParams::from_pairs(vec![
+            ("bg-gradient".to_string(), "navy,#f80@0.7,white".to_string()),
+            ("bg-shape".to_string(), "radial".to_string()),
+        ])
+        .unwrap();
+        let scheme = params.to_generation().unwrap().scheme;
+        let gradient = scheme.bg_gradient.unwrap();
+        assert!(scheme.fancy_background);
+        assert_eq!(Shape::Radial, gradient.shape);
+        assert_eq!(3, gradient.gradient.stops.len());
+
+        let solid_and_gradient = GenParams {
+            bg: Some("black".to_string()),
+            bg_angle: Some("30".to_string()),
+            ..Default::default()
+        };
+        let bad_shape = GenParams {
+            bg_shape: Some("square".to_string()),
+            ..Default::default()
+        };
+        assert!(solid_and_gradient.to_generation().is_err());
+        assert!(bad_shape.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_layer_params() {
+        let params = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            interior: Some("transparent".to_string()),
+            blend: Some("multiply".to_string()),
+            fractal_only: Some("true".to_string()),
+            ..Default::default()
+        };
+        let scheme = params.to_generation().unwrap().scheme;
+        assert!(scheme.transparent_interior);
+        assert!(scheme.fractal_only);
+        assert_eq!(Some(BlendMode::Multiply), scheme.blend);
+
+        let interior_color = GenParams {
+            interior: Some("navy".to_string()),
+            ..Default::default()
+        };
+        let palette = interior_color.to_generation().unwrap().scheme.palette;
+        assert_eq!(Some([0, 0, 128]), palette.unwrap().interior);
+        let bad_blend = GenParams {
+            blend: Some("dissolve".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_blend.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_palette_params() {
+        let params = GenParams {
+            fractal: Some("julia".to_string()),
+            palette: Some("fire".to_string()),
+            mapping: Some("cyclic".to_string()),
+            density: Some("4".to_string()),
+            ..Default::default()
+        };
+        let palette = params.to_generation().unwrap().scheme.palette.unwrap();
+        assert_eq!(Mapping::Cyclic, palette.mapping);
+        assert_eq!(4.0, palette.density);
+
+        let bad_palette = GenParams {
+            palette: Some("plaid".to_string()),
+            ..Default::default()
+        };
+        let bad_density = GenParams {
+            density: Some("0".to_string()),
+            ..Default::default()
+        };
+        assert!(bad_palette.to_generation().is_err());
+        assert!(bad_density.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_sampling_params() {
+        let grid = GenParams {
+            fractal: Some("julia".to_string()),
+            samples: Some("4".to_string()),
+            ..Default::default()
+        };
+        let adaptive = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            sampling: Some("adaptive".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            Sampling::Grid(4),
+            grid.to_generation().unwrap().scheme.sampling
+        );
+        assert_eq!(
+            Sampling::Adaptive(DEFAULT_SAMPLES),
+            adaptive.to_generation().unwrap().scheme.sampling
+        );
+        let too_many = GenParams {
+            fractal: Some("julia".to_string()),
+            samples: Some("20".to_string()),
+            ..Default::default()
+        };
+        assert!(too_many.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_precision_param() {
+        let arbitrary = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            precision: Some("arbitrary".to_string()),
+            center: Some("-1.74972192974233851863, 0".to_string()),
+            ..Default::default()
+        };
+        let scheme = arbitrary.to_generation().unwrap().scheme;
+        assert_eq!(Precision::Arbitrary, scheme.precision);
+        assert_eq!(
+            Some(("-1.74972192974233851863".to_string(), "0".to_string())),
+            scheme.exact_center
+        );
+        let bad = GenParams {
+            precision: Some("f128".to_string()),
+            ..Default::default()
+        };
+        assert!(bad.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_newton_params() {
+        let newton = GenParams {
+            fractal: Some("newton".to_string()),
+            roots: Some("1,-1,i".to_string()),
+            relaxation: Some("1.5-0.5i".to_string()),
+            tolerance: Some("1e-4".to_string()),
+            ..Default::default()
+        };
+        let scheme = newton.to_generation().unwrap().scheme;
+        assert_eq!(3, scheme.polynomial.unwrap().roots.len());
+        assert_eq!(Complex::new(1.5, -0.5), scheme.relaxation);
+        assert_eq!(1e-4, scheme.tolerance);
+        let both = GenParams {
+            fractal: Some("newton".to_string()),
+            polynomial: Some("z^2-1".to_string()),
+            roots: Some("1,-1".to_string()),
+            ..Default::default()
+        };
+        let not_newton = GenParams {
+            fractal: Some("julia".to_string()),
+            polynomial: Some("z^2-1".to_string()),
+            ..Default::default()
+        };
+        let no_relaxation = GenParams {
+            fractal: Some("newton".to_string()),
+            relaxation: Some("0+0i".to_string()),
+            ..Default::default()
+        };
+        assert!(both.to_generation().is_err());
+        assert!(not_newton.to_generation().is_err());
+        assert!(no_relaxation.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_julia_constant() {
+        let julia = GenParams {
+            fractal: Some("julia".to_string()),
+            c: Some("-0.123,0.745".to_string()),
+            ..Default::default()
+        };
+        let scheme = julia.to_generation().unwrap().scheme;
+        assert_eq!(Some(Complex::new(-0.123, 0.745)), scheme.julia_c);
+        let preset = GenParams {
+            fractal: Some("tricorn-julia".to_string()),
+            c: Some("lace".to_string()),
+            ..Default::default()
+        };
+        let scheme = preset.to_generation().unwrap().scheme;
+        assert_eq!(Some(crate::julias::LACE), scheme.julia_c);
+        // Left out, a preset is picked and kept in the scheme.
+        let random = GenParams {
+            fractal: Some("julia".to_string()),
+            ..Default::default()
+        };
+        assert!(random.to_generation().unwrap().scheme.julia_c.is_some());
+        let not_julia = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            c: Some("lace".to_string()),
+            ..Default::default()
+        };
+        assert!(not_julia.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_exponent() {
+        let multibrot = GenParams {
+            fractal: Some("multibrot".to_string()),
+            exponent: Some("2.5+0.5i".to_string()),
+            ..Default::default()
+        };
+        let scheme = multibrot.to_generation().unwrap().scheme;
+        assert_eq!(Complex::new(2.5, 0.5), scheme.exponent);
+        let julia = GenParams {
+            fractal: Some("julia".to_string()),
+            exponent: Some("3".to_string()),
+            ..Default::default()
+        };
+        let bad = GenParams {
+            fractal: Some("multi-julia".to_string()),
+            exponent: Some("two".to_string()),
+            ..Default::default()
+        };
+        assert!(julia.to_generation().is_err());
+        assert!(bad.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_ifs_params() {
+        let preset = GenParams {
+            fractal: Some("ifs".to_string()),
+            ifs: Some("levy-c".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            "levy-c",
+            preset.to_generation().unwrap().scheme.ifs.unwrap().name
+        );
+        let maps = GenParams {
+            fractal: Some("ifs".to_string()),
+            maps: Some("0.5 0 0 0.5 0 0; 0.5 0 0 0.5 0.5 0.5".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            2,
+            maps.to_generation().unwrap().scheme.ifs.unwrap().maps.len()
+        );
+        let unknown = GenParams {
+            fractal: Some("ifs".to_string()),
+            ifs: Some("koch".to_string()),
+            ..Default::default()
+        };
+        let not_ifs = GenParams {
+            fractal: Some("barnsley".to_string()),
+            ifs: Some("fern".to_string()),
+            ..Default::default()
+        };
+        assert!(unknown.to_generation().is_err());
+        assert!(not_ifs.to_generation().is_err());
+        let mut body = GenParams {
+            fractal: Some("ifs".to_string()),
+            ..Default::default()
+        };
+        body.set_body("0.5 0 0 0.5 0 0\n0.5 0 0 0.5 0.5 0.5")
+            .unwrap();
+        assert_eq!(
+            2,
+            body.to_generation().unwrap().scheme.ifs.unwrap().maps.len()
+        );
+        let mut not_ifs_body = GenParams {
+            fractal: Some("barnsley".to_string()),
+            ..Default::default()
+        };
+        assert!(not_ifs_body.set_body("0.5 0 0 0.5 0 0").is_err());
+        assert!(GenParams::default().set_body("0.5 0 0 0.5 0 0").is_err());
+    }
+
+    #[test]
+    fn test_flame_params() {
+        let preset = GenParams {
+            fractal: Some("flame".to_string()),
+            flame: Some("julia-rings".to_string()),
+            estimator: Some("0".to_string()),
+            ..Default::default()
+        };
+        let scheme = preset.to_generation().unwrap().scheme;
+        assert_eq!("julia-rings", scheme.flame.unwrap().name);
+        assert_eq!(0.0, scheme.estimator);
+        let transforms = GenParams {
+            fractal: Some("flame".to_string()),
+            transforms: Some("0.5 0 0 0.5 0 0 swirl=1; 0.5 0 0 0.5 0.5 0.5".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(
+            2,
+            transforms
+                .to_generation()
+                .unwrap()
+                .scheme
+                .flame
+                .unwrap()
+                .transforms
+                .len()
+        );
+        let both = GenParams {
+            fractal: Some("flame".to_string()),
+            flame: Some("julia-rings".to_string()),
+            transforms: Some("0.5 0 0 0.5 0 0".to_string()),
+            ..Default::default()
+        };
+        let not_flame = GenParams {
+            fractal: Some("ifs".to_string()),
+            estimator: Some("2".to_string()),
+            ..Default::default()
+        };
+        assert!(both.to_generation().is_err());
+        assert!(not_flame.to_generation().is_err());
+        let mut body = GenParams {
+            fractal: Some("flame".to_string()),
+            ..Default::default()
+        };
+        body.set_body("0.5 0 0 0.5 0 0 swirl=1").unwrap();
+        assert!(body.maps.is_none());
+        assert!(body.to_generation().unwrap().scheme.flame.is_some());
+    }
+
+    #[test]
+    fn test_rgb_iterations() {
+        let nebulabrot = GenParams {
+            fractal: Some("nebulabrot".to_string()),
+            rgb_iterations: Some("5000, 500,50".to_string()),
+            zoom: Some("2".to_string()),
+            ..Default::default()
+        };
+        let scheme = nebulabrot.to_generation().unwrap().scheme;
+        assert_eq!(Some([5000, 500, 50]), scheme.rgb_iterations);
+        assert!(scheme.viewport.is_some());
+        let two = GenParams {
+            fractal: Some("buddhabrot".to_string()),
+            rgb_iterations: Some("500,50".to_string()),
+            ..Default::default()
+        };
+        let not_buddhabrot = GenParams {
+            fractal: Some("mandelbrot".to_string()),
+            rgb_iterations: Some("500,50,5".to_string()),
+            ..Default::default()
+        };
+        let colored = GenParams {
+            fractal: Some("nebulabrot".to_string()),
+            color: Some("red".to_string()),
+            ..Default::default()
+        };
+        assert!(two.to_generation().is_err());
+        assert!(not_buddhabrot.to_generation().is_err());
+        assert!(colored.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_density_params() {
+        let barnsley = GenParams {
+            fractal: Some("barnsley".to_string()),
+            points: Some("5e6".to_string()),
+            gamma: Some("3".to_string()),
+            samples: Some("2".to_string()),
+            ..Default::default()
+        };
+        let scheme = barnsley.to_generation().unwrap().scheme;
+        assert_eq!(5_000_000, scheme.points);
+        assert_eq!(3.0, scheme.gamma);
+        assert_eq!(Sampling::Grid(2), scheme.sampling);
+        let sampling_mode = GenParams {
+            fractal: Some("ifs".to_string()),
+            sampling: Some("adaptive".to_string()),
+            ..Default::default()
+        };
+        let not_density = GenParams {
+            fractal: Some("julia".to_string()),
+            points: Some("1000".to_string()),
+            ..Default::default()
+        };
+        assert!(sampling_mode.to_generation().is_err());
+        assert!(not_density.to_generation().is_err());
+    }
+
+    #[test]
+    fn test_bad_viewports_are_rejected() {
+        let barnsley = GenParams {
+            fractal: Some("barnsley".to_string()),
+            zoom: Some("2".to_string()),
+            ..Default::default()
+        };
+        let negative_span = GenParams {
+            fractal: Some("julia".to_string()),
+            span: Some("-1".to_string()),
+            ..Default::default()
+        };
+        assert!(barnsley.to_generation().is_err());
+        assert!(negative_span.to_generation().is_err());
+        let too_many = GenParams {
+            fractal: Some("julia".to_string()),
+            iterations: Some("2000000".to_string()),
+            ..Default::default()
+        };
+        assert!(too_many.to_generation().is_err());
+        assert!(GenParams::from_pairs(vec![("size".to_string(), "1".to_string())]).is_err());
+    }
+}
@@ -0,0 +1,128 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Deep zooms of the Mandelbrot set by perturbation. Only one point, the
+//! middle of the view, is iterated in fixed point. Every other point is
+//! iterated as its small difference from that reference orbit, which
+//! `f64` holds accurately however deep the view is. Where the difference
+//! stops being small the reference no longer describes the point (a
+//! glitch), so the point is moved onto the start of the reference orbit
+//! and carries on from there.
+
+// Perturbation: https://en.wikipedia.org/wiki/Plotting_algorithms_for_the_Mandelbrot_set#Perturbation_theory_and_series_approximation
+// Glitch avoidance by rebasing: https://fractalforums.org/fractal-mathematics-and-new-theories/28/another-solution-to-perturbation-glitches/4360
+
+use crate::escape::Escape;
+use crate::precision::*;
+use num::Complex;
+
+/// The orbit of z = z^2 + c for the point in the middle of a view,
+/// computed in fixed point and kept as `f64`.
+pub struct ReferenceOrbit {
+    orbit: Vec<Complex<f64>>,
+}
+
+impl ReferenceOrbit {
+    /// Iterate the middle of a view in fixed point, until it escapes or
+    /// reaches `max_iterations`.
+    pub fn new(c: &FixedComplex, max_iterations: u32, bailout: f64) -> ReferenceOrbit {
+        let bits = c.bits;
+        let bailout_sqr = to_fixed(bailout * bailout, bits);
+        let mut z = FixedComplex::new(0.0, 0.0, bits);
+        let mut orbit = Vec::new();
+        for _ in 0..=max_iterations {
+            orbit.push(Complex::new(
+                from_fixed(&z.re, bits),
+                from_fixed(&z.im, bits),
+            ));
+            if z.norm_sqr() > bailout_sqr {
+                break;
+            }
+            z = z.square().add(c);
+        }
+        ReferenceOrbit { orbit }
+    }
+
+    /// Count the iterations of the point `dc` away from the middle of the
+    /// view, as `mandelbrot::escape_time` does, by iterating its distance
+    /// dz from the reference orbit Z: dz -> 2 Z dz + dz^2 + dc.
+    pub fn escape_time(&self, dc: Complex<f64>, max_iterations: u32, bailout: f64) -> Escape {
+        let bailout_sqr = bailout * bailout;
+        let mut dz = Complex::new(0.0, 0.0);
+        let mut n = 0;
+        for i in 0..max_iterations {
+            let z = self.orbit[n] + dz;
+            let norm_sqr = z.norm_sqr();
+            if norm_sqr > bailout_sqr {
+                return Escape::escaped(i, norm_sqr, bailout, 2.0);
+            }
+            // The point is nearer zero than the reference is, or the
+            // reference has escaped: rebase onto the start of the orbit,
+            // where Z is zero and dz is the point itself.
+            if norm_sqr < dz.norm_sqr() || n + 1 == self.orbit.len() {
+                dz = z;
+                n = 0;
+            }
+            dz = (self.orbit[n] * 2.0 + dz) * dz + dc;
+            n += 1;
+        }
+        Escape::inside(max_iterations)
+    }
+}
+
+#[cfg(test)]
+mod perturbation_tests {
+    use super::*;
+    use crate::mandelbrot::{escape_time, escape_time_fixed};
+    use crate::viewport::Viewport;
+
+    fn reference(viewport: &Viewport, bits: usize, max_iterations: u32) -> ReferenceOrbit {
+        let center = FixedComplex::new(viewport.center_re, viewport.center_im, bits);
+        ReferenceOrbit::new(&center, max_iterations, 2.0)
+    }
+
+    /// Iterations of the reference orbit before it escaped, or the limit.
+    fn iterations(reference: &ReferenceOrbit) -> usize {
+        reference.orbit.len() - 1
+    }
+
+    #[test]
+    fn test_matches_direct_iteration_on_shallow_views() {
+        let viewport = Viewport::new(-0.75, 0.1, 0.5);
+        let reference = reference(&viewport, 64, 1000);
+        for &(dx, dy) in &[(0.0, 0.0), (0.2, 0.0), (-0.1, 0.15), (0.05, -0.2)] {
+            let direct = escape_time(Complex::new(-0.75 + dx, 0.1 + dy), 1000, 2.0);
+            let perturbed = reference.escape_time(Complex::new(dx, dy), 1000, 2.0);
+            assert_eq!(direct.iterations, perturbed.iterations);
+            assert!((direct.smooth - perturbed.smooth).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn test_matches_fixed_point_on_deep_views() {
+        let viewport = Viewport::new(-0.743_643_887_037_151, 0.131_825_904_205_33, 1e-17);
+        let bits = fixed_bits(viewport.pixel_size(8, 8));
+        let reference = reference(&viewport, bits, 4000);
+        for &x in &[0.5, 3.5, 7.5] {
+            let fixed = FixedComplex::new(viewport.center_re, viewport.center_im, bits)
+                .offset_by(&viewport, x, 4.0, 8, 8);
+            let (dx, dy) = viewport.offset(x, 4.0, 8, 8);
+            let direct = escape_time_fixed(&fixed, 4000, 2.0);
+            let perturbed = reference.escape_time(Complex::new(dx, dy), 4000, 2.0);
+            assert_eq!(direct.iterations, perturbed.iterations);
+            assert!((direct.smooth - perturbed.smooth).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    fn test_rebases_when_the_reference_escapes() {
+        // The reference escapes at once, but points inside the set don't.
+        let viewport = Viewport::new(1.0, 0.0, 1.0);
+        let reference = reference(&viewport, 64, 100);
+        assert!(iterations(&reference) < 10);
+        assert!(
+            !reference
+                .escape_time(Complex::new(-1.0, 0.0), 100, 2.0)
+                .escaped
+        );
+    }
+}
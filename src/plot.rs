@@ -0,0 +1,140 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Mapping from the plane a point-plotting fractal lives in to the pixels
+//! of an image. The whole of a fractal's bounds is fitted into the image
+//! with the same scale across and up, so it keeps its shape whatever the
+//! image size, centered, with a margin around it. Points falling outside
+//! the image are clipped rather than drawn out of range.
+
+/// Share of each side of the image left empty around a fractal.
+pub const MARGIN: f64 = 0.05;
+
+/// Smallest rectangle holding an attractor, as min and max x and y.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bounds {
+    pub min_x: f64,
+    pub min_y: f64,
+    pub max_x: f64,
+    pub max_y: f64,
+}
+
+impl Bounds {
+    /// Width and height, never zero so lines and points can be scaled.
+    pub fn size(&self) -> (f64, f64) {
+        let width = self.max_x - self.min_x;
+        let height = self.max_y - self.min_y;
+        let floor = 1e-9 * width.max(height).max(1.0);
+        (width.max(floor), height.max(floor))
+    }
+}
+
+/// Where the points of some bounds land in an image of some size, with
+/// y growing up in the plane and down in the image.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PlotMapping {
+    pub width: u32,
+    pub height: u32,
+    scale: f64,
+    left: f64,
+    top: f64,
+    min_x: f64,
+    max_y: f64,
+}
+
+impl PlotMapping {
+    /// Fit bounds into a width by height image, leaving `margin` of each
+    /// side empty. The image is taken to be at least one pixel each way.
+    pub fn fit(bounds: &Bounds, width: u32, height: u32, margin: f64) -> PlotMapping {
+        let (width, height) = (width.max(1), height.max(1));
+        let (span_x, span_y) = bounds.size();
+        let (pixels_x, pixels_y) = (f64::from(width), f64::from(height));
+        let fill = (1.0 - 2.0 * margin.clamp(0.0, 0.49)).max(0.0);
+        let scale = (pixels_x / span_x).min(pixels_y / span_y) * fill;
+        PlotMapping {
+            width,
+            height,
+            scale,
+            left: (pixels_x - span_x * scale) / 2.0,
+            top: (pixels_y - span_y * scale) / 2.0,
+            min_x: bounds.min_x,
+            max_y: bounds.max_y,
+        }
+    }
+
+    /// Position of a point in the image, where pixel (x, y) covers
+    /// x..x+1, y..y+1. May be outside the image.
+    pub fn position(&self, (x, y): (f64, f64)) -> (f64, f64) {
+        (
+            self.left + (x - self.min_x) * self.scale,
+            self.top + (self.max_y - y) * self.scale,
+        )
+    }
+
+    /// Pixel a point lands on, or `None` if it is outside the image.
+    /// Points on the far edges of the image belong to its last column and
+    /// row, so nothing in the bounds is lost even without a margin.
+    pub fn pixel(&self, point: (f64, f64)) -> Option<(u32, u32)> {
+        let (x, y) = self.position(point);
+        let inside = |n: f64, size: u32| n >= 0.0 && n <= f64::from(size);
+        if inside(x, self.width) && inside(y, self.height) {
+            Some((
+                (x as u32).min(self.width - 1),
+                (y as u32).min(self.height - 1),
+            ))
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod plot_tests {
+    use super::*;
+
+    const SQUARE: Bounds = Bounds {
+        min_x: -1.0,
+        min_y: -1.0,
+        max_x: 1.0,
+        max_y: 1.0,
+    };
+
+    #[test]
+    fn test_bounds_fit_odd_and_non_square_images() {
+        for &(width, height) in &[(1, 1), (7, 7), (9, 4), (4, 9), (640, 481), (1, 300)] {
+            let mapping = PlotMapping::fit(&SQUARE, width, height, 0.0);
+            for &corner in &[
+                (-1.0, -1.0),
+                (-1.0, 1.0),
+                (1.0, -1.0),
+                (1.0, 1.0),
+                (0.0, 0.0),
+            ] {
+                let (x, y) = mapping.pixel(corner).expect("bounds are inside the image");
+                assert!(x < width && y < height);
+            }
+        }
+
+        // The square stays square, centered across the wider image.
+        let mapping = PlotMapping::fit(&SQUARE, 9, 4, 0.0);
+        assert_eq!((2.5, 0.0), mapping.position((-1.0, 1.0)));
+        assert_eq!((6.5, 4.0), mapping.position((1.0, -1.0)));
+        assert_eq!(Some((6, 3)), mapping.pixel((1.0, -1.0)));
+    }
+
+    #[test]
+    fn test_points_outside_are_clipped() {
+        let mapping = PlotMapping::fit(&SQUARE, 5, 3, MARGIN);
+        assert_eq!(None, mapping.pixel((0.0, 1.2)));
+        assert_eq!(None, mapping.pixel((-3.0, 0.0)));
+        assert_eq!(None, mapping.pixel((f64::NAN, 0.0)));
+        assert_eq!(None, mapping.pixel((f64::INFINITY, 0.0)));
+        assert_eq!(Some((2, 1)), mapping.pixel((0.0, 0.0)));
+    }
+
+    #[test]
+    fn test_margin_shrinks_the_fit() {
+        let mapping = PlotMapping::fit(&SQUARE, 100, 100, 0.1);
+        assert_eq!((10.0, 10.0), mapping.position((-1.0, 1.0)));
+        assert_eq!((90.0, 90.0), mapping.position((1.0, -1.0)));
+    }
+}
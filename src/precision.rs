@@ -0,0 +1,331 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Number types the escape time fractals iterate with. Shallow views use
+//! `f64` (or `f32` when asked), which stops telling neighbouring pixels
+//! apart once they are about 1e-14 apart. Deeper views switch to binary
+//! fixed point numbers held in big integers, with enough fractional bits
+//! to resolve a pixel however far the view is zoomed, either for every
+//! point or, by perturbation, for one reference point only.
+
+use crate::util::{unknown_name, Scheme};
+use crate::viewport::Viewport;
+use num::{BigInt, Complex, Float, ToPrimitive, Zero};
+
+/// Names of the precisions, for input and messages.
+pub const PRECISION_NAMES: [&str; 5] = ["auto", "f32", "f64", "arbitrary", "perturbation"];
+/// Smallest pixel spacing `f64` is trusted to resolve. Values of z are
+/// around 1 to 2 in size, and `f64` keeps about 16 significant digits.
+pub const F64_MIN_PIXEL_SIZE: f64 = 1e-14;
+/// Fractional bits kept beyond those needed to resolve a pixel, so
+/// rounding errors do not build up into visible ones.
+const GUARD_BITS: usize = 32;
+/// Largest power of ten `parse_fixed` works out, so huge exponents in
+/// input can not make it multiply for ever.
+const MAX_DECIMAL_EXPONENT: i64 = 1000;
+
+/// Precision asked for by a scheme.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Precision {
+    /// `f64`, or perturbation once `f64` can not resolve the pixels.
+    #[default]
+    Auto,
+    Single,
+    Double,
+    Arbitrary,
+    Perturbation,
+}
+
+/// The arithmetic an image is actually drawn with.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Arithmetic {
+    F32,
+    F64,
+    /// Fixed point with this many fractional bits.
+    Fixed(usize),
+    /// Fixed point with this many fractional bits for a reference orbit,
+    /// and `f64` for each point's difference from it. Fractals without a
+    /// perturbation renderer use plain fixed point instead.
+    Perturbation(usize),
+}
+
+/// Parse the name of a precision.
+pub fn parse_precision(name: &str) -> Result<Precision, String> {
+    match name.trim().to_lowercase().as_str() {
+        "auto" => Ok(Precision::Auto),
+        "f32" | "single" => Ok(Precision::Single),
+        "f64" | "double" => Ok(Precision::Double),
+        "arbitrary" => Ok(Precision::Arbitrary),
+        "perturbation" => Ok(Precision::Perturbation),
+        other => Err(unknown_name("precision", other, &PRECISION_NAMES)),
+    }
+}
+
+/// Pick the arithmetic for drawing a viewport onto an image of some size.
+pub fn choose_arithmetic(
+    precision: Precision,
+    viewport: &Viewport,
+    imgx: u32,
+    imgy: u32,
+) -> Arithmetic {
+    let pixel_size = viewport.pixel_size(imgx, imgy);
+    match precision {
+        Precision::Single => Arithmetic::F32,
+        Precision::Double => Arithmetic::F64,
+        Precision::Arbitrary => Arithmetic::Fixed(fixed_bits(pixel_size)),
+        Precision::Perturbation => Arithmetic::Perturbation(fixed_bits(pixel_size)),
+        Precision::Auto if pixel_size < F64_MIN_PIXEL_SIZE => {
+            Arithmetic::Perturbation(fixed_bits(pixel_size))
+        }
+        Precision::Auto => Arithmetic::F64,
+    }
+}
+
+/// Fractional bits needed to resolve pixels `pixel_size` apart.
+pub fn fixed_bits(pixel_size: f64) -> usize {
+    let needed = -pixel_size.max(1e-300).log2();
+    needed.ceil().max(0.0) as usize + GUARD_BITS
+}
+
+/// Convert an `f64` to a float type of the escape time core.
+pub fn real<T: Float>(x: f64) -> T {
+    <T as num::NumCast>::from(x).unwrap_or_else(T::nan)
+}
+
+/// The point of the complex plane at a position in an image, in a float type.
+pub fn float_point<T: Float>(
+    viewport: &Viewport,
+    px: f64,
+    py: f64,
+    imgx: u32,
+    imgy: u32,
+) -> Complex<T> {
+    let (re, im) = viewport.to_complex(px, py, imgx, imgy);
+    Complex::new(real(re), real(im))
+}
+
+/// Convert an `f64` to fixed point with `bits` fractional bits, exactly
+/// apart from the bits below the last fractional one.
+pub fn to_fixed(x: f64, bits: usize) -> BigInt {
+    if x == 0.0 || !x.is_finite() {
+        return BigInt::zero();
+    }
+    let (mantissa, exponent, sign) = x.integer_decode();
+    let mantissa = BigInt::from(mantissa);
+    let shift = i64::from(exponent) + bits as i64;
+    let value = if shift >= 0 {
+        mantissa << shift as usize
+    } else {
+        mantissa >> (-shift) as usize
+    };
+    if sign < 0 {
+        -value
+    } else {
+        value
+    }
+}
+
+/// Read a decimal number such as "-0.7436438870371587047" or "1.5e-3" to
+/// fixed point with `bits` fractional bits, keeping every digit written.
+pub fn parse_fixed(number: &str, bits: usize) -> Option<BigInt> {
+    let number = number.trim().to_lowercase();
+    let (mantissa, exponent) = match number.find('e') {
+        Some(e) => (&number[..e], number[e + 1..].parse::<i64>().ok()?),
+        None => (number.as_str(), 0),
+    };
+    let (negative, mantissa) = match mantissa.chars().next() {
+        Some('-') => (true, &mantissa[1..]),
+        Some('+') => (false, &mantissa[1..]),
+        _ => (false, mantissa),
+    };
+    let (whole, fraction) = match mantissa.find('.') {
+        Some(point) => (&mantissa[..point], &mantissa[point + 1..]),
+        None => (mantissa, ""),
+    };
+    let digits = format!("{}{}", whole, fraction);
+    if digits.is_empty() || !digits.chars().all(|c| c.is_ascii_digit()) {
+        return None;
+    }
+    // The number is digits * 10^exponent, scaled by 2^bits.
+    let exponent = exponent - fraction.len() as i64;
+    if exponent.abs() > MAX_DECIMAL_EXPONENT {
+        return None;
+    }
+    let power = (0..exponent.abs()).fold(BigInt::from(1), |power, _| power * BigInt::from(10));
+    let scaled = BigInt::parse_bytes(digits.as_bytes(), 10)? << bits;
+    let value = if exponent >= 0 {
+        scaled * power
+    } else {
+        scaled / power
+    };
+    Some(if negative { -value } else { value })
+}
+
+/// The middle of a scheme's view in fixed point, from the center the
+/// scheme was given in full where there is one, since that may have more
+/// digits than the viewport's `f64` center holds.
+pub fn fixed_center(scheme: &Scheme, viewport: &Viewport, bits: usize) -> FixedComplex {
+    if let Some((re, im)) = &scheme.exact_center {
+        if let (Some(re), Some(im)) = (parse_fixed(re, bits), parse_fixed(im, bits)) {
+            return FixedComplex { re, im, bits };
+        }
+    }
+    FixedComplex::new(viewport.center_re, viewport.center_im, bits)
+}
+
+/// Convert a fixed point number with `bits` fractional bits back to `f64`.
+pub fn from_fixed(value: &BigInt, bits: usize) -> f64 {
+    // Keep only the top bits, which fit in an i64, so neither the value
+    // nor 2^bits ever has to be converted whole.
+    let shift = value.bits().saturating_sub(62);
+    let top = (value.clone() >> shift).to_i64().unwrap_or(0);
+    top as f64 * 2.0_f64.powi(shift as i32 - bits as i32)
+}
+
+/// A complex number in binary fixed point: both parts are scaled by 2^bits.
+#[derive(Clone, Debug, PartialEq)]
+pub struct FixedComplex {
+    pub re: BigInt,
+    pub im: BigInt,
+    pub bits: usize,
+}
+
+impl FixedComplex {
+    /// A fixed point complex number from two `f64`s.
+    pub fn new(re: f64, im: f64, bits: usize) -> FixedComplex {
+        FixedComplex {
+            re: to_fixed(re, bits),
+            im: to_fixed(im, bits),
+            bits,
+        }
+    }
+
+    /// This number, taken as the middle of a viewport, moved to a position
+    /// in an image.
+    pub fn offset_by(
+        &self,
+        viewport: &Viewport,
+        px: f64,
+        py: f64,
+        imgx: u32,
+        imgy: u32,
+    ) -> FixedComplex {
+        let (dx, dy) = viewport.offset(px, py, imgx, imgy);
+        self.add(&FixedComplex::new(dx, dy, self.bits))
+    }
+
+    /// Sum of two numbers with the same number of fractional bits.
+    pub fn add(&self, other: &FixedComplex) -> FixedComplex {
+        FixedComplex {
+            re: &self.re + &other.re,
+            im: &self.im + &other.im,
+            bits: self.bits,
+        }
+    }
+
+    /// Product of two numbers with the same number of fractional bits.
+    pub fn mul(&self, other: &FixedComplex) -> FixedComplex {
+        let re = &self.re * &other.re - &self.im * &other.im;
+        let im = &self.re * &other.im + &self.im * &other.re;
+        FixedComplex {
+            re: re >> self.bits,
+            im: im >> self.bits,
+            bits: self.bits,
+        }
+    }
+
+    /// This number squared, with one multiplication fewer than `mul`.
+    pub fn square(&self) -> FixedComplex {
+        let re = &self.re * &self.re - &self.im * &self.im;
+        let im = (&self.re * &self.im) << 1;
+        FixedComplex {
+            re: re >> self.bits,
+            im: im >> self.bits,
+            bits: self.bits,
+        }
+    }
+
+    /// This number raised to a whole power of at least 1.
+    pub fn powi(&self, power: u32) -> FixedComplex {
+        let mut result = self.clone();
+        for _ in 1..power {
+            result = result.mul(self);
+        }
+        result
+    }
+
+    /// Squared magnitude, still in fixed point.
+    pub fn norm_sqr(&self) -> BigInt {
+        (&self.re * &self.re + &self.im * &self.im) >> self.bits
+    }
+}
+
+#[cfg(test)]
+mod precision_tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_round_trip() {
+        for &x in &[0.0, 1.5, -0.743_643_887_037_151, 3.0e-20, -2.0] {
+            assert_eq!(x, from_fixed(&to_fixed(x, 200), 200));
+        }
+        assert_eq!(0.25, from_fixed(&to_fixed(0.25, 8), 8));
+    }
+
+    #[test]
+    fn test_parse_fixed_keeps_every_digit() {
+        assert_eq!(Some(to_fixed(-1.5, 64)), parse_fixed("-1.5", 64));
+        assert_eq!(Some(to_fixed(0.25, 64)), parse_fixed("25e-2", 64));
+        assert_eq!(Some(to_fixed(3.0, 8)), parse_fixed("3.", 8));
+        // 1 + 2^-80 is 1 in f64, but not when parsed in full.
+        let tiny =
+            "1.00000000000000000000000082718061255302767487140869206996285356581211090087890625";
+        assert_eq!(
+            Some(to_fixed(1.0, 100) + (BigInt::from(1) << 20)),
+            parse_fixed(tiny, 100)
+        );
+        assert_eq!(None, parse_fixed("1.2.3", 64));
+        assert_eq!(None, parse_fixed("-", 64));
+    }
+
+    #[test]
+    fn test_fixed_arithmetic() {
+        let z = FixedComplex::new(1.5, -0.5, 80);
+        let square = z.square();
+        assert_eq!(z.mul(&z), square);
+        assert_eq!(2.0, from_fixed(&square.re, 80));
+        assert_eq!(-1.5, from_fixed(&square.im, 80));
+        assert_eq!(z.mul(&z).mul(&z), z.powi(3));
+        assert_eq!(2.5, from_fixed(&z.norm_sqr(), 80));
+    }
+
+    #[test]
+    fn test_fixed_keeps_offsets_f64_would_lose() {
+        let viewport = Viewport::new(-1.5, 0.0, 1e-20);
+        let bits = fixed_bits(viewport.pixel_size(100, 100));
+        let center = FixedComplex::new(viewport.center_re, viewport.center_im, bits);
+        let left = center.offset_by(&viewport, 10.0, 50.0, 100, 100);
+        let right = center.offset_by(&viewport, 11.0, 50.0, 100, 100);
+        assert_ne!(left, right);
+        let (re, _) = viewport.to_complex(10.0, 50.0, 100, 100);
+        assert_eq!(re, viewport.to_complex(11.0, 50.0, 100, 100).0);
+    }
+
+    #[test]
+    fn test_choose_arithmetic() {
+        let shallow = Viewport::new(-0.5, 0.0, 3.0);
+        let deep = Viewport::new(-0.5, 0.0, 1e-13);
+        assert_eq!(
+            Arithmetic::F64,
+            choose_arithmetic(Precision::Auto, &shallow, 1000, 1000)
+        );
+        match choose_arithmetic(Precision::Auto, &deep, 1000, 1000) {
+            Arithmetic::Perturbation(bits) => assert!(bits > 53),
+            other => panic!("expected perturbation, got {:?}", other),
+        }
+        assert_eq!(
+            Arithmetic::F32,
+            choose_arithmetic(Precision::Single, &deep, 1000, 1000)
+        );
+        assert!(parse_precision("quad").is_err());
+    }
+}
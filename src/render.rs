@@ -0,0 +1,104 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Multithreaded pixel rendering shared by the escape time fractals.
+//! The image is split into bands of rows which worker threads take
+//! turns computing, so every pixel gets exactly the value the single
+//! threaded loop would give it.
+
+use image::{Pixel, Rgba, RgbaImage};
+use std::sync::Mutex;
+use std::thread;
+
+/// Most worker threads the web server will use for a single fractal.
+pub const MAX_WEB_THREADS: usize = 4;
+/// Rows of pixels handed to a worker at a time.
+const BAND_ROWS: usize = 16;
+
+/// Number of threads to use when none is configured: one per CPU core.
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Update every pixel of an image from its coordinates and current
+/// value, using up to `threads` worker threads.
+pub fn par_for_each_pixel<F>(imgbuf: &mut RgbaImage, threads: usize, f: F)
+where
+    F: Fn(u32, u32, &mut Rgba<u8>) + Sync,
+{
+    let width = imgbuf.width() as usize;
+    par_for_each_band(imgbuf, width * 4, BAND_ROWS, threads, |first_row, data| {
+        for (i, channels) in data.chunks_mut(4).enumerate() {
+            let x = (i % width) as u32;
+            let y = (first_row + i / width) as u32;
+            f(x, y, Rgba::from_slice_mut(channels));
+        }
+    });
+}
+
+/// Update a grid of values `row_len` to a row in bands of `band_rows`
+/// rows, using up to `threads` worker threads. `f` is given the first row
+/// of a band and its values.
+pub fn par_for_each_band<T, F>(
+    data: &mut [T],
+    row_len: usize,
+    band_rows: usize,
+    threads: usize,
+    f: F,
+) where
+    T: Send,
+    F: Fn(usize, &mut [T]) + Sync,
+{
+    if row_len == 0 || data.is_empty() {
+        return;
+    }
+    let band_rows = band_rows.max(1);
+    let bands = Mutex::new(data.chunks_mut(row_len * band_rows).enumerate());
+
+    let worker = || loop {
+        let next = bands.lock().unwrap().next();
+        match next {
+            Some((band, data)) => f(band * band_rows, data),
+            None => break,
+        }
+    };
+
+    if threads <= 1 {
+        worker();
+    } else {
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(worker);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod render_tests {
+    use super::*;
+
+    fn pattern(threads: usize, imgx: u32, imgy: u32) -> RgbaImage {
+        let mut imgbuf = RgbaImage::new(imgx, imgy);
+        par_for_each_pixel(&mut imgbuf, threads, |x, y, pixel| {
+            *pixel = Rgba([x as u8, y as u8, (x * y) as u8, 255]);
+        });
+        imgbuf
+    }
+
+    #[test]
+    fn test_threads_match_single_thread() {
+        let single = pattern(1, 37, 53).into_raw();
+        assert_eq!(single, pattern(3, 37, 53).into_raw());
+        assert_eq!(single, pattern(16, 37, 53).into_raw());
+    }
+
+    #[test]
+    fn test_every_pixel_visited() {
+        let imgbuf = pattern(4, 21, 40);
+        assert_eq!(
+            Rgba([20, 39, (20 * 39) as u8, 255]),
+            *imgbuf.get_pixel(20, 39)
+        );
+        assert!(imgbuf.pixels().all(|pixel| pixel[3] == 255));
+    }
+}
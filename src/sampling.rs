@@ -0,0 +1,246 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Supersampling anti-aliasing for the escape time fractals. Instead of
+//! one point in the middle of each pixel, a pixel may be colored from
+//! a grid of points, from points jittered randomly within that grid, or
+//! adaptively, spending extra points only on pixels at the edges of
+//! the fractal. Samples are averaged after they are colored.
+
+// Supersampling: https://en.wikipedia.org/wiki/Supersampling
+
+use crate::render::par_for_each_pixel;
+use crate::util::unknown_name;
+use image::{Rgba, RgbaImage};
+
+/// Most samples per side of a pixel, so at most 8x8 samples per pixel.
+pub const MAX_SAMPLES: u32 = 8;
+/// Samples per side of a pixel when only a sampling mode is given.
+pub const DEFAULT_SAMPLES: u32 = 3;
+/// Names of the sampling modes, for input and messages.
+pub const SAMPLING_NAMES: [&str; 3] = ["grid", "jittered", "adaptive"];
+/// Largest difference in any channel between neighbouring pixels
+/// that adaptive sampling leaves alone.
+const ADAPTIVE_THRESHOLD: u8 = 8;
+
+/// How the points a pixel is colored from are chosen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sampling {
+    /// An n by n grid of evenly spaced points; 1 is a single point in the middle.
+    Grid(u32),
+    /// One random point in each cell of an n by n grid.
+    Jittered(u32),
+    /// A single point, then an n by n grid for pixels that differ from a neighbour.
+    Adaptive(u32),
+}
+
+impl Sampling {
+    /// Samples per side of a pixel.
+    pub fn per_side(self) -> u32 {
+        match self {
+            Sampling::Grid(n) | Sampling::Jittered(n) | Sampling::Adaptive(n) => n,
+        }
+    }
+}
+
+impl Default for Sampling {
+    fn default() -> Sampling {
+        Sampling::Grid(1)
+    }
+}
+
+/// Parse a sampling mode name and a number of samples per side of a pixel.
+pub fn parse_sampling(mode: &str, samples: u32) -> Result<Sampling, String> {
+    match mode.trim().to_lowercase().as_str() {
+        "grid" => Ok(Sampling::Grid(samples)),
+        "jittered" | "jitter" => Ok(Sampling::Jittered(samples)),
+        "adaptive" => Ok(Sampling::Adaptive(samples)),
+        other => Err(unknown_name("sampling", other, &SAMPLING_NAMES)),
+    }
+}
+
+/// Parse the number of samples per side of a pixel.
+pub fn parse_samples(samples: &str) -> Result<u32, String> {
+    match samples.trim().parse::<u32>() {
+        Ok(n) if (1..=MAX_SAMPLES).contains(&n) => Ok(n),
+        _ => Err(format!(
+            "Invalid samples '{}', expected a number from 1 to {}",
+            samples, MAX_SAMPLES
+        )),
+    }
+}
+
+/// Color every pixel of an image from samples of `color`, which takes a
+/// continuous position in the image (pixel (x, y) covers x..x+1, y..y+1).
+/// Uses up to `threads` worker threads, and gives the same image for any
+/// number of threads.
+pub fn supersample<F>(imgbuf: &mut RgbaImage, threads: usize, sampling: Sampling, color: F)
+where
+    F: Fn(f64, f64) -> Rgba<u8> + Sync,
+{
+    match sampling {
+        Sampling::Grid(n) => par_for_each_pixel(imgbuf, threads, |x, y, pixel| {
+            *pixel = sample_pixel(x, y, n, false, &color);
+        }),
+        Sampling::Jittered(n) => par_for_each_pixel(imgbuf, threads, |x, y, pixel| {
+            *pixel = sample_pixel(x, y, n, true, &color);
+        }),
+        Sampling::Adaptive(n) => {
+            par_for_each_pixel(imgbuf, threads, |x, y, pixel| {
+                *pixel = sample_pixel(x, y, 1, false, &color);
+            });
+            let coarse = imgbuf.clone();
+            par_for_each_pixel(imgbuf, threads, |x, y, pixel| {
+                if is_edge(&coarse, x, y) {
+                    *pixel = sample_pixel(x, y, n, false, &color);
+                }
+            });
+        }
+    }
+}
+
+/// Average the colors of an n by n grid of samples of pixel (x, y),
+/// jittering each sample within its cell if asked to.
+fn sample_pixel<F>(x: u32, y: u32, n: u32, jitter: bool, color: &F) -> Rgba<u8>
+where
+    F: Fn(f64, f64) -> Rgba<u8>,
+{
+    let n = n.max(1);
+    // Premultiplied sums, so transparent samples add no color.
+    let mut sum = [0.0_f64; 4];
+    for j in 0..n {
+        for i in 0..n {
+            let (dx, dy) = if jitter {
+                jitter_offset(x, y, i + j * n)
+            } else {
+                (0.5, 0.5)
+            };
+            let px = f64::from(x) + (f64::from(i) + dx) / f64::from(n);
+            let py = f64::from(y) + (f64::from(j) + dy) / f64::from(n);
+            let Rgba(sample) = color(px, py);
+            let alpha = f64::from(sample[3]) / 255.0;
+            sum[0] += f64::from(sample[0]) * alpha;
+            sum[1] += f64::from(sample[1]) * alpha;
+            sum[2] += f64::from(sample[2]) * alpha;
+            sum[3] += alpha;
+        }
+    }
+    if sum[3] <= 0.0 {
+        return Rgba([0, 0, 0, 0]);
+    }
+    let channel = |c: f64| (c / sum[3]).round() as u8;
+    let count = f64::from(n * n);
+    Rgba([
+        channel(sum[0]),
+        channel(sum[1]),
+        channel(sum[2]),
+        (sum[3] / count * 255.0).round() as u8,
+    ])
+}
+
+/// Random offset within a grid cell, from 0 to 1 on each axis. The offset
+/// depends only on the pixel and sample, so threads agree on it.
+fn jitter_offset(x: u32, y: u32, sample: u32) -> (f64, f64) {
+    let seed = (u64::from(x) << 40) ^ (u64::from(y) << 16) ^ u64::from(sample);
+    let bits = splitmix(seed);
+    let unit = |bits: u64| (bits & 0xff_ffff) as f64 / f64::from(0x100_0000);
+    (unit(bits), unit(bits >> 32))
+}
+
+/// SplitMix64, a small hash giving well mixed bits from a counter.
+// See: http://xorshift.di.unimi.it/splitmix64.c
+fn splitmix(seed: u64) -> u64 {
+    let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+/// Whether a pixel differs noticeably from any of its four neighbours.
+fn is_edge(imgbuf: &RgbaImage, x: u32, y: u32) -> bool {
+    let pixel = imgbuf.get_pixel(x, y);
+    let neighbours = [
+        (x.wrapping_sub(1), y),
+        (x + 1, y),
+        (x, y.wrapping_sub(1)),
+        (x, y + 1),
+    ];
+    neighbours
+        .iter()
+        .filter(|&&(nx, ny)| nx < imgbuf.width() && ny < imgbuf.height())
+        .any(|&(nx, ny)| {
+            let other = imgbuf.get_pixel(nx, ny);
+            (0..4).any(|i| {
+                let difference = i16::from(pixel[i]) - i16::from(other[i]);
+                difference.abs() > i16::from(ADAPTIVE_THRESHOLD)
+            })
+        })
+}
+
+#[cfg(test)]
+mod sampling_tests {
+    use super::*;
+
+    /// White left of x = 10.25, black right of it.
+    fn edge(px: f64, _py: f64) -> Rgba<u8> {
+        if px < 10.25 {
+            Rgba([255, 255, 255, 255])
+        } else {
+            Rgba([0, 0, 0, 255])
+        }
+    }
+
+    fn render(sampling: Sampling, threads: usize) -> RgbaImage {
+        let mut imgbuf = RgbaImage::new(20, 20);
+        supersample(&mut imgbuf, threads, sampling, edge);
+        imgbuf
+    }
+
+    #[test]
+    fn test_grid_averages_samples() {
+        assert_eq!(
+            Rgba([0, 0, 0, 255]),
+            *render(Sampling::Grid(1), 1).get_pixel(10, 5)
+        );
+        // A quarter of a 4x4 grid lands left of the edge.
+        assert_eq!(
+            Rgba([64, 64, 64, 255]),
+            *render(Sampling::Grid(4), 1).get_pixel(10, 5)
+        );
+        assert_eq!(
+            Rgba([255, 255, 255, 255]),
+            *render(Sampling::Grid(4), 1).get_pixel(9, 5)
+        );
+    }
+
+    #[test]
+    fn test_transparent_samples_add_no_color() {
+        let half_clear = |px: f64, _py: f64| {
+            if px.fract() < 0.5 {
+                Rgba([200, 100, 0, 255])
+            } else {
+                Rgba([0, 0, 0, 0])
+            }
+        };
+        let pixel = sample_pixel(0, 0, 2, false, &half_clear);
+        assert_eq!(Rgba([200, 100, 0, 128]), pixel);
+    }
+
+    #[test]
+    fn test_adaptive_only_samples_edges() {
+        let adaptive = render(Sampling::Adaptive(4), 3);
+        assert_eq!(
+            render(Sampling::Grid(4), 1).get_pixel(10, 5),
+            adaptive.get_pixel(10, 5)
+        );
+        assert_eq!(Rgba([0, 0, 0, 255]), *adaptive.get_pixel(15, 5));
+    }
+
+    #[test]
+    fn test_jittered_is_the_same_on_any_thread_count() {
+        let single = render(Sampling::Jittered(3), 1).into_raw();
+        assert_eq!(single, render(Sampling::Jittered(3), 4).into_raw());
+        assert!(parse_sampling("jittered", 3).is_ok());
+        assert!(parse_sampling("random", 3).is_err());
+        assert!(parse_samples("9").is_err());
+    }
+}
@@ -2,11 +2,29 @@
 
 //! Contains utility functions and stuctures for handling user input and managing fractal images.
 
+use crate::background::{BackgroundGradient, Shape};
+use crate::css_colors::css_color;
+use crate::density::{DEFAULT_ESTIMATOR, DEFAULT_GAMMA, DEFAULT_POINTS};
+use crate::escape::Escape;
+use crate::flame::Flame;
+use crate::fractal;
+use crate::ifs::Ifs;
+use crate::layers::BlendMode;
+use crate::newton::{Polynomial, DEFAULT_TOLERANCE};
+use crate::output::{encode_png_with_text, scheme_metadata};
+use crate::palette::Palette;
+use crate::precision::Precision;
+use crate::render::default_threads;
+use crate::sampling::Sampling;
 use crate::util::Color::*;
+use crate::viewport::Viewport;
 use image::imageops::*;
+use image::DynamicImage::ImageRgba8;
 use image::*;
+use num::Complex;
 use rand::Rng;
 use std::fs;
+use std::path::Path;
 use std::str::FromStr;
 
 /// Smooth filter 3x3 matrix values.
@@ -15,9 +33,6 @@ const SMOOTH_KERNEL: [f32; 9] = [1.0, 1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0, 1.0];
 const SHARPEN_KERNEL: [f32; 9] = [-1.0, -1.0, -1.0, -1.0, 9.0, -1.0, -1.0, -1.0, -1.0];
 /// Raosed filter 3x3 matrix values.
 const RAISED_KERNEL: [f32; 9] = [0.0, 0.0, -2.0, 0.0, 2.0, 0.0, 1.0, 0.0, 0.0];
-const COLORS: [&str; 8] = [
-    "red", "blue", "green", "orange", "yellow", "violet", "black", "white",
-];
 /// Str literals used to call random image operations.
 const TRANSFORMS: [&str; 9] = [
     "brighten",
@@ -30,9 +45,40 @@ const TRANSFORMS: [&str; 9] = [
     "sharpen filter",
     "raised filter",
 ];
+/// Str literals for every transformation `process_image` understands.
+pub const ALL_TRANSFORMS: [&str; 12] = [
+    "none",
+    "brighten",
+    "contrast",
+    "huerotate",
+    "invert",
+    "rotate90",
+    "rotate180",
+    "rotate270",
+    "blur",
+    "smooth filter",
+    "sharpen filter",
+    "raised filter",
+];
 const ROTATIONS: [i32; 3] = [90, 180, 270];
+/// Colors a fractal fading in from a black background can be drawn in.
+pub const FRACTAL_COLORS: [Color; 7] = [Red, Orange, Yellow, Green, Blue, Violet, White];
+/// Every color offered by name in the menus.
+pub const ALL_COLORS: [Color; 8] = [Red, Orange, Yellow, Green, Blue, Violet, White, Black];
+/// Colors a random transitional background is made of.
+const TRANSITION_COLORS: [Color; 3] = [Red, Blue, Green];
+/// Shapes a random transitional background is drawn in.
+const SHAPES: [Shape; 3] = [Shape::Linear, Shape::Radial, Shape::Conic];
+/// Forms a color can be written in, for error messages.
+const COLOR_FORMS: &str = "a CSS color name, #rrggbb, #rgb, rgb(r, g, b) or hsl(h, s%, l%)";
 
-/// Supported colors for fractals and backgrounds.
+/// Iteration limit of escape time fractals unless a scheme says otherwise.
+pub const DEFAULT_MAX_ITERATIONS: u32 = 255;
+/// Escape radius of escape time fractals unless a scheme says otherwise.
+pub const DEFAULT_BAILOUT: f64 = 2.0;
+
+/// Colors for fractals and backgrounds. The menu colors have names,
+/// any other color is kept as its RGB value.
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub enum Color {
     Red,
@@ -43,6 +89,19 @@ pub enum Color {
     Violet,
     Black,
     White,
+    Rgb([u8; 3]),
+}
+
+impl Color {
+    /// The color with some RGB value, named if it is one of the menu colors,
+    /// so e.g. #ff0000 and red are the same color.
+    pub fn from_rgb(rgb: [u8; 3]) -> Color {
+        ALL_COLORS
+            .iter()
+            .copied()
+            .find(|color| color_to_rgb(*color) == rgb)
+            .unwrap_or(Color::Rgb(rgb))
+    }
 }
 
 /// Container for properties of the fractal being generated.
@@ -54,9 +113,61 @@ pub struct Scheme {
     pub fancy_background: bool,
     pub bg_color: Color,
     pub bg_color_2: Color,
-    pub random: bool,
+    /// Gradient drawn by a transitional background, or a linear
+    /// gradient from `bg_color` to `bg_color_2`
+    pub bg_gradient: Option<BackgroundGradient>,
+    /// Transformations picked at random for a random scheme, applied after
+    /// `transform`
+    pub random_transforms: Vec<String>,
     pub do_transform: bool,
     pub transform: String,
+    /// Worker threads used to draw the fractal
+    pub threads: usize,
+    /// Region of the complex plane to draw, or the fractal's default
+    pub viewport: Option<Viewport>,
+    /// Center of the viewport as written, which may have more digits than
+    /// `f64` holds, for arbitrary precision
+    pub exact_center: Option<(String, String)>,
+    /// Most iterations an escape time fractal tries per point
+    pub max_iterations: u32,
+    /// Distance from the origin past which a point has escaped
+    pub bailout: f64,
+    /// Color escape time fractals by their smooth iteration count
+    pub smooth: bool,
+    /// Palette used to color the fractal instead of its single color
+    pub palette: Option<Palette>,
+    /// Leave points inside an escape time set transparent
+    pub transparent_interior: bool,
+    /// Blend mode of the fractal over the background, or the fractal's default
+    pub blend: Option<BlendMode>,
+    /// Draw only the fractal layer, on a transparent background
+    pub fractal_only: bool,
+    /// Points escape time fractals color each pixel from
+    pub sampling: Sampling,
+    /// Number type escape time fractals iterate with
+    pub precision: Precision,
+    /// Polynomial of the Newton fractal, or its default
+    pub polynomial: Option<Polynomial>,
+    /// Factor of each Newton step
+    pub relaxation: Complex<f64>,
+    /// Newton step size below which a point has converged
+    pub tolerance: f64,
+    /// Constant c of a Julia set, or the fractal's own
+    pub julia_c: Option<Complex<f64>>,
+    /// Exponent n of the multi-Julia and Multibrot sets z^n + c
+    pub exponent: Complex<f64>,
+    /// Maps of the IFS fractal, or a random preset
+    pub ifs: Option<Ifs>,
+    /// Points plotted by the chaos game fractals
+    pub points: u64,
+    /// Gamma of the log-density the chaos game fractals are drawn with
+    pub gamma: f64,
+    /// Transforms of the flame fractal, or a random preset
+    pub flame: Option<Flame>,
+    /// Radius in pixels of the density estimation filter of flames
+    pub estimator: f64,
+    /// Iteration limits of the red, green and blue channels of a Buddhabrot
+    pub rgb_iterations: Option<[u32; 3]>,
 }
 
 /// Reasonable values are set for a default fractal scheme
@@ -69,14 +180,39 @@ impl Default for Scheme {
             fancy_background: false,
             bg_color: Black,
             bg_color_2: Red,
-            random: false,
+            bg_gradient: None,
+            random_transforms: Vec::new(),
             do_transform: false,
             transform: String::new(),
+            threads: default_threads(),
+            viewport: None,
+            exact_center: None,
+            max_iterations: DEFAULT_MAX_ITERATIONS,
+            bailout: DEFAULT_BAILOUT,
+            smooth: false,
+            palette: None,
+            transparent_interior: false,
+            blend: None,
+            fractal_only: false,
+            sampling: Sampling::default(),
+            precision: Precision::default(),
+            polynomial: None,
+            relaxation: Complex::new(1.0, 0.0),
+            tolerance: DEFAULT_TOLERANCE,
+            julia_c: None,
+            exponent: Complex::new(2.0, 0.0),
+            ifs: None,
+            points: DEFAULT_POINTS,
+            gamma: DEFAULT_GAMMA,
+            flame: None,
+            estimator: DEFAULT_ESTIMATOR,
+            rgb_iterations: None,
         }
     }
 }
 
 /// Convert a Color enum into RGB data values.
+/// The named colors have their CSS values.
 pub fn color_to_rgb(color: Color) -> [u8; 3] {
     match color {
         Red => [255, 0, 0],
@@ -87,54 +223,186 @@ pub fn color_to_rgb(color: Color) -> [u8; 3] {
         Violet => [238, 130, 238],
         Black => [0, 0, 0],
         White => [255, 255, 255],
+        Color::Rgb(rgb) => rgb,
     }
 }
 
-/// Convert a str to a Color.
-/// Defaults to Black for invalid input colors.
-pub fn str_to_color(color: &str) -> Color {
+/// Whether a color shows up when faded in from black, as the escape
+/// time fractals are. Every color except black itself does.
+pub fn visible_on_black(color: Color) -> bool {
+    color_to_rgb(color) != [0, 0, 0]
+}
+
+/// The palette a scheme colors its fractal with: its own palette,
+/// or a fade from black to the scheme's color.
+pub fn scheme_palette(scheme: &Scheme) -> Palette {
+    match &scheme.palette {
+        Some(palette) => palette.clone(),
+        None => Palette::single_color(scheme.color),
+    }
+}
+
+/// The fractal layer pixel of an escape time point colored `color`:
+/// opaque, unless the point is inside the set and the scheme
+/// leaves the interior transparent.
+pub fn escape_pixel(escape: &Escape, color: [u8; 3], scheme: &Scheme) -> Rgba<u8> {
+    if scheme.transparent_interior && !escape.escaped {
+        Rgba([0, 0, 0, 0])
+    } else {
+        Rgba([color[0], color[1], color[2], 255])
+    }
+}
+
+/// Convert a Color into the text used for input: the lowercase name
+/// of a menu color, or #rrggbb for any other color.
+pub fn color_to_str(color: Color) -> String {
     match color {
-        "red" => Red,
-        "orange" => Orange,
-        "yellow" => Yellow,
-        "blue" => Blue,
-        "green" => Green,
-        "violet" => Violet,
-        "black" => Black,
-        "white" => White,
-        &_ => Black,
+        Red => "red".to_string(),
+        Orange => "orange".to_string(),
+        Yellow => "yellow".to_string(),
+        Blue => "blue".to_string(),
+        Green => "green".to_string(),
+        Violet => "violet".to_string(),
+        Black => "black".to_string(),
+        White => "white".to_string(),
+        Color::Rgb([r, g, b]) => format!("#{:02x}{:02x}{:02x}", r, g, b),
+    }
+}
+
+/// Error message for a name that is not one of `names`.
+pub fn unknown_name(kind: &str, value: &str, names: &[&str]) -> String {
+    format!(
+        "Unknown {} '{}', expected one of: {}",
+        kind,
+        value,
+        names.join(", ")
+    )
+}
+
+/// Convert a str to a Color. Accepts the CSS color names, hex colors
+/// such as #ff8800 or #f80, and the rgb(...) and hsl(...) functions.
+/// Anything else is reported as an error.
+pub fn parse_color(color: &str) -> Result<Color, String> {
+    let color = color.trim().to_lowercase();
+    let invalid = || format!("Invalid color '{}', expected {}", color, COLOR_FORMS);
+
+    let rgb = if let Some(hex) = color.strip_prefix('#') {
+        parse_hex(hex).ok_or_else(invalid)?
+    } else if let Some(args) = function_args(&color, "rgb") {
+        match args[..] {
+            [r, g, b] => [
+                parse_channel(r).ok_or_else(invalid)?,
+                parse_channel(g).ok_or_else(invalid)?,
+                parse_channel(b).ok_or_else(invalid)?,
+            ],
+            _ => return Err(invalid()),
+        }
+    } else if let Some(args) = function_args(&color, "hsl") {
+        match args[..] {
+            [h, s, l] => hsl_to_rgb(
+                parse_hue(h).ok_or_else(invalid)?,
+                parse_percent(s).ok_or_else(invalid)?,
+                parse_percent(l).ok_or_else(invalid)?,
+            ),
+            _ => return Err(invalid()),
+        }
+    } else {
+        css_color(&color)
+            .ok_or_else(|| format!("Unknown color '{}', expected {}", color, COLOR_FORMS))?
+    };
+    Ok(Color::from_rgb(rgb))
+}
+
+/// Parse the digits of a #rrggbb or #rgb hex color.
+fn parse_hex(hex: &str) -> Option<[u8; 3]> {
+    if !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    let digit = |i: usize| u8::from_str_radix(&hex[i..=i], 16).ok();
+    let pair = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+    match hex.len() {
+        3 => Some([digit(0)? * 17, digit(1)? * 17, digit(2)? * 17]),
+        6 => Some([pair(0)?, pair(2)?, pair(4)?]),
+        _ => None,
+    }
+}
+
+/// The arguments of a color function such as "rgb(1, 2, 3)", separated by
+/// commas or spaces, if `color` is a call of the function `name`.
+fn function_args<'a>(color: &'a str, name: &str) -> Option<Vec<&'a str>> {
+    let args = color
+        .strip_prefix(name)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    Some(
+        args.split(|c: char| c == ',' || c.is_whitespace())
+            .filter(|arg| !arg.is_empty())
+            .collect(),
+    )
+}
+
+/// Parse an rgb() channel, either 0 to 255 or a percentage.
+fn parse_channel(channel: &str) -> Option<u8> {
+    if channel.ends_with('%') {
+        return parse_percent(channel).map(|p| (p * 255.0).round() as u8);
+    }
+    match channel.parse::<f64>() {
+        Ok(n) if (0.0..=255.0).contains(&n) => Some(n.round() as u8),
+        _ => None,
+    }
+}
+
+/// Parse a percentage from 0% to 100% as a fraction from 0 to 1.
+fn parse_percent(percent: &str) -> Option<f64> {
+    match percent.strip_suffix('%')?.parse::<f64>() {
+        Ok(p) if (0.0..=100.0).contains(&p) => Some(p / 100.0),
+        _ => None,
+    }
+}
+
+/// Parse an hsl() hue in degrees, with or without a "deg" unit.
+fn parse_hue(hue: &str) -> Option<f64> {
+    match hue.trim_end_matches("deg").parse::<f64>() {
+        Ok(h) if h.is_finite() => Some(h.rem_euclid(360.0)),
+        _ => None,
+    }
+}
+
+/// Convert a hue in degrees, saturation and lightness from 0 to 1 into RGB.
+// Conversion: https://www.w3.org/TR/css-color-4/#hsl-to-rgb
+fn hsl_to_rgb(hue: f64, saturation: f64, lightness: f64) -> [u8; 3] {
+    let a = saturation * lightness.min(1.0 - lightness);
+    let channel = |n: f64| {
+        let k = (n + hue / 30.0) % 12.0;
+        let value = lightness - a * (k - 3.0).min(9.0 - k).clamp(-1.0, 1.0);
+        (value * 255.0).round() as u8
+    };
+    [channel(0.0), channel(8.0), channel(4.0)]
+}
+
+/// Check a transformation name against the ones `process_image` supports.
+pub fn parse_transform(transform: &str) -> Result<String, String> {
+    let transform = transform.trim().to_lowercase();
+    if ALL_TRANSFORMS.contains(&transform.as_str()) {
+        Ok(transform)
+    } else {
+        Err(unknown_name("transform", &transform, &ALL_TRANSFORMS))
     }
 }
 
 /// Iterate over the pixels of the image and apply a cool
-/// background color. The coloring will either transition from
-/// one color to another or just a solid background.
+/// background color. The coloring will either be a gradient
+/// between any number of colors or just a solid background.
 pub fn apply_background(imgbuf: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, scheme: &Scheme) {
     let color: [u8; 3] = color_to_rgb(scheme.bg_color);
-    let alpha: u8 = if scheme.fractal == "barnsley" { 75 } else { 30 };
+    let gradient = scheme_gradient(scheme);
+    let (imgx, imgy) = imgbuf.dimensions();
 
     for (x, y, pixel) in imgbuf.enumerate_pixels_mut() {
-        let xc: u8 = (0.3 * x as f32) as u8;
-        let yc: u8 = (0.3 * y as f32) as u8;
         if scheme.fancy_background {
-            match scheme.bg_color {
-                Red => match scheme.bg_color_2 {
-                    Blue => *pixel = Rgba([xc, 0, yc, alpha]),
-                    Green => *pixel = Rgba([xc, yc, 0, alpha]),
-                    _ => println!("Unsupported bg_color_2"),
-                },
-                Green => match scheme.bg_color_2 {
-                    Blue => *pixel = Rgba([0, xc, yc, alpha]),
-                    Red => *pixel = Rgba([xc, yc, 0, alpha]),
-                    _ => println!("Unsupported bg_color_2"),
-                },
-                Blue => match scheme.bg_color_2 {
-                    Red => *pixel = Rgba([xc, 0, yc, alpha]),
-                    Green => *pixel = Rgba([0, xc, yc, alpha]),
-                    _ => println!("Unsupported bg_color_2"),
-                },
-                _ => println!("Unsupported bg_color"),
-            }
+            let color = gradient.color_at(x, y, imgx, imgy);
+            *pixel = Rgba([color[0], color[1], color[2], 255]);
         } else {
             //solid bg
             *pixel = Rgba([color[0], color[1], color[2], 255]);
@@ -142,6 +410,15 @@ pub fn apply_background(imgbuf: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, scheme: &Sc
     }
 }
 
+/// The gradient a transitional background is drawn with: the scheme's own
+/// gradient, or one from its first background color to its second.
+pub fn scheme_gradient(scheme: &Scheme) -> BackgroundGradient {
+    match &scheme.bg_gradient {
+        Some(gradient) => gradient.clone(),
+        None => BackgroundGradient::two_color(scheme.bg_color, scheme.bg_color_2),
+    }
+}
+
 /// Invoke an image processing function
 /// to be used for fun and randomization functions
 /// as well as the custom menu.
@@ -150,87 +427,115 @@ pub fn apply_background(imgbuf: &mut ImageBuffer<Rgba<u8>, Vec<u8>>, scheme: &Sc
 // Range for brighten -50 to 80
 // Range for contrast -20.0 to 200.0
 // Range for huerotate 5 to 355
-pub fn process_image(filename: &str, transformation: &str) {
-    let mut image = image::open(filename).unwrap();
+pub fn process_image(mut image: DynamicImage, transformation: &str) -> DynamicImage {
     let rotate_index = rand::thread_rng().gen_range(0, 3);
 
     match transformation {
-        "blur" => blur(&image, 3.0_f32).save(filename).unwrap(),
-        "brighten" => brighten(&image, 70).save(filename).unwrap(),
-        "contrast" => contrast(&image, 100.0_f32).save(filename).unwrap(),
-        "huerotate" => huerotate(&image, ROTATIONS[rotate_index])
-            .save(filename)
-            .unwrap(),
+        "none" => image,
+        "blur" => ImageRgba8(blur(&image, 3.0_f32)),
+        "brighten" => ImageRgba8(brighten(&image, 70)),
+        "contrast" => ImageRgba8(contrast(&image, 100.0_f32)),
+        "huerotate" => ImageRgba8(huerotate(&image, ROTATIONS[rotate_index])),
         "invert" => {
             invert(&mut image);
-            image.save(filename).unwrap()
+            image
         }
-        "rotate90" => rotate90(&image).save(filename).unwrap(),
-        "rotate180" => rotate180(&image).save(filename).unwrap(),
-        "rotate270" => rotate270(&image).save(filename).unwrap(),
-        "smooth filter" => filter3x3(&image, &SMOOTH_KERNEL).save(filename).unwrap(),
-        "sharpen filter" => filter3x3(&image, &SHARPEN_KERNEL).save(filename).unwrap(),
-        "raised filter" => filter3x3(&image, &RAISED_KERNEL).save(filename).unwrap(),
-        &_ => blur(&image, 0.9_f32).save("dfault_transform.png").unwrap(),
-    };
+        "rotate90" => ImageRgba8(rotate90(&image)),
+        "rotate180" => ImageRgba8(rotate180(&image)),
+        "rotate270" => ImageRgba8(rotate270(&image)),
+        "smooth filter" => ImageRgba8(filter3x3(&image, &SMOOTH_KERNEL)),
+        "sharpen filter" => ImageRgba8(filter3x3(&image, &SHARPEN_KERNEL)),
+        "raised filter" => ImageRgba8(filter3x3(&image, &RAISED_KERNEL)),
+        &_ => ImageRgba8(blur(&image, 0.9_f32)),
+    }
+}
+
+/// Write a finished fractal to a file, for use from the command line.
+/// The image format is picked from the file extension. PNG files also
+/// keep the scheme's metadata as text.
+pub fn save_image(image: &DynamicImage, filename: &str, scheme: &Scheme) -> Result<(), String> {
+    let is_png = Path::new(filename)
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+    if is_png {
+        let png = encode_png_with_text(image, &scheme_metadata(scheme))?;
+        return fs::write(filename, png)
+            .map_err(|e| format!("Image write to {} failed: {}", filename, e));
+    }
+    image
+        .save(filename)
+        .map_err(|e| format!("Image write to {} failed: {}", filename, e))
 }
 
 /// Generate a random fractal scheme.
 /// This includes color, type of background, and background color(s).
-/// Barnsley is able to support more colors for its fractal.
+/// The fractal color is picked from the colors the fractal supports.
 pub fn randomize(scheme: &mut Scheme) {
-    scheme.random = true;
-    if rand::thread_rng().gen_range(0, 1) == 0 {
-        scheme.fancy_background = true;
-    } else {
-        scheme.fancy_background = false;
-    }
+    scheme.fancy_background = rand::thread_rng().gen::<bool>();
 
-    let fractal_color: usize = if scheme.fractal == "barnsley" {
-        rand::thread_rng().gen_range(0, 8)
-    } else {
-        rand::thread_rng().gen_range(0, 3)
-    };
-    scheme.color = str_to_color(COLORS[fractal_color]);
+    let fractal = fractal::lookup(&scheme.fractal);
+    let colors = fractal.map_or(&FRACTAL_COLORS[..], |f| f.colors());
+    // Fractals that color themselves offer no colors to pick from.
+    if !colors.is_empty() {
+        scheme.color = colors[rand::thread_rng().gen_range(0, colors.len())];
+    }
 
     if scheme.fancy_background {
         let background_1 = rand::thread_rng().gen_range(0, 3);
-        scheme.bg_color = str_to_color(COLORS[background_1]);
+        scheme.bg_color = TRANSITION_COLORS[background_1];
         let mut different = true;
         while different {
             let background_2 = rand::thread_rng().gen_range(0, 3);
-            scheme.bg_color_2 = str_to_color(COLORS[background_2]);
+            scheme.bg_color_2 = TRANSITION_COLORS[background_2];
             if scheme.bg_color_2 != scheme.bg_color {
                 different = false;
             }
         }
+        let mut gradient = BackgroundGradient::two_color(scheme.bg_color, scheme.bg_color_2);
+        gradient.shape = SHAPES[rand::thread_rng().gen_range(0, 3)];
+        gradient.angle = f64::from(rand::thread_rng().gen_range(0, 8) * 45);
+        scheme.bg_gradient = Some(gradient);
     } else {
         let bg_num = rand::thread_rng().gen_range(0, 8);
-        scheme.bg_color = str_to_color(COLORS[bg_num]);
+        scheme.bg_color = ALL_COLORS[bg_num];
+    }
+
+    if let Some(fractal) = fractal {
+        fractal.randomize(scheme);
     }
 }
 
-/// Apply a random number of random transformations
-/// to some image file, always a fractal for this program.
-pub fn random_transforms(scheme: &Scheme, filename: &str) {
+/// Pick a random number of random transformations to apply
+/// to some image, always a fractal for this program.
+pub fn pick_random_transforms() -> Vec<String> {
     let num_transforms = rand::thread_rng().gen_range(1, 7);
-    let mut record: Vec<String> = Vec::new();
-    for _ in 0..num_transforms {
-        let transform_index = rand::thread_rng().gen_range(0, 8);
-        let transform = TRANSFORMS[transform_index];
-        process_image(filename, transform);
-        record.push(transform.to_string());
-    }
+    (0..num_transforms)
+        .map(|_| TRANSFORMS[rand::thread_rng().gen_range(0, 8)].to_string())
+        .collect()
+}
 
-    log_random(&scheme, filename, record);
+/// The transformations a scheme has applied to its fractal, in order:
+/// its chosen one followed by any random ones.
+pub fn scheme_transforms(scheme: &Scheme) -> Vec<&str> {
+    let chosen = Some(scheme.transform.as_str()).filter(|_| scheme.do_transform);
+    chosen
+        .into_iter()
+        .chain(
+            scheme
+                .random_transforms
+                .iter()
+                .map(|transform| transform.as_str()),
+        )
+        .collect()
 }
 
 /// Helps keep track of what happened when a fractal
-/// was randomized by writing the Scheme and transformations to a txt file.
+/// was randomized by writing the Scheme and transformations to a txt file
+/// in /tmp, named after the image.
 /// In case a randomization happens to look cool
 /// and one would like to apply the same characteristics to another fractal.
-pub fn log_random(scheme: &Scheme, filename: &str, record: Vec<String>) {
-    let transforms = record.join(", \n");
+pub fn log_random(scheme: &Scheme, filename: &str) -> Result<(), String> {
+    let transforms = scheme_transforms(scheme).join(", \n");
 
     let data: String = format!(
         "Fractal log\n
@@ -239,8 +544,12 @@ pub fn log_random(scheme: &Scheme, filename: &str, record: Vec<String>) {
         scheme, transforms
     );
 
-    let write_path = "/tmp/".to_owned() + filename + ".log";
-    fs::write(write_path, data).expect("Log write failed...");
+    let name = Path::new(filename)
+        .file_name()
+        .and_then(|name| name.to_str())
+        .unwrap_or(filename);
+    let write_path = "/tmp/".to_owned() + name + ".log";
+    fs::write(&write_path, data).map_err(|e| format!("Log write to {} failed: {}", write_path, e))
 }
 
 // From B&O chapter 2, p28 - modified by Bart Massey
@@ -257,6 +566,27 @@ pub fn parse_pair<T: FromStr>(s: &str, sep: char) -> Option<(T, T)> {
     }
 }
 
+/// Split a list at a separator, ignoring separators inside parentheses so
+/// colors such as "rgb(1, 2, 3)" stay whole. Items are trimmed.
+pub fn split_list(list: &str, sep: char) -> Vec<&str> {
+    let mut items = Vec::new();
+    let mut depth = 0;
+    let mut start = 0;
+    for (i, c) in list.char_indices() {
+        match c {
+            '(' => depth += 1,
+            ')' => depth -= 1,
+            c if c == sep && depth == 0 => {
+                items.push(list[start..i].trim());
+                start = i + c.len_utf8();
+            }
+            _ => (),
+        }
+    }
+    items.push(list[start..].trim());
+    items
+}
+
 #[cfg(test)]
 mod util_tests {
     use super::*;
@@ -272,17 +602,79 @@ mod util_tests {
     }
 
     #[test]
-    fn test_default_color() {
-        assert_eq!(Color::Black, str_to_color("badInput"));
+    fn test_entered_color() {
+        assert_eq!(Ok(Color::Violet), parse_color("violet"));
     }
 
     #[test]
-    fn test_entered_color() {
-        assert_eq!(Color::Violet, str_to_color("violet"));
+    fn test_parse_color_rejects_bad_input() {
+        assert!(parse_color("badInput").is_err());
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#ggg").is_err());
+        assert!(parse_color("rgb(256, 0, 0)").is_err());
+        assert!(parse_color("rgb(1, 2)").is_err());
+        assert!(parse_color("hsl(0, 50, 50%)").is_err());
+    }
+
+    #[test]
+    fn test_parse_color_forms() {
+        let orange = Ok(Color::Rgb([255, 136, 0]));
+        assert_eq!(orange, parse_color("#ff8800"));
+        assert_eq!(orange, parse_color("#F80"));
+        assert_eq!(orange, parse_color("rgb(255, 136, 0)"));
+        assert_eq!(orange, parse_color("rgb(100% 53.3% 0%)"));
+        assert_eq!(Ok(Color::Rgb([102, 51, 153])), parse_color("rebeccapurple"));
+        assert_eq!(
+            Ok(Color::Rgb([0, 255, 255])),
+            parse_color("hsl(180deg, 100%, 50%)")
+        );
+        assert_eq!(
+            Ok(Color::Rgb([191, 64, 64])),
+            parse_color("hsl(0, 50%, 50%)")
+        );
+    }
+
+    #[test]
+    fn test_named_colors_are_canonical() {
+        assert_eq!(Ok(Color::Red), parse_color("#ff0000"));
+        assert_eq!(Ok(Color::Green), parse_color("rgb(0, 128, 0)"));
+        assert_eq!("#0a0b0c", color_to_str(Color::Rgb([10, 11, 12])));
+        assert_eq!(Ok(Color::Rgb([10, 11, 12])), parse_color("#0a0b0c"));
+    }
+
+    #[test]
+    fn test_parse_color_ignores_case() {
+        assert_eq!(Ok(Color::Blue), parse_color("Blue"));
+    }
+
+    #[test]
+    fn test_split_list() {
+        assert_eq!(
+            vec!["black", "rgb(1, 2, 3)@0.5", "white"],
+            split_list("black, rgb(1, 2, 3)@0.5,white", ',')
+        );
+    }
+
+    #[test]
+    fn test_parse_transform() {
+        assert_eq!(Ok("invert".to_string()), parse_transform("invert"));
+        assert!(parse_transform("explode").is_err());
     }
 
     #[test]
     fn test_rgb_data() {
         assert_eq!([238u8, 130u8, 238u8], color_to_rgb(Color::Violet));
     }
+
+    #[test]
+    fn test_gradient_background_is_opaque() {
+        let scheme = Scheme {
+            fractal: "barnsley".to_string(),
+            fancy_background: true,
+            ..Default::default()
+        };
+        let mut imgbuf = ImageBuffer::new(4, 4);
+        apply_background(&mut imgbuf, &scheme);
+        assert!(imgbuf.pixels().all(|pixel| pixel[3] == 255));
+    }
 }
@@ -0,0 +1,353 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! Variants of the Mandelbrot set that take absolute values or the
+//! conjugate of z before squaring it: the Burning Ship, Tricorn
+//! (Mandelbar), Celtic, Perpendicular Mandelbrot and Buffalo fractals,
+//! each with a matching Julia set that keeps c fixed and starts z at
+//! the point being drawn.
+
+// Burning Ship: https://en.wikipedia.org/wiki/Burning_Ship_fractal
+// Tricorn: https://en.wikipedia.org/wiki/Tricorn_(mathematics)
+// The other variants: http://www.paulbourke.net/fractals/burnship/
+
+use crate::escape::Escape;
+use crate::fractal::*;
+use crate::julias::JULIA_PARAMS;
+use crate::precision::*;
+use crate::sampling::supersample;
+use crate::util::*;
+use crate::viewport::Viewport;
+use image::RgbaImage;
+use num::{Complex, Float, Signed};
+
+/// Which variant of z = z^2 + c is iterated.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Variant {
+    /// z = (|Re z| + i |Im z|)^2 + c
+    BurningShip,
+    /// z = conj(z)^2 + c
+    Tricorn,
+    /// z = |Re z^2| + i Im z^2 + c
+    Celtic,
+    /// z = (|Re z| - i Im z)^2 + c
+    Perpendicular,
+    /// z = |Re z^2| + i |Im z^2| + c
+    Buffalo,
+}
+
+impl Variant {
+    /// One step of the variant's formula.
+    pub fn step<T: Float>(self, z: Complex<T>, c: Complex<T>) -> Complex<T> {
+        let (x, y) = (z.re, z.im);
+        let real = x * x - y * y;
+        let cross = (x + x) * y;
+        let (re, im) = match self {
+            Variant::BurningShip => (real, cross.abs()),
+            Variant::Tricorn => (real, -cross),
+            Variant::Celtic => (real.abs(), cross),
+            Variant::Perpendicular => (real, -(x + x).abs() * y),
+            Variant::Buffalo => (real.abs(), cross.abs()),
+        };
+        Complex::new(re + c.re, im + c.im)
+    }
+
+    /// One step of the variant's formula in fixed point.
+    pub fn step_fixed(self, z: &FixedComplex, c: &FixedComplex) -> FixedComplex {
+        let bits = z.bits;
+        let real = (&z.re * &z.re - &z.im * &z.im) >> bits;
+        // 2xy, shifted one bit less to double it.
+        let cross = (&z.re * &z.im) >> (bits - 1);
+        let (re, im) = match self {
+            Variant::BurningShip => (real, cross.abs()),
+            Variant::Tricorn => (real, -cross),
+            Variant::Celtic => (real.abs(), cross),
+            // -2|x|y is 2xy with the sign of x flipped.
+            Variant::Perpendicular if z.re.is_negative() => (real, cross),
+            Variant::Perpendicular => (real, -cross),
+            Variant::Buffalo => (real.abs(), cross.abs()),
+        };
+        FixedComplex {
+            re: re + &c.re,
+            im: im + &c.im,
+            bits,
+        }
+    }
+
+    /// Region showing the whole set, or the whole Julia set.
+    pub fn viewport(self, julia: bool) -> Viewport {
+        match (self, julia) {
+            (_, true) => Viewport::new(0.0, 0.0, 3.5),
+            (Variant::BurningShip, false) => Viewport::new(-0.4, -0.5, 3.2),
+            (Variant::Tricorn, false) => Viewport::new(-0.3, 0.0, 3.6),
+            (Variant::Celtic, false) => Viewport::new(-0.5, 0.0, 3.4),
+            (Variant::Perpendicular, false) => Viewport::new(-0.6, 0.0, 3.4),
+            (Variant::Buffalo, false) => Viewport::new(-0.5, -0.3, 3.6),
+        }
+    }
+
+    /// Constant c the variant's Julia set is drawn with.
+    pub fn julia_constant(self) -> (f64, f64) {
+        match self {
+            Variant::BurningShip => (-0.734, -0.92),
+            Variant::Tricorn => (-0.49, -0.066),
+            Variant::Celtic => (-1.337, -0.199),
+            Variant::Perpendicular => (0.476, -0.99),
+            Variant::Buffalo => (-0.489, 0.342),
+        }
+    }
+}
+
+/// A variant of the Mandelbrot set, or its Julia set, drawn by `variant_fractal`.
+pub struct EscapeVariant {
+    pub name: &'static str,
+    pub variant: Variant,
+    pub julia: bool,
+}
+
+/// The abs and conjugate variants and their Julia sets, for the registry.
+pub static VARIANTS: [EscapeVariant; 10] = [
+    EscapeVariant {
+        name: "burning-ship",
+        variant: Variant::BurningShip,
+        julia: false,
+    },
+    EscapeVariant {
+        name: "burning-ship-julia",
+        variant: Variant::BurningShip,
+        julia: true,
+    },
+    EscapeVariant {
+        name: "tricorn",
+        variant: Variant::Tricorn,
+        julia: false,
+    },
+    EscapeVariant {
+        name: "tricorn-julia",
+        variant: Variant::Tricorn,
+        julia: true,
+    },
+    EscapeVariant {
+        name: "celtic",
+        variant: Variant::Celtic,
+        julia: false,
+    },
+    EscapeVariant {
+        name: "celtic-julia",
+        variant: Variant::Celtic,
+        julia: true,
+    },
+    EscapeVariant {
+        name: "perpendicular",
+        variant: Variant::Perpendicular,
+        julia: false,
+    },
+    EscapeVariant {
+        name: "perpendicular-julia",
+        variant: Variant::Perpendicular,
+        julia: true,
+    },
+    EscapeVariant {
+        name: "buffalo",
+        variant: Variant::Buffalo,
+        julia: false,
+    },
+    EscapeVariant {
+        name: "buffalo-julia",
+        variant: Variant::Buffalo,
+        julia: true,
+    },
+];
+
+impl Fractal for EscapeVariant {
+    fn name(&self) -> &'static str {
+        self.name
+    }
+
+    fn params(&self) -> Vec<Param> {
+        let mut params = escape_time_params();
+        if self.julia {
+            params.extend_from_slice(&JULIA_PARAMS);
+        }
+        params
+    }
+
+    fn default_viewport(&self) -> Option<Viewport> {
+        Some(self.variant.viewport(self.julia))
+    }
+
+    fn render(&self, scheme: &Scheme, imgx: u32, imgy: u32) -> RgbaImage {
+        variant_fractal(self.variant, self.julia, imgx, imgy, scheme)
+    }
+}
+
+/// Draw a variant of the Mandelbrot set, or its Julia set, the way
+/// `mandelbrot_fractal` draws the Mandelbrot set. Views too deep for
+/// `f64` iterate every point in fixed point, since the absolute values
+/// keep perturbation from applying.
+pub fn variant_fractal(
+    variant: Variant,
+    julia: bool,
+    imgx: u32,
+    imgy: u32,
+    scheme: &Scheme,
+) -> RgbaImage {
+    let viewport = scheme.viewport.unwrap_or_else(|| variant.viewport(julia));
+    let palette = scheme_palette(scheme);
+    let arithmetic = choose_arithmetic(scheme.precision, &viewport, imgx, imgy);
+    let max = scheme.max_iterations;
+    let center = match arithmetic {
+        Arithmetic::Fixed(bits) | Arithmetic::Perturbation(bits) => {
+            Some(fixed_center(scheme, &viewport, bits))
+        }
+        _ => None,
+    };
+    let (julia_re, julia_im) = scheme
+        .julia_c
+        .map_or(variant.julia_constant(), |c| (c.re, c.im));
+
+    let mut imgbuf = RgbaImage::new(imgx, imgy);
+
+    supersample(&mut imgbuf, scheme.threads, scheme.sampling, |px, py| {
+        let escape = match (arithmetic, &center) {
+            (_, Some(center)) => {
+                let point = center.offset_by(&viewport, px, py, imgx, imgy);
+                let (z, c) = if julia {
+                    let c = FixedComplex::new(julia_re, julia_im, point.bits);
+                    (point, c)
+                } else {
+                    (FixedComplex::new(0.0, 0.0, point.bits), point)
+                };
+                variant_escape_fixed(variant, z, &c, max, scheme.bailout)
+            }
+            (Arithmetic::F32, None) => {
+                let point = float_point::<f32>(&viewport, px, py, imgx, imgy);
+                let julia_c = Complex::new(real(julia_re), real(julia_im));
+                let (z, c) = if julia {
+                    (point, julia_c)
+                } else {
+                    (Complex::new(0.0, 0.0), point)
+                };
+                variant_escape(variant, z, c, max, scheme.bailout as f32)
+            }
+            (_, None) => {
+                let point = float_point::<f64>(&viewport, px, py, imgx, imgy);
+                let (z, c) = if julia {
+                    (point, Complex::new(julia_re, julia_im))
+                } else {
+                    (Complex::new(0.0, 0.0), point)
+                };
+                variant_escape(variant, z, c, max, scheme.bailout)
+            }
+        };
+        let color = palette.escape_color(&escape, max, scheme.smooth);
+        escape_pixel(&escape, color, scheme)
+    });
+
+    imgbuf
+}
+
+/// Count the iterations of a variant's formula, starting from `z`,
+/// before |z| grows past the bailout radius.
+pub fn variant_escape<T: Float>(
+    variant: Variant,
+    mut z: Complex<T>,
+    c: Complex<T>,
+    max_iterations: u32,
+    bailout: T,
+) -> Escape {
+    let bailout_sqr = bailout * bailout;
+    for i in 0..max_iterations {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr {
+            return Escape::escaped(
+                i,
+                norm_sqr.to_f64().unwrap_or(f64::INFINITY),
+                bailout.to_f64().unwrap_or(2.0),
+                2.0,
+            );
+        }
+        z = variant.step(z, c);
+    }
+    Escape::inside(max_iterations)
+}
+
+/// `variant_escape` in fixed point, for views too deep for `f64`.
+pub fn variant_escape_fixed(
+    variant: Variant,
+    mut z: FixedComplex,
+    c: &FixedComplex,
+    max_iterations: u32,
+    bailout: f64,
+) -> Escape {
+    let bailout_sqr = to_fixed(bailout * bailout, z.bits);
+    for i in 0..max_iterations {
+        let norm_sqr = z.norm_sqr();
+        if norm_sqr > bailout_sqr {
+            return Escape::escaped(i, from_fixed(&norm_sqr, z.bits), bailout, 2.0);
+        }
+        z = variant.step_fixed(&z, c);
+    }
+    Escape::inside(max_iterations)
+}
+
+#[cfg(test)]
+mod variants_tests {
+    use super::*;
+
+    const ALL: [Variant; 5] = [
+        Variant::BurningShip,
+        Variant::Tricorn,
+        Variant::Celtic,
+        Variant::Perpendicular,
+        Variant::Buffalo,
+    ];
+
+    #[test]
+    fn test_steps() {
+        let z = Complex::new(-1.0, 2.0);
+        let c = Complex::new(0.5, 0.25);
+        // z^2 is -3 - 4i.
+        let expected = [
+            (-2.5, 4.25),
+            (-2.5, 4.25),
+            (3.5, -3.75),
+            (-2.5, -3.75),
+            (3.5, 4.25),
+        ];
+        for (&variant, &(re, im)) in ALL.iter().zip(expected.iter()) {
+            assert_eq!(Complex::new(re, im), variant.step(z, c), "{:?}", variant);
+        }
+    }
+
+    #[test]
+    fn test_fixed_point_agrees_with_f64() {
+        for &variant in &ALL {
+            for &(re, im) in &[(-1.7, -0.03), (0.2, 0.4), (-0.5, 0.5)] {
+                let double = variant_escape(
+                    variant,
+                    Complex::new(0.0, 0.0),
+                    Complex::new(re, im),
+                    300,
+                    2.0,
+                );
+                let fixed = variant_escape_fixed(
+                    variant,
+                    FixedComplex::new(0.0, 0.0, 96),
+                    &FixedComplex::new(re, im, 96),
+                    300,
+                    2.0,
+                );
+                assert_eq!(double.iterations, fixed.iterations, "{:?}", variant);
+            }
+        }
+    }
+
+    #[test]
+    fn test_variants_are_registered() {
+        for variant in &VARIANTS {
+            let fractal = lookup(variant.name).unwrap();
+            assert_eq!(variant.name, fractal.name());
+            assert!(fractal.default_viewport().is_some());
+        }
+    }
+}
@@ -0,0 +1,148 @@
+// Copyright © 2019 Liam Rotchford, Simon Barton
+
+//! The region of the complex plane an escape time fractal is drawn from.
+//! A viewport is a center point, a span and a rotation. The span covers
+//! the shorter side of the image, so any aspect ratio shows at least the
+//! same region without stretching it.
+
+/// A rotated, square-pixel window onto the complex plane.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Viewport {
+    /// Real part of the point in the middle of the image.
+    pub center_re: f64,
+    /// Imaginary part of the point in the middle of the image.
+    pub center_im: f64,
+    /// Distance across the shorter side of the image.
+    pub span: f64,
+    /// Counterclockwise rotation in degrees.
+    pub rotation: f64,
+}
+
+impl Viewport {
+    /// An unrotated viewport around some center.
+    pub const fn new(center_re: f64, center_im: f64, span: f64) -> Viewport {
+        Viewport {
+            center_re,
+            center_im,
+            span,
+            rotation: 0.0,
+        }
+    }
+
+    /// The same viewport magnified `zoom` times around its center.
+    pub fn zoomed(self, zoom: f64) -> Viewport {
+        Viewport {
+            span: self.span / zoom,
+            ..self
+        }
+    }
+
+    /// Distance in the complex plane between neighbouring pixels.
+    pub fn pixel_size(&self, imgx: u32, imgy: u32) -> f64 {
+        self.span / f64::from(imgx.min(imgy).max(1))
+    }
+
+    /// Map a position in the image to the complex plane. Positions are
+    /// continuous, so pixel (x, y) covers x..x+1 and y..y+1, and the
+    /// imaginary axis points up the image.
+    pub fn to_complex(self, px: f64, py: f64, imgx: u32, imgy: u32) -> (f64, f64) {
+        let (dx, dy) = self.offset(px, py, imgx, imgy);
+        (self.center_re + dx, self.center_im + dy)
+    }
+
+    /// Offset from the center to a position in the image, the part of
+    /// `to_complex` that stays small however deep the viewport is.
+    pub fn offset(&self, px: f64, py: f64, imgx: u32, imgy: u32) -> (f64, f64) {
+        let scale = self.pixel_size(imgx, imgy);
+        let dx = (px - f64::from(imgx) / 2.0) * scale;
+        let dy = (f64::from(imgy) / 2.0 - py) * scale;
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        (dx * cos - dy * sin, dx * sin + dy * cos)
+    }
+
+    /// Map points of the complex plane to their positions in the image,
+    /// the inverse of `to_complex`, with the rotation worked out once.
+    pub fn image_mapping(&self, imgx: u32, imgy: u32) -> impl Fn(f64, f64) -> (f64, f64) {
+        let (sin, cos) = self.rotation.to_radians().sin_cos();
+        let scale = self.pixel_size(imgx, imgy);
+        let (center_re, center_im) = (self.center_re, self.center_im);
+        let (half_x, half_y) = (f64::from(imgx) / 2.0, f64::from(imgy) / 2.0);
+        move |re, im| {
+            let (re, im) = (re - center_re, im - center_im);
+            let (dx, dy) = (re * cos + im * sin, im * cos - re * sin);
+            (half_x + dx / scale, half_y - dy / scale)
+        }
+    }
+}
+
+/// Parse a complex point written as "re,im", e.g. "-0.743,0.1318".
+pub fn parse_point(point: &str) -> Result<(f64, f64), String> {
+    let fields: Vec<&str> = point.split(',').collect();
+    if fields.len() == 2 {
+        if let (Ok(re), Ok(im)) = (
+            fields[0].trim().parse::<f64>(),
+            fields[1].trim().parse::<f64>(),
+        ) {
+            if re.is_finite() && im.is_finite() {
+                return Ok((re, im));
+            }
+        }
+    }
+    Err(format!("Invalid point '{}', expected <re>,<im>", point))
+}
+
+#[cfg(test)]
+mod viewport_tests {
+    use super::*;
+
+    fn close(a: (f64, f64), b: (f64, f64)) -> bool {
+        (a.0 - b.0).abs() < 1e-12 && (a.1 - b.1).abs() < 1e-12
+    }
+
+    #[test]
+    fn test_center_of_image_is_center() {
+        let viewport = Viewport::new(-0.75, 0.1, 2.0);
+        assert!(close(
+            (-0.75, 0.1),
+            viewport.to_complex(400.0, 300.0, 800, 600)
+        ));
+    }
+
+    #[test]
+    fn test_span_covers_shorter_side() {
+        let viewport = Viewport::new(0.0, 0.0, 2.0);
+        assert!(close(
+            (-1.0, 1.0),
+            viewport.to_complex(300.0, 0.0, 800, 200)
+        ));
+        assert!(close(
+            (-4.0, -1.0),
+            viewport.to_complex(0.0, 200.0, 800, 200)
+        ));
+    }
+
+    #[test]
+    fn test_rotation_and_zoom() {
+        let viewport = Viewport {
+            rotation: 90.0,
+            ..Viewport::new(1.0, 0.0, 4.0)
+        }
+        .zoomed(2.0);
+        // The right edge of the image now points up the imaginary axis.
+        assert!(close(
+            (1.0, 1.0),
+            viewport.to_complex(100.0, 50.0, 100, 100)
+        ));
+        let to_image = viewport.image_mapping(100, 100);
+        assert!(close((100.0, 50.0), to_image(1.0, 1.0)));
+        let (re, im) = viewport.to_complex(13.0, 71.5, 100, 100);
+        assert!(close((13.0, 71.5), to_image(re, im)));
+    }
+
+    #[test]
+    fn test_parse_point() {
+        assert_eq!(Ok((-0.743, 0.1318)), parse_point("-0.743,0.1318"));
+        assert!(parse_point("0.5").is_err());
+        assert!(parse_point("a,b").is_err());
+    }
+}